@@ -0,0 +1,165 @@
+//! Benchmarks for the parts of the engine that don't need a GPU. Every input
+//! is built from fixed seeds, so runs on the same machine can be compared
+//! against a saved baseline:
+//!
+//!     cargo bench -- --save-baseline before
+//!     cargo bench -- --baseline before
+
+use cgmath::{Point3, Vector3};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
+
+use constructor::raycast::raycast;
+use constructor::render::{
+    block::{Block, Palette, DIRT, GRASS, STONE},
+    chunk::{Chunk, ChunkManager, ChunkPosition, CHUNK_SIZE},
+    lod::{mesh_lod, MAX_LOD},
+    mesh::{mesh_chunk, Neighbors},
+};
+use constructor::save::{decode_chunk, encode_chunk};
+use constructor::terrain::{Generator, HEIGHT_CHUNKS};
+
+const SEED: u64 = 42;
+// The chunk column's surface is in this chunk, with solid ground below it
+const SURFACE: i32 = 1;
+
+fn chunk(blocks: impl Fn(usize, usize, usize) -> Option<usize>) -> Chunk {
+    let mut chunk = Chunk::new(0, Vector3::new(0, 0, 0));
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_block(Vector3::new(x, y, z), blocks(x, y, z).map(Block::new));
+            }
+        }
+    }
+    chunk
+}
+
+// Half the blocks filled with a mix of types, the same every run
+fn random_chunk() -> Chunk {
+    let mut state = SEED as u32;
+    let mut blocks = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE);
+    for _ in 0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+        // xorshift32
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        blocks.push(match state % 6 {
+            0 => Some(STONE),
+            1 => Some(DIRT),
+            2 => Some(GRASS),
+            _ => None,
+        });
+    }
+    chunk(|x, y, z| blocks[(x * CHUNK_SIZE + y) * CHUNK_SIZE + z])
+}
+
+fn meshing(c: &mut Criterion) {
+    let chunks = [
+        ("empty", chunk(|_, _, _| None)),
+        ("full", chunk(|_, _, _| Some(STONE))),
+        (
+            "checkerboard",
+            chunk(|x, y, z| ((x + y + z) % 2 == 0).then_some(STONE)),
+        ),
+        ("random", random_chunk()),
+    ];
+    let palette = Palette::default();
+    let mut group = c.benchmark_group("mesh_chunk");
+    for (name, chunk) in &chunks {
+        group.bench_function(*name, |b| {
+            b.iter(|| mesh_chunk(black_box(chunk), &Neighbors::default(), &palette))
+        });
+    }
+    group.finish();
+}
+
+fn lod_meshing(c: &mut Criterion) {
+    let chunk = Generator::new(SEED).generate(Vector3::new(0, SURFACE, 0));
+    let palette = Palette::default();
+    let mut group = c.benchmark_group("mesh_lod");
+    for level in 1..=MAX_LOD {
+        group.bench_function(format!("level_{}", level), |b| {
+            b.iter(|| mesh_lod(black_box(&chunk), level, &palette))
+        });
+    }
+    group.finish();
+}
+
+fn generation(c: &mut Criterion) {
+    let generator = Generator::new(SEED);
+    let mut group = c.benchmark_group("generate");
+    let chunks = [
+        ("ground", SURFACE - 1),
+        ("surface", SURFACE),
+        ("air", HEIGHT_CHUNKS - 1),
+    ];
+    for &(name, y) in &chunks {
+        // Boxed, as criterion reads what's returned through a volatile load,
+        // which takes forever to compile for a whole chunk
+        group.bench_function(name, |b| {
+            b.iter(|| Box::new(generator.generate(black_box(Vector3::new(3, y, -2)))))
+        });
+    }
+    group.finish();
+}
+
+fn storage(c: &mut Criterion) {
+    let position = Vector3::new(0, SURFACE, 0);
+    let chunks = [
+        ("terrain", Generator::new(SEED).generate(position)),
+        ("random", random_chunk()),
+    ];
+    let palette = Palette::default();
+    let mut group = c.benchmark_group("chunk_storage");
+    for (name, chunk) in &chunks {
+        let data = encode_chunk(chunk);
+        group.bench_function(format!("encode_{}", name), |b| {
+            b.iter(|| encode_chunk(black_box(chunk)))
+        });
+        group.bench_function(format!("decode_{}", name), |b| {
+            b.iter(|| Box::new(decode_chunk(position, black_box(&data), &palette).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+fn raycasting(c: &mut Criterion) {
+    let generator = Generator::new(SEED);
+    let mut loaded: HashMap<ChunkPosition, Chunk> = HashMap::new();
+    for x in -2..=2 {
+        for y in 0..HEIGHT_CHUNKS {
+            for z in -2..=2 {
+                let position = Vector3::new(x, y, z);
+                loaded.insert(position, generator.generate(position));
+            }
+        }
+    }
+    let chunks = ChunkManager::new(loaded);
+
+    let origin = Point3::new(0.5, 40.5, 0.5);
+    let mut group = c.benchmark_group("raycast");
+    // Looking down at the ground nearby, across it at a distance, and up at
+    // the sky, which steps the whole way without hitting anything
+    let rays = [
+        ("down", Vector3::new(0.2, -1.0, 0.1)),
+        ("far", Vector3::new(1.0, -0.5, 0.7)),
+        ("miss", Vector3::new(0.3, 1.0, 0.2)),
+    ];
+    for (name, direction) in &rays {
+        group.bench_function(*name, |b| {
+            b.iter(|| raycast(&chunks, black_box(origin), black_box(*direction), 64.0))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    meshing,
+    lod_meshing,
+    generation,
+    storage,
+    raycasting
+);
+criterion_main!(benches);
@@ -0,0 +1,27 @@
+use std::{env, fs, path::PathBuf};
+
+// Compiles every shader in shaders/ to SPIR-V so the game doesn't need
+// shaderc at runtime
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let mut compiler = shaderc::Compiler::new().expect("Failed to initialize shaderc");
+
+    println!("cargo:rerun-if-changed=shaders");
+    for entry in fs::read_dir("shaders").expect("Failed to read shaders/") {
+        let path = entry.unwrap().path();
+        let kind = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            _ => continue,
+        };
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(&path).unwrap();
+        let spirv = compiler
+            .compile_into_spirv(&source, kind, name, "main", None)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        fs::write(out_dir.join(format!("{}.spv", name)), spirv.as_binary_u8()).unwrap();
+    }
+}
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=1) in vec4 v_color;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_quad;
+layout(set=0, binding=1) uniform sampler s_quad;
+
+void main() {
+    f_color = texture(sampler2D(t_quad, s_quad), v_tex_coords) * v_color;
+}
@@ -0,0 +1,75 @@
+#version 450
+
+layout(location=0) in vec3 v_direction;
+
+layout(location=0) out vec4 f_color;
+
+layout(set=1, binding=0)
+uniform SkyUniforms {
+    vec4 u_sun_direction;
+    // x: 1.0 when a cubemap is bound, y: time of day
+    vec4 u_params;
+};
+layout(set=1, binding=1) uniform textureCube t_sky;
+layout(set=1, binding=2) uniform sampler s_sky;
+
+const vec3 DAY_ZENITH = vec3(0.24, 0.47, 0.88);
+const vec3 DAY_HORIZON = vec3(0.70, 0.82, 0.95);
+const vec3 NIGHT_ZENITH = vec3(0.01, 0.01, 0.04);
+const vec3 NIGHT_HORIZON = vec3(0.05, 0.06, 0.12);
+const vec3 DUSK = vec3(0.95, 0.45, 0.20);
+
+float hash(vec3 p) {
+    p = fract(p * 0.3183099 + 0.1);
+    p *= 17.0;
+    return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+vec3 procedural(vec3 dir) {
+    vec3 sun = normalize(u_sun_direction.xyz);
+    vec3 moon = -sun;
+
+    float day = smoothstep(-0.2, 0.2, sun.y);
+    float height = clamp(dir.y, 0.0, 1.0);
+    float gradient = pow(height, 0.5);
+
+    vec3 zenith = mix(NIGHT_ZENITH, DAY_ZENITH, day);
+    vec3 horizon = mix(NIGHT_HORIZON, DAY_HORIZON, day);
+    vec3 color = mix(horizon, zenith, gradient);
+
+    // Warm band along the horizon while the sun is rising or setting
+    float dusk = (1.0 - abs(sun.y) * 4.0) * (1.0 - gradient);
+    float facing = max(dot(normalize(vec3(dir.x, 0.0, dir.z)), normalize(vec3(sun.x, 0.0, sun.z))), 0.0);
+    color = mix(color, DUSK, clamp(dusk, 0.0, 1.0) * (0.3 + 0.7 * facing) * 0.6);
+
+    // Stars only show up at night and above the horizon
+    vec3 cell = floor(dir * 300.0);
+    float star = step(0.998, hash(cell)) * (1.0 - day) * step(0.0, dir.y);
+    color += vec3(star);
+
+    float sun_amount = dot(dir, sun);
+    color += vec3(1.0, 0.9, 0.7) * smoothstep(0.9990, 0.9995, sun_amount);
+    color += vec3(1.0, 0.8, 0.5) * pow(max(sun_amount, 0.0), 64.0) * 0.3;
+
+    float moon_amount = dot(dir, moon);
+    color += vec3(0.8, 0.8, 0.9) * smoothstep(0.9993, 0.9996, moon_amount);
+
+    // Darken below the horizon
+    if (dir.y < 0.0) {
+        color *= 1.0 + dir.y * 0.7;
+    }
+
+    return color;
+}
+
+void main() {
+    vec3 dir = normalize(v_direction);
+
+    vec3 color;
+    if (u_params.x > 0.5) {
+        color = texture(samplerCube(t_sky, s_sky), dir).rgb;
+    } else {
+        color = procedural(dir);
+    }
+    f_color = vec4(color, 1.0);
+}
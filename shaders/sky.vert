@@ -0,0 +1,38 @@
+#version 450
+
+layout(location=0) out vec3 v_direction;
+
+layout(set=0, binding=0)
+uniform Uniforms {
+    vec3 u_view_position;
+    mat4 u_view_proj;
+};
+
+const vec3 CORNERS[8] = vec3[8](
+    vec3(-1.0, -1.0, -1.0),
+    vec3( 1.0, -1.0, -1.0),
+    vec3( 1.0,  1.0, -1.0),
+    vec3(-1.0,  1.0, -1.0),
+    vec3(-1.0, -1.0,  1.0),
+    vec3( 1.0, -1.0,  1.0),
+    vec3( 1.0,  1.0,  1.0),
+    vec3(-1.0,  1.0,  1.0)
+);
+
+const int INDICES[36] = int[36](
+    0, 1, 2, 2, 3, 0,
+    5, 4, 7, 7, 6, 5,
+    4, 0, 3, 3, 7, 4,
+    1, 5, 6, 6, 2, 1,
+    3, 2, 6, 6, 7, 3,
+    4, 5, 1, 1, 0, 4
+);
+
+void main() {
+    vec3 direction = CORNERS[INDICES[gl_VertexIndex]];
+    v_direction = direction;
+
+    // Centered on the camera and pushed to the far plane
+    vec4 position = u_view_proj * vec4(u_view_position + direction, 1.0);
+    gl_Position = position.xyww;
+}
@@ -0,0 +1,276 @@
+use cgmath::{Point3, Vector3};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use crate::edit::Region;
+use crate::player::GameMode;
+use crate::settings::{Settings, WindowMode, MAX_RENDER_DISTANCE};
+
+/// Options given on the command line. Anything set here takes precedence over
+/// settings.toml for this run, without being written back to it.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "constructors", about = "A voxel building game")]
+pub struct Cli {
+    /// Directory the world is loaded from and saved to, created on first save
+    #[structopt(long, parse(from_os_str), default_value = "world")]
+    pub world: PathBuf,
+
+    /// Terrain seed, only used when creating a new world
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Starting position instead of the world's spawn point, as x,y,z
+    #[structopt(long, parse(try_from_str = parse_position))]
+    pub spawn: Option<Point3<f32>>,
+
+    /// Switch the player to creative or survival
+    #[structopt(long, value_name = "MODE")]
+    pub game_mode: Option<GameMode>,
+
+    /// Render distance in chunks
+    #[structopt(long, parse(try_from_str = parse_render_distance))]
+    pub render_distance: Option<u16>,
+
+    /// Start in borderless fullscreen
+    #[structopt(long, conflicts_with = "windowed")]
+    pub fullscreen: bool,
+
+    /// Start in a window
+    #[structopt(long)]
+    pub windowed: bool,
+
+    /// Window or image width in pixels
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub width: Option<u32>,
+
+    /// Window or image height in pixels
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub height: Option<u32>,
+
+    /// Settings file to use
+    #[structopt(long, parse(from_os_str), default_value = "settings.toml")]
+    pub config: PathBuf,
+
+    /// Render a single frame without opening a window and save it as a PNG
+    #[structopt(long, conflicts_with = "benchmark")]
+    pub headless: bool,
+
+    /// Where --headless writes its image [default: headless.png]
+    #[structopt(long, parse(from_os_str), requires = "headless")]
+    pub output: Option<PathBuf>,
+
+    /// Golden image that the --headless image has to match, failing if it
+    /// differs by more than small rendering differences
+    #[structopt(long, parse(from_os_str), requires = "headless")]
+    pub golden: Option<PathBuf>,
+
+    /// Render this many frames without a window and report the frame times
+    #[structopt(long, value_name = "FRAMES")]
+    pub benchmark: Option<u32>,
+
+    /// Write the blocks in --region to an .obj or .glb file, without opening a
+    /// window or using the GPU
+    #[structopt(
+        long,
+        parse(from_os_str),
+        requires = "region",
+        conflicts_with_all = &["headless", "benchmark"]
+    )]
+    pub export: Option<PathBuf>,
+
+    /// Blocks that --export writes, between two corners as x,y,z:x,y,z
+    #[structopt(
+        long,
+        parse(try_from_str = parse_region),
+        requires = "export",
+        allow_hyphen_values = true
+    )]
+    pub region: Option<Region>,
+}
+
+pub enum Mode {
+    Play,
+    // Where the image is written and the golden image to check it against
+    Headless(PathBuf, Option<PathBuf>),
+    Benchmark(u32),
+    Export(PathBuf, Region),
+}
+
+impl Cli {
+    pub fn mode(&self) -> Mode {
+        if let (Some(path), Some(region)) = (&self.export, self.region) {
+            Mode::Export(path.clone(), region)
+        } else if self.headless {
+            Mode::Headless(
+                self.output
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from("headless.png")),
+                self.golden.clone(),
+            )
+        } else if let Some(frames) = self.benchmark {
+            Mode::Benchmark(frames)
+        } else {
+            Mode::Play
+        }
+    }
+
+    /// Overrides `settings` with the ones given on the command line
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(render_distance) = self.render_distance {
+            settings.render_distance = render_distance;
+        }
+        if self.fullscreen {
+            settings.window_mode = WindowMode::Borderless;
+        } else if self.windowed {
+            settings.window_mode = WindowMode::Windowed;
+        }
+        if let Some(width) = self.width {
+            settings.window_width = width;
+        }
+        if let Some(height) = self.height {
+            settings.window_height = height;
+        }
+    }
+
+    /// The settings to write back to the settings file. Each setting that was
+    /// overridden on the command line and hasn't changed since keeps its value
+    /// from `file`, what the file had before this run.
+    pub fn saved_settings(&self, settings: &Settings, file: &Settings) -> Settings {
+        let mut overridden = file.clone();
+        self.apply(&mut overridden);
+
+        let mut saved = settings.clone();
+        if saved.render_distance == overridden.render_distance {
+            saved.render_distance = file.render_distance;
+        }
+        if saved.window_mode == overridden.window_mode {
+            saved.window_mode = file.window_mode;
+        }
+        if saved.window_width == overridden.window_width {
+            saved.window_width = file.window_width;
+        }
+        if saved.window_height == overridden.window_height {
+            saved.window_height = file.window_height;
+        }
+        saved
+    }
+}
+
+fn parse_position(text: &str) -> Result<Point3<f32>, String> {
+    let coords = text
+        .split(',')
+        .map(|coord| coord.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{} in {:?}", e, text))?;
+
+    match coords[..] {
+        [x, y, z] if coords.iter().all(|coord| coord.is_finite()) => Ok(Point3::new(x, y, z)),
+        [_, _, _] => Err(format!("coordinates must be finite but got {:?}", text)),
+        _ => Err(format!("expected x,y,z but got {:?}", text)),
+    }
+}
+
+fn parse_region(text: &str) -> Result<Region, String> {
+    let corners = text
+        .split(':')
+        .map(parse_corner)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match corners[..] {
+        [a, b] => Ok(Region::new(a, b)),
+        _ => Err(format!("expected x,y,z:x,y,z but got {:?}", text)),
+    }
+}
+
+fn parse_corner(text: &str) -> Result<Vector3<i32>, String> {
+    let coords = text
+        .split(',')
+        .map(|coord| coord.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{} in {:?}", e, text))?;
+
+    match coords[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => Err(format!("expected x,y,z but got {:?}", text)),
+    }
+}
+
+fn parse_render_distance(text: &str) -> Result<u16, String> {
+    match text.parse::<u16>() {
+        Ok(0) => Err(String::from("must be at least 1")),
+        Ok(distance) if distance > MAX_RENDER_DISTANCE => {
+            Err(format!("must be at most {}", MAX_RENDER_DISTANCE))
+        }
+        Ok(distance) => Ok(distance),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_size(text: &str) -> Result<u32, String> {
+    match text.parse::<u32>() {
+        Ok(0) => Err(String::from("must be at least 1")),
+        Ok(size) => Ok(size),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(
+            parse_position("1.5, -2,30").unwrap(),
+            Point3::new(1.5, -2.0, 30.0)
+        );
+        for bad in &["", "1,2", "1,2,3,4", "1,x,3", "1,,3", "NaN,2,3", "1,inf,3"] {
+            assert!(parse_position(bad).is_err(), "parsed {:?}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_render_distances() {
+        assert_eq!(parse_render_distance("1"), Ok(1));
+        assert_eq!(parse_render_distance("12"), Ok(12));
+        for bad in &["0", "33", "-3", "", "two", "70000"] {
+            assert!(parse_render_distance(bad).is_err(), "parsed {:?}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1"), Ok(1));
+        assert_eq!(parse_size("1920"), Ok(1920));
+        for bad in &["0", "-1", "", "wide"] {
+            assert!(parse_size(bad).is_err(), "parsed {:?}", bad);
+        }
+    }
+
+    #[test]
+    fn overrides_are_not_saved() {
+        let cli = Cli::from_iter(&["constructors", "--render-distance", "9", "--fullscreen"]);
+        let file = Settings::default();
+        let mut settings = file.clone();
+        cli.apply(&mut settings);
+        assert_eq!(settings.render_distance, 9);
+        assert_eq!(settings.window_mode, WindowMode::Borderless);
+
+        // Only the changed setting is saved, and the overrides are dropped
+        settings.fov = 90.0;
+        let saved = cli.saved_settings(&settings, &file);
+        assert_eq!(
+            saved,
+            Settings {
+                fov: 90.0,
+                ..file.clone()
+            }
+        );
+
+        // Unless they've been changed as well
+        settings.render_distance = 4;
+        let saved = cli.saved_settings(&settings, &file);
+        assert_eq!(saved.render_distance, 4);
+        assert_eq!(saved.window_mode, file.window_mode);
+    }
+}
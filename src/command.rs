@@ -0,0 +1,1252 @@
+use cgmath::{Point3, Vector3};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::edit::{
+    self, BlockEdit, Clipboard, EditSummary, Editor, HistoryError, Region, MAX_EDIT_VOLUME,
+};
+use crate::error::Result;
+use crate::export::{self, Format};
+use crate::inventory::{ItemStack, MAX_STACK};
+use crate::player::Player;
+use crate::raycast::RayHit;
+use crate::render::{
+    block::{Block, BlockType, Palette},
+    chunk::ChunkManager,
+    mesh::MeshData,
+};
+use crate::schematic;
+use crate::settings::{Settings, MAX_RENDER_DISTANCE};
+use crate::vox;
+use crate::world::World;
+
+// Where /schem and /vox read and write files
+const SCHEMATIC_DIR: &str = "schematics";
+const SCHEMATIC_EXTENSION: &str = "schem";
+const VOX_EXTENSION: &str = "vox";
+// Where /export writes meshes
+const EXPORT_DIR: &str = "exports";
+
+/// What commands are allowed to touch. Commands only go through this, so the
+/// world can change underneath without breaking them.
+pub struct CommandContext<'a> {
+    world: &'a mut World,
+    settings: &'a mut Settings,
+}
+
+impl<'a> CommandContext<'a> {
+    pub fn new(world: &'a mut World, settings: &'a mut Settings) -> Self {
+        Self { world, settings }
+    }
+
+    pub fn player(&mut self) -> &mut Player {
+        self.world.player_mut()
+    }
+
+    pub fn chunks(&mut self) -> &mut ChunkManager {
+        self.world.chunks_mut()
+    }
+
+    pub fn palette(&mut self) -> &mut Palette {
+        self.world.palette_mut()
+    }
+
+    /// The blocks in `region` meshed for export
+    pub fn mesh_region(&self, region: Region) -> MeshData {
+        export::mesh_region(self.world.chunks(), self.world.palette(), region)
+    }
+
+    pub fn editor(&mut self) -> &mut Editor {
+        self.world.editor_mut()
+    }
+
+    pub fn target(&self) -> Option<RayHit> {
+        self.world.target()
+    }
+
+    pub fn apply(&mut self, edit: &BlockEdit) -> EditSummary {
+        self.world.apply_edit(edit)
+    }
+
+    pub fn undo(&mut self) -> std::result::Result<EditSummary, HistoryError> {
+        self.world.undo()
+    }
+
+    pub fn redo(&mut self) -> std::result::Result<EditSummary, HistoryError> {
+        self.world.redo()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.world.seed()
+    }
+
+    pub fn time(&self) -> f32 {
+        self.world.time()
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.world.set_time(time);
+    }
+
+    pub fn set_render_distance(&mut self, render_distance: u16) {
+        self.settings.render_distance = render_distance;
+        self.world.apply_settings(self.settings);
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        self.world.save()
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Unknown(String),
+    // Wrong number of arguments, with the command's usage
+    Usage(&'static str),
+    InvalidArgument { argument: String, expected: String },
+    Failed(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => {
+                write!(f, "Unknown command /{}, try /help", name)
+            }
+            CommandError::Usage(usage) => write!(f, "Usage: {}", usage),
+            CommandError::InvalidArgument { argument, expected } => {
+                write!(f, "Invalid argument {:?}, expected {}", argument, expected)
+            }
+            CommandError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+pub type CommandResult = std::result::Result<String, CommandError>;
+
+// Name, usage and description of /help, which `Commands` runs itself
+const HELP: (&str, &str, &str) = (
+    "help",
+    "/help [command]",
+    "List commands or show how to use one",
+);
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    run: fn(&mut CommandContext, &[&str]) -> CommandResult,
+    // Suggestions for the argument at an index, given the arguments before it
+    // and the world's block types
+    complete: fn(&Palette, usize, &[&str]) -> Vec<String>,
+}
+
+/// Every command the console knows, looked up by name
+pub struct Commands {
+    commands: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self {
+            commands: vec![
+                Command {
+                    name: "tp",
+                    usage: "/tp <x> <y> <z>",
+                    description: "Teleport, with ~ for coordinates relative to the player",
+                    run: teleport,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "time",
+                    usage: "/time set <day|noon|night|midnight|0.0-1.0> or /time query",
+                    description: "Change or show the time of day",
+                    run: time,
+                    complete: complete_time,
+                },
+                Command {
+                    name: "give",
+                    usage: "/give <block> [count]",
+                    description: "Add blocks to the inventory",
+                    run: give,
+                    complete: complete_give,
+                },
+                Command {
+                    name: "fill",
+                    usage: "/fill <x1> <y1> <z1> <x2> <y2> <z2> <block|air>",
+                    description: "Set every block in a box",
+                    run: fill,
+                    complete: complete_fill,
+                },
+                Command {
+                    name: "wand",
+                    usage: "/wand",
+                    description: "Toggle the tool for selecting a region with the mouse",
+                    run: wand,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "pos1",
+                    usage: "/pos1 [x y z]",
+                    description: "Set the first corner of the selection, by default the targeted block",
+                    run: first_corner,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "pos2",
+                    usage: "/pos2 [x y z]",
+                    description: "Set the second corner of the selection, by default the targeted block",
+                    run: second_corner,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "desel",
+                    usage: "/desel",
+                    description: "Clear the selection",
+                    run: deselect,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "set",
+                    usage: "/set <block|air>",
+                    description: "Fill the selection",
+                    run: set,
+                    complete: complete_block,
+                },
+                Command {
+                    name: "replace",
+                    usage: "/replace <from|air> <to|air>",
+                    description: "Change one type of block to another in the selection",
+                    run: replace,
+                    complete: complete_replace,
+                },
+                Command {
+                    name: "hollow",
+                    usage: "/hollow <block>",
+                    description: "Build the outside of the selection and empty the inside",
+                    run: hollow,
+                    complete: complete_block,
+                },
+                Command {
+                    name: "sphere",
+                    usage: "/sphere <block>",
+                    description: "Fill the largest sphere that fits in the selection",
+                    run: sphere,
+                    complete: complete_block,
+                },
+                Command {
+                    name: "cyl",
+                    usage: "/cyl <block>",
+                    description: "Fill the largest upright cylinder that fits in the selection",
+                    run: cylinder,
+                    complete: complete_block,
+                },
+                Command {
+                    name: "copy",
+                    usage: "/copy",
+                    description: "Copy the selection to the clipboard",
+                    run: copy,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "paste",
+                    usage: "/paste [x y z]",
+                    description: "Paste the clipboard with its lowest corner at a position, by default against the targeted face",
+                    run: paste,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "rotate",
+                    usage: "/rotate [90|180|270]",
+                    description: "Turn the clipboard clockwise, seen from above",
+                    run: rotate,
+                    complete: complete_rotate,
+                },
+                Command {
+                    name: "mirror",
+                    usage: "/mirror <x|y|z>",
+                    description: "Flip the clipboard along an axis",
+                    run: mirror,
+                    complete: complete_mirror,
+                },
+                Command {
+                    name: "schem",
+                    usage: "/schem export <name>, /schem import <name> [0|90|180|270] [x y z] or /schem list",
+                    description: "Save the selection as a schematic, or paste one, by default against the targeted face",
+                    run: schematic,
+                    complete: complete_schematic,
+                },
+                Command {
+                    name: "vox",
+                    usage: "/vox export <name>, /vox import <name> [0|90|180|270] [x y z] or /vox list",
+                    description: "Save the selection as a MagicaVoxel model, or paste one, by default against the targeted face",
+                    run: vox_model,
+                    complete: complete_vox,
+                },
+                Command {
+                    name: "export",
+                    usage: "/export <obj|glb> <name>",
+                    description: "Save the selection as a mesh for other 3D tools",
+                    run: export_mesh,
+                    complete: complete_export,
+                },
+                Command {
+                    name: "undo",
+                    usage: "/undo",
+                    description: "Undo the last edit, also Ctrl+Z",
+                    run: undo,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "redo",
+                    usage: "/redo",
+                    description: "Redo the last undone edit, also Ctrl+Y",
+                    run: redo,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "seed",
+                    usage: "/seed",
+                    description: "Show the world's seed",
+                    run: seed,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "renderdistance",
+                    usage: "/renderdistance <chunks>",
+                    description: "Change how many chunks are drawn around the player",
+                    run: render_distance,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "fly",
+                    usage: "/fly",
+                    description: "Switch between flying and walking",
+                    run: fly,
+                    complete: no_completions,
+                },
+                Command {
+                    name: "save",
+                    usage: "/save",
+                    description: "Save the world",
+                    run: save,
+                    complete: no_completions,
+                },
+            ],
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|command| command.name == name)
+    }
+
+    /// Runs a line typed without its leading slash
+    pub fn run(&self, context: &mut CommandContext, line: &str) -> CommandResult {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name.to_lowercase(),
+            None => return Err(CommandError::Usage("/<command> [arguments]")),
+        };
+        let args: Vec<&str> = words.collect();
+
+        // Help needs the registry itself, which commands don't get, so it
+        // isn't in it
+        if name == HELP.0 {
+            return self.help(&args);
+        }
+        match self.get(&name) {
+            Some(command) => (command.run)(context, &args),
+            None => Err(CommandError::Unknown(name)),
+        }
+    }
+
+    fn help(&self, args: &[&str]) -> CommandResult {
+        let (_, usage, description) = HELP;
+        let line = |usage: &str, description: &str| format!("{} - {}", usage, description);
+        match args {
+            [] => Ok(std::iter::once(line(usage, description))
+                .chain(
+                    self.commands
+                        .iter()
+                        .map(|command| line(command.usage, command.description)),
+                )
+                .collect::<Vec<_>>()
+                .join("\n")),
+            [name] => {
+                let name = name.trim_start_matches('/').to_lowercase();
+                if name == HELP.0 {
+                    return Ok(line(usage, description));
+                }
+                self.get(&name)
+                    .map(|command| line(command.usage, command.description))
+                    .ok_or(CommandError::Unknown(name))
+            }
+            _ => Err(CommandError::Usage(usage)),
+        }
+    }
+
+    // Every command name, help included
+    fn names(&self) -> Vec<String> {
+        std::iter::once(HELP.0)
+            .chain(self.commands.iter().map(|command| command.name))
+            .map(String::from)
+            .collect()
+    }
+
+    /// Candidates for the last word of `line`, which doesn't include the slash
+    pub fn complete(&self, line: &str, palette: &Palette) -> Vec<String> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        // A trailing space starts a new, empty word
+        if line.is_empty() || line.ends_with(char::is_whitespace) {
+            words.push("");
+        }
+        let (last, before) = match words.split_last() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+
+        let candidates = match before.split_first() {
+            None => self.names(),
+            Some((name, [])) if name.eq_ignore_ascii_case(HELP.0) => self.names(),
+            Some((name, args)) => match self.get(&name.to_lowercase()) {
+                Some(command) => (command.complete)(palette, args.len(), args),
+                None => Vec::new(),
+            },
+        };
+
+        let last = last.to_lowercase();
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.to_lowercase().starts_with(&last))
+            .collect()
+    }
+}
+
+fn no_completions(_: &Palette, _: usize, _: &[&str]) -> Vec<String> {
+    Vec::new()
+}
+
+// How a block type is written in commands. Arguments are split on spaces, so
+// color-only types go by their hex color alone, e.g. #9b3d7a.
+fn block_argument(block_type: &BlockType) -> String {
+    let name = block_type.name.to_lowercase();
+    match name.strip_prefix("color ") {
+        Some(hex) => hex.to_string(),
+        None => name,
+    }
+}
+
+fn block_names(palette: &Palette) -> Vec<String> {
+    palette
+        .iter()
+        .map(|(_, block_type)| block_argument(block_type))
+        .collect()
+}
+
+fn complete_time(_: &Palette, index: usize, args: &[&str]) -> Vec<String> {
+    match (index, args.first()) {
+        (0, _) => vec!["set".into(), "query".into()],
+        (1, Some(&"set")) => ["day", "noon", "night", "midnight"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn complete_give(palette: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index == 0 {
+        block_names(palette)
+    } else {
+        Vec::new()
+    }
+}
+
+fn block_names_and_air(palette: &Palette) -> Vec<String> {
+    let mut names = block_names(palette);
+    names.push("air".into());
+    names
+}
+
+fn complete_fill(palette: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index == 6 {
+        block_names_and_air(palette)
+    } else {
+        Vec::new()
+    }
+}
+
+fn complete_block(palette: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index == 0 {
+        block_names_and_air(palette)
+    } else {
+        Vec::new()
+    }
+}
+
+fn complete_replace(palette: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index < 2 {
+        block_names_and_air(palette)
+    } else {
+        Vec::new()
+    }
+}
+
+fn complete_rotate(_: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index == 0 {
+        vec!["90".into(), "180".into(), "270".into()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn complete_schematic(_: &Palette, index: usize, args: &[&str]) -> Vec<String> {
+    match (index, args.first()) {
+        (0, _) => vec!["export".into(), "import".into(), "list".into()],
+        (1, Some(&"import")) => schematic_names(SCHEMATIC_EXTENSION),
+        (2, Some(&"import")) => vec!["0".into(), "90".into(), "180".into(), "270".into()],
+        _ => Vec::new(),
+    }
+}
+
+fn complete_vox(_: &Palette, index: usize, args: &[&str]) -> Vec<String> {
+    match (index, args.first()) {
+        (0, _) => vec!["export".into(), "import".into(), "list".into()],
+        (1, Some(&"import")) => schematic_names(VOX_EXTENSION),
+        (2, Some(&"import")) => vec!["0".into(), "90".into(), "180".into(), "270".into()],
+        _ => Vec::new(),
+    }
+}
+
+fn complete_export(_: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index == 0 {
+        Format::EXTENSIONS
+            .iter()
+            .map(|&format| format.into())
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn complete_mirror(_: &Palette, index: usize, _: &[&str]) -> Vec<String> {
+    if index == 0 {
+        vec!["x".into(), "y".into(), "z".into()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn invalid(argument: &str, expected: &str) -> CommandError {
+    CommandError::InvalidArgument {
+        argument: argument.to_string(),
+        expected: expected.to_string(),
+    }
+}
+
+// A coordinate, where `~` is relative to `base`
+fn parse_coordinate(argument: &str, base: f32) -> std::result::Result<f32, CommandError> {
+    let (relative, number) = match argument.strip_prefix('~') {
+        Some(rest) => (true, rest),
+        None => (false, argument),
+    };
+    let offset = if relative && number.is_empty() {
+        0.0
+    } else {
+        number
+            .parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| invalid(argument, "a number or ~"))?
+    };
+    Ok(if relative { base + offset } else { offset })
+}
+
+fn parse_block_position(
+    args: &[&str],
+    base: Point3<f32>,
+) -> std::result::Result<Vector3<i32>, CommandError> {
+    Ok(Vector3::new(
+        parse_coordinate(args[0], base.x)?.floor() as i32,
+        parse_coordinate(args[1], base.y)?.floor() as i32,
+        parse_coordinate(args[2], base.z)?.floor() as i32,
+    ))
+}
+
+/// A block type by name, ignoring case, or by id
+fn parse_block(palette: &Palette, argument: &str) -> std::result::Result<usize, CommandError> {
+    palette
+        .iter()
+        .find(|(_, block_type)| block_argument(block_type).eq_ignore_ascii_case(argument))
+        .map(|(id, _)| id)
+        .or_else(|| {
+            argument
+                .parse::<usize>()
+                .ok()
+                .filter(|&id| id < palette.len())
+        })
+        .ok_or_else(|| invalid(argument, "a block name or id"))
+}
+
+fn parse_block_or_air(
+    palette: &Palette,
+    argument: &str,
+) -> std::result::Result<Option<Block>, CommandError> {
+    if argument.eq_ignore_ascii_case("air") {
+        Ok(None)
+    } else {
+        parse_block(palette, argument).map(|id| Some(Block::new(id)))
+    }
+}
+
+fn check_volume(region: Region) -> std::result::Result<(), CommandError> {
+    let volume = region.volume();
+    if volume > MAX_EDIT_VOLUME {
+        Err(CommandError::Failed(format!(
+            "That's {} blocks, edits are limited to {}",
+            volume, MAX_EDIT_VOLUME
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn summarize(summary: EditSummary) -> String {
+    if summary.unloaded > 0 {
+        format!(
+            "Changed {} blocks, skipped {} in unloaded chunks",
+            summary.changed, summary.unloaded
+        )
+    } else {
+        format!("Changed {} blocks", summary.changed)
+    }
+}
+
+fn teleport(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if args.len() != 3 {
+        return Err(CommandError::Usage("/tp <x> <y> <z>"));
+    }
+    let camera = &mut context.player().camera;
+    let base = camera.position;
+    let position = Point3::new(
+        parse_coordinate(args[0], base.x)?,
+        parse_coordinate(args[1], base.y)?,
+        parse_coordinate(args[2], base.z)?,
+    );
+    camera.position = position;
+    Ok(format!(
+        "Teleported to {:.1}, {:.1}, {:.1}",
+        position.x, position.y, position.z
+    ))
+}
+
+fn time(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    const USAGE: &str = "/time set <day|noon|night|midnight|0.0-1.0> or /time query";
+    match args {
+        ["query"] => Ok(format!("The time is {:.3}", context.time())),
+        ["set", value] => {
+            let time = match *value {
+                "day" => 0.35,
+                "noon" => 0.5,
+                "night" => 0.85,
+                "midnight" => 0.0,
+                number => number
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|time| (0.0..=1.0).contains(time))
+                    .ok_or_else(|| invalid(number, "day, noon, night, midnight or 0.0-1.0"))?,
+            };
+            context.set_time(time);
+            Ok(format!("Set the time to {:.3}", time))
+        }
+        _ => Err(CommandError::Usage(USAGE)),
+    }
+}
+
+fn give(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let (block, count) = match args {
+        [block] => (parse_block(context.palette(), block)?, 1),
+        [block, count] => (
+            parse_block(context.palette(), block)?,
+            count
+                .parse::<u32>()
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or_else(|| invalid(count, "a positive whole number"))?,
+        ),
+        _ => return Err(CommandError::Usage("/give <block> [count]")),
+    };
+
+    let inventory = &mut context.player().inventory;
+    let mut left = count;
+    while left > 0 {
+        let stack = ItemStack::new(block, left.min(MAX_STACK));
+        let added = stack.count - inventory.add(stack);
+        if added == 0 {
+            break;
+        }
+        left -= added;
+    }
+    let name = &context.palette().block_type(block).name;
+    if left == count {
+        Err(CommandError::Failed("The inventory is full".into()))
+    } else if left > 0 {
+        Ok(format!(
+            "Gave {} {}, {} didn't fit",
+            count - left,
+            name,
+            left
+        ))
+    } else {
+        Ok(format!("Gave {} {}", count, name))
+    }
+}
+
+fn fill(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if args.len() != 7 {
+        return Err(CommandError::Usage(
+            "/fill <x1> <y1> <z1> <x2> <y2> <z2> <block|air>",
+        ));
+    }
+    let base = context.player().camera.position;
+    let region = Region::new(
+        parse_block_position(&args[0..3], base)?,
+        parse_block_position(&args[3..6], base)?,
+    );
+    let block = parse_block_or_air(context.palette(), args[6])?;
+    check_volume(region)?;
+    Ok(summarize(context.apply(&edit::fill(region, block))))
+}
+
+fn seed(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/seed"));
+    }
+    Ok(format!("Seed: {}", context.seed()))
+}
+
+fn render_distance(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let distance = match args {
+        [distance] => distance
+            .parse::<u16>()
+            .ok()
+            .filter(|distance| (1..=MAX_RENDER_DISTANCE).contains(distance))
+            .ok_or_else(|| {
+                invalid(
+                    distance,
+                    &format!("a whole number from 1 to {}", MAX_RENDER_DISTANCE),
+                )
+            })?,
+        _ => return Err(CommandError::Usage("/renderdistance <chunks>")),
+    };
+    context.set_render_distance(distance);
+    Ok(format!("Render distance set to {} chunks", distance))
+}
+
+fn fly(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/fly"));
+    }
+    let player = context.player();
+    player.flying = !player.flying;
+    Ok(String::from(if player.flying {
+        "Flying"
+    } else {
+        "Walking"
+    }))
+}
+
+fn save(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/save"));
+    }
+    context
+        .save()
+        .map(|()| String::from("Saved the world"))
+        .map_err(|e| CommandError::Failed(format!("Failed to save: {}", e)))
+}
+
+fn wand(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/wand"));
+    }
+    let editor = context.editor();
+    editor.tool = !editor.tool;
+    Ok(String::from(if editor.tool {
+        "Selection tool out, left click the first corner and right click the second"
+    } else {
+        "Selection tool put away"
+    }))
+}
+
+// The block at the given coordinates, or the targeted block without any
+fn corner(
+    context: &mut CommandContext,
+    args: &[&str],
+) -> std::result::Result<Vector3<i32>, CommandError> {
+    match args.len() {
+        0 => context
+            .target()
+            .map(|target| target.block)
+            .ok_or_else(|| CommandError::Failed("Not looking at a block".into())),
+        3 => {
+            let base = context.player().camera.position;
+            parse_block_position(args, base)
+        }
+        _ => Err(CommandError::Usage("/pos1 [x y z] or /pos2 [x y z]")),
+    }
+}
+
+fn first_corner(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let position = corner(context, args)?;
+    context.editor().selection.first = Some(position);
+    Ok(format!(
+        "First corner at {}, {}, {}",
+        position.x, position.y, position.z
+    ))
+}
+
+fn second_corner(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let position = corner(context, args)?;
+    context.editor().selection.second = Some(position);
+    Ok(format!(
+        "Second corner at {}, {}, {}",
+        position.x, position.y, position.z
+    ))
+}
+
+fn deselect(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/desel"));
+    }
+    context.editor().selection = Default::default();
+    Ok(String::from("Selection cleared"))
+}
+
+// The selected region, if both corners are set and it isn't too large
+fn selection(context: &mut CommandContext) -> std::result::Result<Region, CommandError> {
+    let region = context.editor().selection.region().ok_or_else(|| {
+        CommandError::Failed("Select two corners first, with /wand or /pos1 and /pos2".into())
+    })?;
+    check_volume(region)?;
+    Ok(region)
+}
+
+// Runs a command taking a single block argument over the selection
+fn shape(
+    context: &mut CommandContext,
+    args: &[&str],
+    usage: &'static str,
+    build: fn(Region, Option<Block>) -> BlockEdit,
+) -> CommandResult {
+    let block = match args {
+        [block] => parse_block_or_air(context.palette(), block)?,
+        _ => return Err(CommandError::Usage(usage)),
+    };
+    let region = selection(context)?;
+    Ok(summarize(context.apply(&build(region, block))))
+}
+
+fn set(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    shape(context, args, "/set <block|air>", edit::fill)
+}
+
+fn hollow(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    shape(context, args, "/hollow <block>", edit::hollow)
+}
+
+fn sphere(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    shape(context, args, "/sphere <block>", edit::sphere)
+}
+
+fn cylinder(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    shape(context, args, "/cyl <block>", edit::cylinder)
+}
+
+fn replace(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let (from, to) = match args {
+        [from, to] => (
+            parse_block_or_air(context.palette(), from)?.map(|block| block.id()),
+            parse_block_or_air(context.palette(), to)?,
+        ),
+        _ => return Err(CommandError::Usage("/replace <from|air> <to|air>")),
+    };
+    let region = selection(context)?;
+    let edit = edit::replace(context.chunks(), region, from, to);
+    Ok(summarize(context.apply(&edit)))
+}
+
+fn copy(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/copy"));
+    }
+    let region = selection(context)?;
+    let clipboard = Clipboard::copy(context.chunks(), region);
+    let size = clipboard.size();
+    context.editor().clipboard = Some(clipboard);
+    Ok(format!(
+        "Copied {} x {} x {} blocks",
+        size.x, size.y, size.z
+    ))
+}
+
+fn clipboard<'a>(
+    context: &'a mut CommandContext,
+) -> std::result::Result<&'a mut Clipboard, CommandError> {
+    context
+        .editor()
+        .clipboard
+        .as_mut()
+        .ok_or_else(|| CommandError::Failed("Nothing copied yet, use /copy".into()))
+}
+
+// The given coordinates, or against the face of the targeted block without any
+fn paste_origin(
+    context: &mut CommandContext,
+    args: &[&str],
+) -> std::result::Result<Vector3<i32>, CommandError> {
+    match args.len() {
+        0 => context
+            .target()
+            .map(|target| target.adjacent())
+            .ok_or_else(|| CommandError::Failed("Not looking at a block".into())),
+        3 => {
+            let base = context.player().camera.position;
+            parse_block_position(args, base)
+        }
+        _ => Err(CommandError::Usage("/paste [x y z]")),
+    }
+}
+
+fn paste(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let origin = paste_origin(context, args)?;
+    let edit = clipboard(context)?.paste(origin);
+    Ok(summarize(context.apply(&edit)))
+}
+
+fn parse_rotation(argument: &str) -> std::result::Result<u32, CommandError> {
+    match argument {
+        "0" => Ok(0),
+        "90" => Ok(90),
+        "180" => Ok(180),
+        "270" => Ok(270),
+        other => Err(invalid(other, "0, 90, 180 or 270")),
+    }
+}
+
+fn rotate(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let degrees = match args {
+        [] => 90,
+        [degrees] => parse_rotation(degrees)?,
+        _ => return Err(CommandError::Usage("/rotate [90|180|270]")),
+    };
+    let clipboard = clipboard(context)?;
+    *clipboard = clipboard.rotate(degrees / 90);
+    Ok(format!("Rotated the clipboard {} degrees", degrees))
+}
+
+fn mirror(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let axis = match args {
+        [axis] => match axis.to_lowercase().as_str() {
+            "x" => 0,
+            "y" => 1,
+            "z" => 2,
+            _ => return Err(invalid(axis, "x, y or z")),
+        },
+        _ => return Err(CommandError::Usage("/mirror <x|y|z>")),
+    };
+    let clipboard = clipboard(context)?;
+    *clipboard = clipboard.mirror(axis);
+    Ok(format!("Mirrored the clipboard along {}", args[0]))
+}
+
+fn undo(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/undo"));
+    }
+    context
+        .undo()
+        .map(|summary| format!("Undid {} block changes", summary.changed))
+        .map_err(|e| history_failed(e, "undo"))
+}
+
+fn redo(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    if !args.is_empty() {
+        return Err(CommandError::Usage("/redo"));
+    }
+    context
+        .redo()
+        .map(|summary| format!("Redid {} block changes", summary.changed))
+        .map_err(|e| history_failed(e, "redo"))
+}
+
+fn history_failed(error: HistoryError, action: &str) -> CommandError {
+    CommandError::Failed(match error {
+        HistoryError::Empty => format!("Nothing to {}", action),
+        HistoryError::Unloaded => format!(
+            "Can't {} yet, some of the blocks are in chunks that aren't loaded",
+            action
+        ),
+        HistoryError::Survival => format!("Can't {} in survival", action),
+    })
+}
+
+// Files in the schematic directory with `extension`, by name without it
+fn schematic_names(extension: &str) -> Vec<String> {
+    let entries = match std::fs::read_dir(SCHEMATIC_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != extension {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+// Names are limited to what's safe as a file name everywhere
+fn file_path(dir: &str, name: &str, extension: &str) -> std::result::Result<PathBuf, CommandError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(invalid(name, "a name of letters, digits, _ and -"));
+    }
+    Ok(Path::new(dir).join(name).with_extension(extension))
+}
+
+fn schematic(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    const USAGE: &str =
+        "/schem export <name>, /schem import <name> [0|90|180|270] [x y z] or /schem list";
+    match args {
+        ["list"] => {
+            let names = schematic_names(SCHEMATIC_EXTENSION);
+            if names.is_empty() {
+                Ok(String::from("No schematics saved yet"))
+            } else {
+                Ok(names.join(", "))
+            }
+        }
+        ["export", name] => {
+            let path = file_path(SCHEMATIC_DIR, name, SCHEMATIC_EXTENSION)?;
+            let region = selection(context)?;
+            let clipboard = Clipboard::copy(context.chunks(), region);
+            schematic::save(&path, &clipboard, context.palette())
+                .map_err(|e| CommandError::Failed(format!("Failed to export: {}", e)))?;
+            let size = clipboard.size();
+            Ok(format!(
+                "Exported {} x {} x {} blocks to {}",
+                size.x,
+                size.y,
+                size.z,
+                path.display()
+            ))
+        }
+        ["import", name, rest @ ..] => {
+            let path = file_path(SCHEMATIC_DIR, name, SCHEMATIC_EXTENSION)?;
+            let (degrees, position) = match rest {
+                [] => (0, rest),
+                [degrees, position @ ..] if matches!(position.len(), 0 | 3) => {
+                    (parse_rotation(degrees)?, position)
+                }
+                _ => return Err(CommandError::Usage(USAGE)),
+            };
+            let origin = paste_origin(context, position)?;
+            let clipboard = schematic::load(&path, context.palette())
+                .map_err(|e| CommandError::Failed(format!("Failed to import {}: {}", name, e)))?
+                .rotate(degrees / 90);
+            check_volume(Region::new(
+                origin,
+                origin + clipboard.size() - Vector3::new(1, 1, 1),
+            ))?;
+            Ok(summarize(context.apply(&clipboard.paste(origin))))
+        }
+        _ => Err(CommandError::Usage(USAGE)),
+    }
+}
+
+fn vox_model(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    const USAGE: &str =
+        "/vox export <name>, /vox import <name> [0|90|180|270] [x y z] or /vox list";
+    match args {
+        ["list"] => {
+            let names = schematic_names(VOX_EXTENSION);
+            if names.is_empty() {
+                Ok(String::from("No models saved yet"))
+            } else {
+                Ok(names.join(", "))
+            }
+        }
+        ["export", name] => {
+            let path = file_path(SCHEMATIC_DIR, name, VOX_EXTENSION)?;
+            let region = selection(context)?;
+            let clipboard = Clipboard::copy(context.chunks(), region);
+            vox::save(&path, &clipboard, context.palette())
+                .map_err(|e| CommandError::Failed(format!("Failed to export: {}", e)))?;
+            let size = clipboard.size();
+            Ok(format!(
+                "Exported {} x {} x {} blocks to {}",
+                size.x,
+                size.y,
+                size.z,
+                path.display()
+            ))
+        }
+        ["import", name, rest @ ..] => {
+            let path = file_path(SCHEMATIC_DIR, name, VOX_EXTENSION)?;
+            let (degrees, position) = match rest {
+                [] => (0, rest),
+                [degrees, position @ ..] if matches!(position.len(), 0 | 3) => {
+                    (parse_rotation(degrees)?, position)
+                }
+                _ => return Err(CommandError::Usage(USAGE)),
+            };
+            let origin = paste_origin(context, position)?;
+            let file = vox::load(&path)
+                .map_err(|e| CommandError::Failed(format!("Failed to import {}: {}", name, e)))?;
+            let clipboard = file
+                .clipboard(0, context.palette())
+                .map_err(|e| CommandError::Failed(format!("Failed to import {}: {}", name, e)))?
+                .rotate(degrees / 90);
+            check_volume(Region::new(
+                origin,
+                origin + clipboard.size() - Vector3::new(1, 1, 1),
+            ))?;
+            let mut message = summarize(context.apply(&clipboard.paste(origin)));
+            if file.models.len() > 1 {
+                message.push_str(&format!(
+                    ", only the first of {} models was imported",
+                    file.models.len()
+                ));
+            }
+            Ok(message)
+        }
+        _ => Err(CommandError::Usage(USAGE)),
+    }
+}
+
+fn export_mesh(context: &mut CommandContext, args: &[&str]) -> CommandResult {
+    let (extension, name) = match args {
+        [extension, name] => (extension, name),
+        _ => return Err(CommandError::Usage("/export <obj|glb> <name>")),
+    };
+    let format =
+        Format::from_extension(extension).ok_or_else(|| invalid(extension, "obj or glb"))?;
+    let path = file_path(EXPORT_DIR, name, &extension.to_lowercase())?;
+    let region = selection(context)?;
+    check_volume(region)?;
+    let mesh = context.mesh_region(region);
+    export::save(&path, format, &mesh, context.palette())
+        .map_err(|e| CommandError::Failed(format!("Failed to export: {}", e)))?;
+    Ok(format!(
+        "Exported {} faces to {}",
+        mesh.faces.len(),
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{BLOCK_TYPES, DIRT, STONE};
+
+    #[test]
+    fn parses_absolute_and_relative_coordinates() {
+        assert_eq!(parse_coordinate("12", 5.0).unwrap(), 12.0);
+        assert_eq!(parse_coordinate("-3.5", 5.0).unwrap(), -3.5);
+        assert_eq!(parse_coordinate("~", 5.0).unwrap(), 5.0);
+        assert_eq!(parse_coordinate("~2", 5.0).unwrap(), 7.0);
+        assert_eq!(parse_coordinate("~-10", 5.0).unwrap(), -5.0);
+        for bad in &["", "x", "~x", "~~", "1~", "inf", "NaN", "~inf"] {
+            assert!(parse_coordinate(bad, 0.0).is_err(), "parsed {:?}", bad);
+        }
+        assert_eq!(
+            parse_block_position(&["~", "~1", "-0.5"], Point3::new(1.5, 2.5, 3.5)).unwrap(),
+            Vector3::new(1, 3, -1)
+        );
+    }
+
+    #[test]
+    fn parses_blocks_by_name_or_id() {
+        let palette = Palette::default();
+        assert_eq!(parse_block(&palette, "stone").unwrap(), STONE);
+        assert_eq!(parse_block(&palette, "DIRT").unwrap(), DIRT);
+        assert_eq!(parse_block(&palette, &DIRT.to_string()).unwrap(), DIRT);
+        for bad in &["", "air", "bedrock", "-1"] {
+            assert!(parse_block(&palette, bad).is_err(), "parsed {:?}", bad);
+        }
+        assert!(parse_block(&palette, &BLOCK_TYPES.len().to_string()).is_err());
+        assert!(parse_block_or_air(&palette, "Air").unwrap().is_none());
+    }
+
+    #[test]
+    fn color_blocks_go_by_their_hex_color() {
+        let commands = Commands::new();
+        let mut palette = Palette::default();
+        let color = palette.color_block([0x9b, 0x3d, 0x7a]).unwrap();
+        assert_eq!(parse_block(&palette, "#9B3D7A").unwrap(), color);
+        assert_eq!(parse_block(&palette, &color.to_string()).unwrap(), color);
+        assert!(parse_block(&palette, "#123456").is_err());
+        assert_eq!(commands.complete("give #", &palette), vec!["#9b3d7a"]);
+    }
+
+    #[test]
+    fn completes_command_names() {
+        let commands = Commands::new();
+        let palette = Palette::default();
+        assert_eq!(commands.complete("ti", &palette), vec!["time"]);
+        assert_eq!(
+            commands.complete("s", &palette),
+            vec!["set", "sphere", "schem", "seed", "save"]
+        );
+        assert!(commands
+            .complete("", &palette)
+            .contains(&String::from("help")));
+        assert_eq!(
+            commands.complete("", &palette).len(),
+            commands.commands.len() + 1
+        );
+        assert!(commands.complete("nope", &palette).is_empty());
+        assert!(commands.complete("nope ", &palette).is_empty());
+    }
+
+    #[test]
+    fn completes_arguments() {
+        let commands = Commands::new();
+        let palette = Palette::default();
+        assert_eq!(commands.complete("time ", &palette), vec!["set", "query"]);
+        assert_eq!(
+            commands.complete("TIME set n", &palette),
+            vec!["noon", "night"]
+        );
+        assert_eq!(commands.complete("give st", &palette), vec!["stone"]);
+        assert!(commands.complete("give stone ", &palette).is_empty());
+        assert_eq!(
+            commands.complete("help re", &palette),
+            vec!["replace", "redo", "renderdistance"]
+        );
+        assert!(commands.complete("help redo ", &palette).is_empty());
+        assert_eq!(commands.complete("set a", &palette), vec!["air"]);
+    }
+
+    #[test]
+    fn help_lists_the_registry() {
+        let commands = Commands::new();
+        let help = commands.help(&[]).unwrap();
+        assert_eq!(help.lines().count(), commands.commands.len() + 1);
+        assert!(help.starts_with("/help [command]"));
+        assert!(commands
+            .help(&["/tp"])
+            .unwrap()
+            .starts_with("/tp <x> <y> <z>"));
+        assert!(commands.help(&["help"]).unwrap().starts_with("/help"));
+        assert!(commands.help(&["nope"]).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+use crate::command::Commands;
+use crate::render::block::Palette;
+
+// Older lines are dropped from the scrollback past this
+const MAX_LINES: usize = 200;
+const MAX_HISTORY: usize = 50;
+// Lines scrolled by Page Up and Page Down
+const PAGE_LINES: usize = 5;
+// How long new lines stay on screen with the console closed
+pub const RECENT_TIME: Duration = Duration::from_secs(8);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineKind {
+    Chat,
+    Info,
+    Error,
+}
+
+pub struct Line {
+    pub text: String,
+    pub kind: LineKind,
+    pub time: Instant,
+}
+
+pub enum ConsoleEvent {
+    // A line was entered, without surrounding whitespace
+    Submit(String),
+    Close,
+}
+
+/// The chat log and the line being typed. Lines starting with a slash are
+/// commands, anything else is chat.
+pub struct Console {
+    lines: VecDeque<Line>,
+    pub input: String,
+    history: Vec<String>,
+    // The history entry being shown, and what was typed before browsing it
+    browsing: Option<usize>,
+    draft: String,
+    // Lines scrolled back from the newest
+    pub scroll: usize,
+    // The key that opened the console also arrives as a typed character
+    skip: Option<char>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            input: String::new(),
+            history: Vec::new(),
+            browsing: None,
+            draft: String::new(),
+            scroll: 0,
+            skip: None,
+        }
+    }
+
+    /// Starts a new line, beginning with `/` when opened with the slash key
+    pub fn open(&mut self, opened_with: char) {
+        self.input = if opened_with == '/' {
+            String::from("/")
+        } else {
+            String::new()
+        };
+        self.browsing = None;
+        self.scroll = 0;
+        self.skip = Some(opened_with);
+    }
+
+    /// Adds to the scrollback, a line at a time
+    pub fn log(&mut self, text: &str, kind: LineKind) {
+        let time = Instant::now();
+        for line in text.lines() {
+            if self.lines.len() == MAX_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(Line {
+                text: line.to_string(),
+                kind,
+                time,
+            });
+        }
+    }
+
+    /// Newest first
+    pub fn lines(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter().rev()
+    }
+
+    pub fn handle_input(
+        &mut self,
+        event: &WindowEvent,
+        commands: &Commands,
+        palette: &Palette,
+    ) -> Option<ConsoleEvent> {
+        match event {
+            WindowEvent::ReceivedCharacter(c) => {
+                let skipped =
+                    matches!(self.skip.take(), Some(skip) if skip.eq_ignore_ascii_case(c));
+                if skipped || c.is_control() {
+                    return None;
+                }
+                self.input.push(*c);
+                self.browsing = None;
+                None
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => self.key(*key, commands, palette),
+            _ => None,
+        }
+    }
+
+    fn key(
+        &mut self,
+        key: VirtualKeyCode,
+        commands: &Commands,
+        palette: &Palette,
+    ) -> Option<ConsoleEvent> {
+        match key {
+            VirtualKeyCode::Escape => return Some(ConsoleEvent::Close),
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => return Some(self.submit()),
+            VirtualKeyCode::Back => {
+                self.input.pop();
+                self.browsing = None;
+            }
+            VirtualKeyCode::Tab => self.complete(commands, palette),
+            VirtualKeyCode::Up => self.browse_history(true),
+            VirtualKeyCode::Down => self.browse_history(false),
+            VirtualKeyCode::PageUp => {
+                self.scroll = (self.scroll + PAGE_LINES).min(self.lines.len().saturating_sub(1));
+            }
+            VirtualKeyCode::PageDown => self.scroll = self.scroll.saturating_sub(PAGE_LINES),
+            _ => {}
+        }
+        None
+    }
+
+    fn submit(&mut self) -> ConsoleEvent {
+        let line = std::mem::take(&mut self.input).trim().to_string();
+        if line.is_empty() || line == "/" {
+            return ConsoleEvent::Close;
+        }
+        if self.history.last() != Some(&line) {
+            if self.history.len() == MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+        self.browsing = None;
+        ConsoleEvent::Submit(line)
+    }
+
+    // Steps through earlier lines, coming back to the one being typed
+    fn browse_history(&mut self, older: bool) {
+        let index = match (self.browsing, older) {
+            (None, true) if !self.history.is_empty() => {
+                self.draft = self.input.clone();
+                Some(self.history.len() - 1)
+            }
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => {
+                self.input = std::mem::take(&mut self.draft);
+                None
+            }
+            (None, _) => return,
+        };
+        if let Some(index) = index {
+            self.input = self.history[index].clone();
+        }
+        self.browsing = index;
+    }
+
+    /// Completes the command or argument before the cursor as far as every
+    /// candidate agrees, listing the candidates when that doesn't narrow it down
+    fn complete(&mut self, commands: &Commands, palette: &Palette) {
+        let line = match self.input.strip_prefix('/') {
+            Some(line) => line,
+            None => return,
+        };
+        let candidates = commands.complete(line, palette);
+        let common = match common_prefix(&candidates) {
+            Some(common) => common,
+            None => return,
+        };
+
+        // Where the word being completed starts, after the slash or a space
+        let start = self
+            .input
+            .rfind(char::is_whitespace)
+            .map_or(1, |space| space + 1);
+        let word = &self.input[start..];
+        if candidates.len() == 1 {
+            self.input.replace_range(start.., &candidates[0]);
+            self.input.push(' ');
+        } else if common.len() > word.len() {
+            self.input.replace_range(start.., &common);
+        } else {
+            self.log(&candidates.join(", "), LineKind::Info);
+        }
+        self.browsing = None;
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> Option<String> {
+    let (first, rest) = candidates.split_first()?;
+    let mut common = first.as_str();
+    for candidate in rest {
+        let length = common
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(candidate.len()), |((i, _), _)| i);
+        common = &common[..length];
+    }
+    Some(common.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn console(history: &[&str]) -> Console {
+        let mut console = Console::new();
+        console.history = history.iter().map(|line| line.to_string()).collect();
+        console
+    }
+
+    #[test]
+    fn history_goes_back_and_returns_to_the_draft() {
+        let mut console = console(&["/seed", "hello", "/fly"]);
+        console.input = String::from("/ti");
+        console.browse_history(true);
+        assert_eq!(console.input, "/fly");
+        console.browse_history(true);
+        console.browse_history(true);
+        assert_eq!(console.input, "/seed");
+        // Stops at the oldest
+        console.browse_history(true);
+        assert_eq!(console.input, "/seed");
+        console.browse_history(false);
+        assert_eq!(console.input, "hello");
+        console.browse_history(false);
+        console.browse_history(false);
+        assert_eq!(console.input, "/ti");
+        assert_eq!(console.browsing, None);
+        // Nothing newer than the draft
+        console.browse_history(false);
+        assert_eq!(console.input, "/ti");
+    }
+
+    #[test]
+    fn empty_history_keeps_the_input() {
+        let mut console = console(&[]);
+        console.input = String::from("/tp");
+        console.browse_history(true);
+        assert_eq!(console.input, "/tp");
+        assert_eq!(console.browsing, None);
+    }
+
+    #[test]
+    fn common_prefixes() {
+        let strings = |words: &[&str]| {
+            words
+                .iter()
+                .map(|word| word.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(common_prefix(&[]), None);
+        assert_eq!(common_prefix(&strings(&["time"])).unwrap(), "time");
+        assert_eq!(
+            common_prefix(&strings(&["replace", "renderdistance", "redo"])).unwrap(),
+            "re"
+        );
+        assert_eq!(common_prefix(&strings(&["pos1", "pos"])).unwrap(), "pos");
+        assert_eq!(common_prefix(&strings(&["set", "fly"])).unwrap(), "");
+    }
+
+    #[test]
+    fn completes_unique_candidates_and_common_prefixes() {
+        let commands = Commands::new();
+        let palette = Palette::default();
+        let mut console = console(&[]);
+
+        console.input = String::from("/ti");
+        console.complete(&commands, &palette);
+        assert_eq!(console.input, "/time ");
+
+        console.input = String::from("/time set mi");
+        console.complete(&commands, &palette);
+        assert_eq!(console.input, "/time set midnight ");
+
+        console.input = String::from("/po");
+        console.complete(&commands, &palette);
+        assert_eq!(console.input, "/pos");
+    }
+
+    #[test]
+    fn lists_candidates_that_dont_narrow_down() {
+        let commands = Commands::new();
+        let palette = Palette::default();
+        let mut console = console(&[]);
+        console.input = String::from("/pos");
+        console.complete(&commands, &palette);
+        assert_eq!(console.input, "/pos");
+        assert_eq!(console.lines().next().unwrap().text, "pos1, pos2");
+
+        // Chat isn't completed
+        console.input = String::from("ti");
+        console.complete(&commands, &palette);
+        assert_eq!(console.input, "ti");
+    }
+}
@@ -1,24 +1,96 @@
 use cgmath::Deg;
+use std::path::Path;
 use std::time::Duration;
-use winit::{event::WindowEvent, window::Window};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent},
+    window::Window,
+};
 
+use crate::cli::Cli;
+use crate::command::{CommandContext, Commands};
+use crate::console::{Console, ConsoleEvent, LineKind};
+use crate::error::{Error, Result};
+use crate::hud;
+use crate::menu::{self, MenuAction};
+use crate::profiler;
 use crate::render::{
     camera::{Camera, Projection},
     graphics::{Graphics, Render},
+    icon,
+    quad::TextureId,
+    screen::Screen,
 };
-use crate::{player::Player, world::World};
+use crate::settings::{Settings, WindowMode};
+use crate::ui::Ui;
+use crate::{player::Player, save::WorldSave, screenshot::Screenshots, world::World};
+
+// Shift + screenshot key captures at this multiple of the window size
+const HIGH_RES_SCREENSHOT_SCALE: u32 = 4;
+// Pixel size of the block icons drawn in the hotbar
+const BLOCK_ICON_SIZE: u32 = 32;
+// Where F4 writes recorded profiler traces
+const TRACE_DIR: &str = "traces";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameState {
+    MainMenu,
+    // Waiting for the chunks around the player before handing over control
+    Loading,
+    Playing,
+    // The world keeps running behind the inventory screen
+    Inventory,
+    // Typing into the console, with the world still running
+    Console,
+    // The world isn't updated while paused
+    Paused(PauseScreen),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PauseScreen {
+    Menu,
+    Settings,
+}
 
 pub struct Context {
-    pub size: winit::dpi::PhysicalSize<u32>,
+    pub size: PhysicalSize<u32>,
 
-    surface: wgpu::Surface,
+    screen: Screen,
     graphics: Graphics,
 
     world: World,
+    state: GameState,
+    quit: bool,
+    ui: Ui,
+    // Indexed by block id
+    block_icons: Vec<TextureId>,
+    inventory_search: String,
+    console: Console,
+    commands: Commands,
+
+    modifiers: ModifiersState,
+    screenshots: Screenshots,
+    screenshot_scale: Option<u32>,
+
+    // The settings in use, with the command line overrides applied
+    pub settings: Settings,
+    // What the settings file has, without the overrides
+    file_settings: Settings,
+    // `settings` when the file was last written, to tell when they change
+    saved_settings: Settings,
+    cli: Cli,
 }
 
 impl Context {
-    pub async fn new(window: &Window) -> Self {
+    pub async fn new(
+        window: &Window,
+        cli: Cli,
+        file_settings: Settings,
+        save: WorldSave,
+    ) -> Result<Self> {
+        let mut settings = file_settings.clone();
+        cli.apply(&mut settings);
+
         log::info!("Initializing Context");
         let size = window.inner_size();
         let surface = wgpu::Surface::create(window);
@@ -32,72 +104,450 @@ impl Context {
             wgpu::BackendBit::PRIMARY,
         )
         .await
-        .unwrap();
-
-        // Request access to GPU
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                extensions: wgpu::Extensions {
-                    anisotropic_filtering: false,
-                },
-                limits: Default::default(),
-            })
-            .await;
-
-        let sc_desc = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8Unorm,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-        };
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        .ok_or(Error::NoAdapter)?;
 
         log::info!("Initializing Graphics");
-        let graphics = Graphics::new(size, adapter, sc_desc, swap_chain, device, queue);
+        let graphics = Graphics::new(size, adapter, wgpu::TextureFormat::Bgra8Unorm).await;
+        let screen = Screen::new(
+            surface,
+            &graphics.device,
+            size,
+            graphics.format,
+            settings.present_mode(),
+        );
 
-        let camera = Camera::new((-13.0, 16.0, -12.0), Deg(-90.0), Deg(-10.0));
+        let camera = Camera::new(save.spawn(), Deg(-90.0), Deg(-10.0));
         let projection = Projection::new(
-            graphics.sc_desc.width,
-            graphics.sc_desc.height,
-            Deg(70.0),
-            0.1,
-            100.0,
+            size.width,
+            size.height,
+            Deg(settings.fov),
+            settings.znear(),
+            settings.zfar(),
         );
-        let player = Player::new(camera);
+        let mut player = Player::new(camera, settings.movement_speed, settings.mouse_sensitivity);
+        if let Some(data) = &save.player {
+            player.load(data);
+        }
+        if let Some(spawn) = cli.spawn {
+            player.camera.position = spawn;
+        }
+        if let Some(game_mode) = cli.game_mode {
+            player.game_mode = game_mode;
+        }
 
         log::info!("Initializing world");
-        let world = World::new(player, projection, &graphics);
+        let world = World::new(player, projection, &settings, save, &graphics)?;
+        let ui = Ui::new(&graphics)?;
 
-        Self {
+        Ok(Self {
             size,
-            surface,
+            screen,
             graphics,
             world,
-        }
+            state: GameState::MainMenu,
+            quit: false,
+            ui,
+            block_icons: Vec::new(),
+            inventory_search: String::new(),
+            console: Console::new(),
+            commands: Commands::new(),
+            modifiers: ModifiersState::empty(),
+            screenshots: Screenshots::new("screenshots".into()),
+            screenshot_scale: None,
+            saved_settings: settings.clone(),
+            settings,
+            file_settings,
+            cli,
+        })
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.graphics.resize(new_size, &self.surface);
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        // Minimized windows report a zero size, which the swap chain can't use
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        self.size = new_size;
+        if self.settings.window_mode == WindowMode::Windowed {
+            self.settings.window_width = new_size.width;
+            self.settings.window_height = new_size.height;
+        }
+        self.graphics.resize(new_size);
+        self.screen.resize(new_size, &self.graphics.device);
         self.world.resize(new_size, &self.graphics);
     }
 
+    /// Moves on to the next window mode. The window reports its new size
+    /// afterwards, which goes through `resize` like any other.
+    pub fn toggle_fullscreen(&mut self, window: &Window) {
+        self.settings.window_mode = self.settings.window_mode.next();
+        log::info!("Switching to {:?}", self.settings.window_mode);
+
+        let fullscreen = self
+            .settings
+            .fullscreen(window.available_monitors(), window.primary_monitor());
+        window.set_fullscreen(fullscreen);
+        if self.settings.window_mode == WindowMode::Windowed {
+            window.set_inner_size(PhysicalSize::new(
+                self.settings.window_width,
+                self.settings.window_height,
+            ));
+        }
+    }
+
+    /// Whether the cursor should be hidden and kept in the window
+    pub fn cursor_grabbed(&self) -> bool {
+        matches!(self.state, GameState::Playing)
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    fn close_inventory(&mut self) {
+        let player = self.world.player_mut();
+        let dropped = player.inventory.return_held();
+        if dropped > 0 {
+            log::debug!("Inventory full, dropped {} held blocks", dropped);
+        }
+        self.state = GameState::Playing;
+    }
+
+    fn open_console(&mut self, opened_with: char) {
+        self.world.release_input();
+        self.console.open(opened_with);
+        self.state = GameState::Console;
+    }
+
+    fn console_event(&mut self, event: ConsoleEvent) {
+        self.state = GameState::Playing;
+        let line = match event {
+            ConsoleEvent::Submit(line) => line,
+            ConsoleEvent::Close => return,
+        };
+        self.console.log(&line, LineKind::Chat);
+        if let Some(command) = line.strip_prefix('/') {
+            self.run_command(command);
+        }
+    }
+
+    /// Runs a command, without its slash, and logs what it says to the console
+    fn run_command(&mut self, command: &str) {
+        let mut context = CommandContext::new(&mut self.world, &mut self.settings);
+        match self.commands.run(&mut context, command) {
+            Ok(output) => self.console.log(&output, LineKind::Info),
+            Err(e) => self.console.log(&e.to_string(), LineKind::Error),
+        }
+    }
+
+    fn pause(&mut self) {
+        log::info!("Paused");
+        self.world.release_input();
+        self.state = GameState::Paused(PauseScreen::Menu);
+    }
+
     pub fn input(&mut self, event: &WindowEvent, focused: bool) -> bool {
         if !focused {
             return false;
         }
+        // Function keys don't type anything, but shouldn't act behind a
+        // focused text input either
+        let typing = self.ui.wants_keyboard();
         match event {
-            _ => self
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                return false;
+            }
+            WindowEvent::Focused(false) => {
+                if let GameState::Playing = self.state {
+                    self.pause();
+                }
+                return false;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F2),
+                        ..
+                    },
+                ..
+            } if !typing => {
+                self.screenshot_scale = Some(if self.modifiers.shift() {
+                    HIGH_RES_SCREENSHOT_SCALE
+                } else {
+                    1
+                });
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F3),
+                        ..
+                    },
+                ..
+            } if !typing => {
+                self.world.toggle_debug();
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F4),
+                        ..
+                    },
+                ..
+            } if !typing => {
+                self.toggle_trace();
+                return true;
+            }
+            _ => {}
+        }
+
+        if self.ui.handle_input(event) {
+            return true;
+        }
+
+        let escape = key_pressed(event, VirtualKeyCode::Escape);
+        let inventory = key_pressed(event, VirtualKeyCode::E);
+        let chat = key_pressed(event, VirtualKeyCode::T);
+        let command = key_pressed(event, VirtualKeyCode::Slash);
+        let (ctrl, shift) = (self.modifiers.ctrl(), self.modifiers.shift());
+        let undo = ctrl && !shift && key_pressed(event, VirtualKeyCode::Z);
+        let redo = ctrl
+            && (key_pressed(event, VirtualKeyCode::Y)
+                || shift && key_pressed(event, VirtualKeyCode::Z));
+        match self.state {
+            GameState::Playing if escape => {
+                self.pause();
+                true
+            }
+            GameState::Playing if inventory => {
+                self.world.release_input();
+                self.state = GameState::Inventory;
+                true
+            }
+            GameState::Playing if undo => {
+                self.run_command("undo");
+                true
+            }
+            GameState::Playing if redo => {
+                self.run_command("redo");
+                true
+            }
+            GameState::Playing if chat || command => {
+                self.open_console(if command { '/' } else { 't' });
+                true
+            }
+            GameState::Inventory if escape || inventory => {
+                self.close_inventory();
+                true
+            }
+            GameState::Console => {
+                if let Some(event) =
+                    self.console
+                        .handle_input(event, &self.commands, self.world.palette())
+                {
+                    self.console_event(event);
+                }
+                true
+            }
+            GameState::Playing => self
                 .world
                 .handle_input(event, self.size.width, self.size.height),
+            GameState::Paused(PauseScreen::Menu) if escape => {
+                self.menu_action(MenuAction::Resume);
+                true
+            }
+            GameState::Paused(PauseScreen::Settings) if escape => {
+                self.menu_action(MenuAction::Back);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::Play => {
+                log::info!("Loading world");
+                self.state = GameState::Loading;
+            }
+            MenuAction::Quit => self.quit = true,
+            MenuAction::Resume => self.state = GameState::Playing,
+            MenuAction::OpenSettings => self.state = GameState::Paused(PauseScreen::Settings),
+            MenuAction::Back => self.state = GameState::Paused(PauseScreen::Menu),
+            MenuAction::SaveAndQuit => {
+                self.save_world();
+                self.state = GameState::MainMenu;
+            }
+            MenuAction::SettingsChanged => {
+                self.world.apply_settings(&self.settings);
+                self.screen
+                    .set_present_mode(self.settings.present_mode(), &self.graphics.device);
+            }
+        }
+    }
+
+    /// Starts recording profiler scopes, or stops and writes them to the
+    /// traces directory
+    fn toggle_trace(&mut self) {
+        if !profiler::is_recording() {
+            log::info!("Recording trace");
+            profiler::start_recording();
+            return;
+        }
+        if let Some(trace) = profiler::stop_recording() {
+            let name = format!(
+                "{}.json",
+                chrono::Local::now().format("%Y-%m-%d_%H.%M.%S%.3f")
+            );
+            let path = Path::new(TRACE_DIR).join(name);
+            match trace.write(&path) {
+                Ok(()) => log::info!("Wrote trace to {:?}", path),
+                Err(e) => log::error!("Failed to write trace {:?}: {}", path, e),
+            }
+        }
+    }
+
+    // Draws icons for block types added since the last frame, such as the
+    // colors of an imported .vox model
+    fn add_block_icons(&mut self) {
+        let palette = self.world.palette();
+        for (_, block_type) in palette.iter().skip(self.block_icons.len()) {
+            let icon = icon::block_icon(block_type, BLOCK_ICON_SIZE);
+            let texture = self
+                .ui
+                .renderer
+                .add_texture(&self.graphics, &icon, &block_type.name);
+            self.block_icons.push(texture);
+        }
+    }
+
+    // Declares this frame's UI for the current state
+    fn build_ui(&mut self) {
+        self.add_block_icons();
+        self.ui.begin_frame(self.size.width, self.size.height);
+        if self.world.debug_visible() {
+            hud::frame_graph(&mut self.ui, self.world.frame_times());
+        }
+        let action = match self.state {
+            GameState::MainMenu => menu::main_menu(&mut self.ui),
+            GameState::Loading => {
+                menu::loading_screen(&mut self.ui, self.world.pending_chunks());
+                None
+            }
+            GameState::Playing => {
+                hud::crosshair(&mut self.ui);
+                hud::hotbar(
+                    &mut self.ui,
+                    self.world.player(),
+                    self.world.palette(),
+                    &self.block_icons,
+                );
+                hud::selection_tool(&mut self.ui, self.world.editor());
+                hud::console(&mut self.ui, &self.console, false);
+                None
+            }
+            GameState::Console => {
+                hud::hotbar(
+                    &mut self.ui,
+                    self.world.player(),
+                    self.world.palette(),
+                    &self.block_icons,
+                );
+                hud::console(&mut self.ui, &self.console, true);
+                None
+            }
+            GameState::Inventory => {
+                let (player, palette) = self.world.player_and_palette();
+                hud::inventory_screen(
+                    &mut self.ui,
+                    player,
+                    palette,
+                    &mut self.inventory_search,
+                    &self.block_icons,
+                    self.modifiers.shift(),
+                );
+                None
+            }
+            GameState::Paused(PauseScreen::Menu) => menu::pause_menu(&mut self.ui),
+            GameState::Paused(PauseScreen::Settings) => {
+                menu::settings_menu(&mut self.ui, &mut self.settings)
+            }
+        };
+        self.ui.end_frame();
+
+        if let Some(action) = action {
+            self.menu_action(action);
         }
     }
 
     pub fn update(&mut self, dt: Duration) {
-        self.world.update(dt, &self.graphics);
+        profiler::begin_frame();
+        match self.state {
+            GameState::Playing | GameState::Inventory | GameState::Console => {
+                self.world.update(dt, &self.graphics)
+            }
+            GameState::Loading => {
+                self.world.update(dt, &self.graphics);
+                if self.world.is_loaded() {
+                    log::info!("Finished loading");
+                    self.state = GameState::Playing;
+                }
+            }
+            GameState::MainMenu | GameState::Paused(_) => self.world.update_camera(&self.graphics),
+        }
+        self.build_ui();
+        self.screenshots.poll(&self.graphics);
+
+        // Only tried once per change, so a file that can't be written isn't
+        // retried every frame
+        if self.settings != self.saved_settings {
+            self.file_settings = self.cli.saved_settings(&self.settings, &self.file_settings);
+            if let Err(e) = self.file_settings.save(&self.cli.config) {
+                log::warn!("Failed to save settings: {}", e);
+            }
+            self.saved_settings = self.settings.clone();
+        }
+    }
+
+    pub fn save_world(&mut self) {
+        if let Err(e) = self.world.save() {
+            log::error!("Failed to save world: {}", e);
+        }
     }
 
     pub fn render(&mut self) {
-        self.world.render(&mut self.graphics);
+        let frame = match self.screen.next_frame(&self.graphics.device) {
+            Some(frame) => frame,
+            None => {
+                log::warn!("Skipping frame, no swap chain texture available");
+                return;
+            }
+        };
+
+        if let Some(scale) = self.screenshot_scale.take() {
+            self.screenshots
+                .capture(&self.graphics, &mut self.world, &mut self.ui, scale);
+        }
+        self.world.render(&self.graphics, &frame.view);
+        self.ui.render(&self.graphics, &frame.view);
     }
 }
+
+fn key_pressed(event: &WindowEvent, key: VirtualKeyCode) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(pressed),
+                ..
+            },
+            ..
+        } if *pressed == key
+    )
+}
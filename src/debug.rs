@@ -0,0 +1,171 @@
+use cgmath::{Deg, Point3, Vector3};
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use crate::profiler::ScopeTiming;
+use crate::render::chunk::{ChunkPosition, ChunkStats};
+
+// Frame times are summarized and graphed over this many of the latest frames
+pub const FRAME_WINDOW: usize = 120;
+
+/// A rolling window of the most recent frame times
+pub struct FrameTimes {
+    times: VecDeque<Duration>,
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self {
+            times: VecDeque::with_capacity(FRAME_WINDOW),
+        }
+    }
+
+    pub fn push(&mut self, time: Duration) {
+        if self.times.len() == FRAME_WINDOW {
+            self.times.pop_front();
+        }
+        self.times.push_back(time);
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Duration> {
+        self.times.iter()
+    }
+
+    pub fn summary(&self) -> FrameSummary {
+        if self.times.is_empty() {
+            return FrameSummary::default();
+        }
+        let total: Duration = self.times.iter().sum();
+        FrameSummary {
+            min: self.times.iter().min().copied().unwrap_or_default(),
+            average: total / self.times.len() as u32,
+            max: self.times.iter().max().copied().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameSummary {
+    pub min: Duration,
+    pub average: Duration,
+    pub max: Duration,
+}
+
+impl FrameSummary {
+    pub fn fps(&self) -> f64 {
+        let average = self.average.as_secs_f64();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Everything shown on the F3 overlay for a single frame
+pub struct DebugStats<'a> {
+    pub frames: FrameSummary,
+    pub position: Point3<f32>,
+    pub chunk: ChunkPosition,
+    pub direction: Vector3<f32>,
+    pub yaw: Deg<f32>,
+    pub pitch: Deg<f32>,
+    // The targeted block and its id
+    pub target: Option<(Vector3<i32>, usize)>,
+    pub chunks: ChunkStats,
+    // Profiled scopes from the last frame
+    pub scopes: Vec<ScopeTiming>,
+    pub recording: bool,
+    pub adapter: &'a wgpu::AdapterInfo,
+}
+
+impl fmt::Display for DebugStats<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        writeln!(
+            f,
+            "{:.0} fps, frame min/avg/max {:.2}/{:.2}/{:.2} ms",
+            self.frames.fps(),
+            ms(self.frames.min),
+            ms(self.frames.average),
+            ms(self.frames.max),
+        )?;
+        writeln!(
+            f,
+            "x: {:.3}, y: {:.3}, z: {:.3}",
+            self.position.x, self.position.y, self.position.z
+        )?;
+        writeln!(
+            f,
+            "chunk: {}, {}, {}",
+            self.chunk.x, self.chunk.y, self.chunk.z
+        )?;
+        writeln!(
+            f,
+            "facing: {} (yaw {:.1}, pitch {:.1})",
+            compass(self.direction),
+            self.yaw.0,
+            self.pitch.0
+        )?;
+        match self.target {
+            Some((block, id)) => writeln!(
+                f,
+                "target: block {} at {}, {}, {}",
+                id, block.x, block.y, block.z
+            )?,
+            None => writeln!(f, "target: none")?,
+        }
+        writeln!(
+            f,
+            "chunks: {} loaded, {} meshed, {} rendered, {} queued",
+            self.chunks.loaded, self.chunks.meshed, self.chunks.rendered, self.chunks.queued
+        )?;
+        writeln!(
+            f,
+            "lod meshes: {} built, {} rendered",
+            self.chunks.lods, self.chunks.lods_rendered
+        )?;
+        writeln!(f, "triangles: {}", self.chunks.triangles)?;
+        writeln!(
+            f,
+            "chunk memory: {:.1} MiB blocks, {:.1} MiB meshes",
+            mib(self.chunks.block_bytes),
+            mib(self.chunks.mesh_bytes)
+        )?;
+        writeln!(f, "gpu: {} ({:?})", self.adapter.name, self.adapter.backend)?;
+
+        write!(
+            f,
+            "profile (cpu timing{}):",
+            if self.recording { ", recording" } else { "" }
+        )?;
+        for scope in &self.scopes {
+            write!(f, "\n  {}: {:.2} ms", scope.name, ms(scope.total))?;
+            if scope.calls > 1 {
+                write!(f, " ({}x)", scope.calls)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+// The horizontal direction the camera faces, with -z as north
+fn compass(direction: Vector3<f32>) -> &'static str {
+    if direction.x.abs() > direction.z.abs() {
+        if direction.x > 0.0 {
+            "east (+x)"
+        } else {
+            "west (-x)"
+        }
+    } else if direction.z > 0.0 {
+        "south (+z)"
+    } else {
+        "north (-z)"
+    }
+}
@@ -0,0 +1,620 @@
+use cgmath::Vector3;
+use std::collections::VecDeque;
+
+use crate::render::{
+    block::Block,
+    chunk::{block_to_chunk, ChunkManager},
+};
+
+// Largest region a single edit may cover
+pub const MAX_EDIT_VOLUME: i64 = 32 * 32 * 32 * 4;
+// Memory the undo and redo history may use before the oldest edits are forgotten
+const HISTORY_BUDGET: usize = 32 * 1024 * 1024;
+
+/// A box of blocks between two corners, both included
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub min: Vector3<i32>,
+    pub max: Vector3<i32>,
+}
+
+impl Region {
+    pub fn new(a: Vector3<i32>, b: Vector3<i32>) -> Self {
+        Self {
+            min: Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// Blocks along each axis
+    pub fn size(&self) -> Vector3<i32> {
+        self.max - self.min + Vector3::new(1, 1, 1)
+    }
+
+    pub fn volume(&self) -> i64 {
+        (0..3)
+            .map(|axis| self.max[axis] as i64 - self.min[axis] as i64 + 1)
+            .product()
+    }
+
+    /// Every position in the region, x slowest and z fastest
+    pub fn positions(self) -> impl Iterator<Item = Vector3<i32>> {
+        (self.min.x..=self.max.x).flat_map(move |x| {
+            (self.min.y..=self.max.y)
+                .flat_map(move |y| (self.min.z..=self.max.z).map(move |z| Vector3::new(x, y, z)))
+        })
+    }
+
+    fn on_edge(&self, position: Vector3<i32>) -> bool {
+        (0..3).any(|axis| position[axis] == self.min[axis] || position[axis] == self.max[axis])
+    }
+
+    // How far a block's center is from the region's center along each axis,
+    // scaled so the faces of the region are at 1
+    fn normalized(&self, position: Vector3<i32>) -> Vector3<f32> {
+        let center = (self.min + self.max).map(|v| v as f32) / 2.0;
+        let radius = self.size().map(|v| v as f32) / 2.0;
+        let offset = position.map(|v| v as f32) - center;
+        Vector3::new(
+            offset.x / radius.x,
+            offset.y / radius.y,
+            offset.z / radius.z,
+        )
+    }
+}
+
+/// The two corners picked with the selection tool or `/pos1` and `/pos2`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Selection {
+    pub first: Option<Vector3<i32>>,
+    pub second: Option<Vector3<i32>>,
+}
+
+impl Selection {
+    pub fn region(&self) -> Option<Region> {
+        Some(Region::new(self.first?, self.second?))
+    }
+
+    /// The region, or just the corner picked so far, to outline in the world
+    pub fn bounds(&self) -> Option<Region> {
+        self.region().or_else(|| {
+            let corner = self.first.or(self.second)?;
+            Some(Region::new(corner, corner))
+        })
+    }
+}
+
+/// Region editing state that lasts between commands
+#[derive(Default)]
+pub struct Editor {
+    pub selection: Selection,
+    pub clipboard: Option<Clipboard>,
+    // While the selection tool is out, clicks pick corners instead of
+    // breaking and placing blocks
+    pub tool: bool,
+    pub history: History,
+}
+
+/// Block changes applied together, so that each chunk they touch is only
+/// remeshed once however many of its blocks change
+#[derive(Clone, Default)]
+pub struct BlockEdit {
+    changes: Vec<(Vector3<i32>, Option<Block>)>,
+}
+
+impl BlockEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts `block` at `position`, or clears it with None
+    pub fn set(&mut self, position: Vector3<i32>, block: Option<Block>) {
+        self.changes.push((position, block));
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item = (Vector3<i32>, Option<Block>)> + '_ {
+        self.changes.iter().copied()
+    }
+}
+
+/// Why an edit couldn't be undone or redone
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HistoryError {
+    // There's nothing to undo or redo
+    Empty,
+    // Some of the changed blocks are in chunks that aren't loaded, so only
+    // part of the edit could be applied
+    Unloaded,
+    // Undoing in survival would hand out or take back blocks without going
+    // through the inventory
+    Survival,
+}
+
+/// What applying an edit did
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EditSummary {
+    pub changed: usize,
+    // Positions skipped because their chunk isn't loaded
+    pub unloaded: usize,
+}
+
+/// A block that was changed, with what it was before and after
+#[derive(Copy, Clone)]
+pub struct BlockChange {
+    pub position: Vector3<i32>,
+    pub old: Option<Block>,
+    pub new: Option<Block>,
+}
+
+/// The blocks an edit actually changed, in the order they changed, so that it
+/// can be undone and redone
+#[derive(Clone, Default)]
+pub struct ChangeSet {
+    changes: Vec<BlockChange>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, change: BlockChange) {
+        self.changes.push(change);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Memory used by the recorded changes
+    pub fn bytes(&self) -> usize {
+        self.changes.capacity() * std::mem::size_of::<BlockChange>()
+    }
+
+    /// An edit putting back the old blocks, latest change first
+    pub fn reverted(&self) -> BlockEdit {
+        let mut edit = BlockEdit::new();
+        for change in self.changes.iter().rev() {
+            edit.set(change.position, change.old);
+        }
+        edit
+    }
+
+    /// An edit making the same changes again
+    pub fn reapplied(&self) -> BlockEdit {
+        let mut edit = BlockEdit::new();
+        for change in &self.changes {
+            edit.set(change.position, change.new);
+        }
+        edit
+    }
+
+    // Whether every changed block's chunk is loaded
+    fn loaded(&self, chunks: &ChunkManager) -> bool {
+        self.changes.iter().all(|change| {
+            chunks
+                .get_chunk(&block_to_chunk(change.position).0)
+                .is_some()
+        })
+    }
+}
+
+/// Edits that can be undone, and undone edits that can be redone. The oldest
+/// edits are forgotten once the history outgrows its memory budget.
+pub struct History {
+    undo: VecDeque<ChangeSet>,
+    redo: Vec<ChangeSet>,
+    bytes: usize,
+    budget: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(HISTORY_BUDGET)
+    }
+}
+
+impl History {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            bytes: 0,
+            budget,
+        }
+    }
+
+    /// Adds an edit that was just made, which replaces anything there was to redo
+    pub fn record(&mut self, mut changes: ChangeSet) {
+        if changes.is_empty() {
+            return;
+        }
+        changes.changes.shrink_to_fit();
+        for undone in self.redo.drain(..) {
+            self.bytes -= undone.bytes();
+        }
+        self.bytes += changes.bytes();
+        self.undo.push_back(changes);
+
+        while self.bytes > self.budget {
+            match self.undo.pop_front() {
+                Some(oldest) => self.bytes -= oldest.bytes(),
+                None => break,
+            }
+        }
+    }
+
+    /// Reverts the latest edit. An edit reaching into unloaded chunks is left
+    /// as it is, to be undone once they're loaded again.
+    pub fn undo(&mut self, chunks: &mut ChunkManager) -> Result<EditSummary, HistoryError> {
+        let changes = self.undo.back().ok_or(HistoryError::Empty)?;
+        if !changes.loaded(chunks) {
+            return Err(HistoryError::Unloaded);
+        }
+        let (summary, _) = chunks.apply_edit(&changes.reverted());
+        self.redo.extend(self.undo.pop_back());
+        Ok(summary)
+    }
+
+    /// Makes the latest undone edit again, as long as its chunks are loaded
+    pub fn redo(&mut self, chunks: &mut ChunkManager) -> Result<EditSummary, HistoryError> {
+        let changes = self.redo.last().ok_or(HistoryError::Empty)?;
+        if !changes.loaded(chunks) {
+            return Err(HistoryError::Unloaded);
+        }
+        let (summary, _) = chunks.apply_edit(&changes.reapplied());
+        self.undo.extend(self.redo.pop());
+        Ok(summary)
+    }
+}
+
+pub fn fill(region: Region, block: Option<Block>) -> BlockEdit {
+    let mut edit = BlockEdit::new();
+    for position in region.positions() {
+        edit.set(position, block);
+    }
+    edit
+}
+
+/// Changes blocks of type `from` to `to`, where None matches or leaves air
+pub fn replace(
+    chunks: &ChunkManager,
+    region: Region,
+    from: Option<usize>,
+    to: Option<Block>,
+) -> BlockEdit {
+    let mut edit = BlockEdit::new();
+    for position in region.positions() {
+        if chunks.block(position).map(|block| block.id()) == from {
+            edit.set(position, to);
+        }
+    }
+    edit
+}
+
+/// Walls, floor and ceiling around the edge of the region, emptying the inside
+pub fn hollow(region: Region, block: Option<Block>) -> BlockEdit {
+    let mut edit = BlockEdit::new();
+    for position in region.positions() {
+        edit.set(
+            position,
+            if region.on_edge(position) {
+                block
+            } else {
+                None
+            },
+        );
+    }
+    edit
+}
+
+/// The largest sphere, or ellipsoid, that fits in the region
+pub fn sphere(region: Region, block: Option<Block>) -> BlockEdit {
+    let mut edit = BlockEdit::new();
+    for position in region.positions() {
+        let offset = region.normalized(position);
+        if offset.x * offset.x + offset.y * offset.y + offset.z * offset.z <= 1.0 {
+            edit.set(position, block);
+        }
+    }
+    edit
+}
+
+/// The largest upright cylinder that fits in the region
+pub fn cylinder(region: Region, block: Option<Block>) -> BlockEdit {
+    let mut edit = BlockEdit::new();
+    for position in region.positions() {
+        let offset = region.normalized(position);
+        if offset.x * offset.x + offset.z * offset.z <= 1.0 {
+            edit.set(position, block);
+        }
+    }
+    edit
+}
+
+/// Blocks copied out of a region, ordered like `Region::positions` starting
+/// from its lowest corner
+#[derive(Clone, Debug)]
+pub struct Clipboard {
+    size: Vector3<i32>,
+    blocks: Vec<Option<usize>>,
+}
+
+impl Clipboard {
+    /// `blocks` holds a block id or None for air at each position
+    pub fn new(size: Vector3<i32>, blocks: Vec<Option<usize>>) -> Self {
+        debug_assert_eq!(
+            blocks.len(),
+            Region::new(Vector3::new(0, 0, 0), size - Vector3::new(1, 1, 1)).volume() as usize
+        );
+        Self { size, blocks }
+    }
+
+    pub fn copy(chunks: &ChunkManager, region: Region) -> Self {
+        Self {
+            size: region.size(),
+            blocks: region
+                .positions()
+                .map(|position| chunks.block(position).map(|block| block.id()))
+                .collect(),
+        }
+    }
+
+    pub fn size(&self) -> Vector3<i32> {
+        self.size
+    }
+
+    pub fn blocks(&self) -> &[Option<usize>] {
+        &self.blocks
+    }
+
+    fn index(&self, position: Vector3<i32>) -> usize {
+        ((position.x * self.size.y + position.y) * self.size.z + position.z) as usize
+    }
+
+    // A clipboard of `size` whose blocks are read from this one through `source`,
+    // which maps positions in the new clipboard to positions in this one
+    fn remap(&self, size: Vector3<i32>, source: impl Fn(Vector3<i32>) -> Vector3<i32>) -> Self {
+        let region = Region::new(Vector3::new(0, 0, 0), size - Vector3::new(1, 1, 1));
+        Self {
+            size,
+            blocks: region
+                .positions()
+                .map(|position| self.blocks[self.index(source(position))])
+                .collect(),
+        }
+    }
+
+    /// Turns the contents a quarter turn clockwise, seen from above, `turns` times
+    pub fn rotate(&self, turns: u32) -> Self {
+        let mut rotated = self.clone();
+        for _ in 0..turns % 4 {
+            let size = Vector3::new(rotated.size.z, rotated.size.y, rotated.size.x);
+            let last_z = rotated.size.z - 1;
+            rotated = rotated.remap(size, |p| Vector3::new(p.z, p.y, last_z - p.x));
+        }
+        rotated
+    }
+
+    /// Flips the contents along an axis, 0 for x, 1 for y and 2 for z
+    pub fn mirror(&self, axis: usize) -> Self {
+        let last = self.size[axis] - 1;
+        self.remap(self.size, |mut p| {
+            p[axis] = last - p[axis];
+            p
+        })
+    }
+
+    /// Places the contents with their lowest corner at `origin`
+    pub fn paste(&self, origin: Vector3<i32>) -> BlockEdit {
+        let region = Region::new(origin, origin + self.size - Vector3::new(1, 1, 1));
+        let mut edit = BlockEdit::new();
+        for (position, block) in region.positions().zip(&self.blocks) {
+            edit.set(position, block.map(Block::new));
+        }
+        edit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{DIRT, STONE};
+    use crate::render::chunk::Chunk;
+    use std::collections::HashMap;
+
+    fn region(size: [i32; 3]) -> Region {
+        Region::new(
+            Vector3::new(-3, 10, 4),
+            Vector3::new(-3 + size[0] - 1, 10 + size[1] - 1, 4 + size[2] - 1),
+        )
+    }
+
+    fn solid(edit: &BlockEdit) -> usize {
+        edit.changes().filter(|(_, block)| block.is_some()).count()
+    }
+
+    // Every block different, so any misplaced one shows
+    fn numbered(size: Vector3<i32>) -> Clipboard {
+        let volume = (size.x * size.y * size.z) as usize;
+        Clipboard::new(size, (0..volume).map(Some).collect())
+    }
+
+    #[test]
+    fn four_quarter_turns_are_the_identity() {
+        let clipboard = numbered(Vector3::new(2, 3, 5));
+        let mut rotated = clipboard.clone();
+        for turn in 1..=4 {
+            rotated = rotated.rotate(1);
+            if turn % 2 == 1 {
+                assert_eq!(rotated.size(), Vector3::new(5, 3, 2));
+            }
+            if turn < 4 {
+                assert_ne!(rotated.blocks(), clipboard.blocks());
+            }
+        }
+        assert_eq!(rotated.size(), clipboard.size());
+        assert_eq!(rotated.blocks(), clipboard.blocks());
+        assert_eq!(
+            clipboard.rotate(3).blocks(),
+            clipboard.rotate(1).rotate(1).rotate(1).blocks()
+        );
+    }
+
+    #[test]
+    fn rotates_clockwise_seen_from_above() {
+        // x runs east and z south, so the block at the east end goes south
+        let clipboard = Clipboard::new(Vector3::new(2, 1, 1), vec![None, Some(STONE)]);
+        let rotated = clipboard.rotate(1);
+        assert_eq!(rotated.size(), Vector3::new(1, 1, 2));
+        assert_eq!(rotated.blocks(), &[None, Some(STONE)]);
+    }
+
+    #[test]
+    fn mirroring_twice_is_the_identity() {
+        let clipboard = numbered(Vector3::new(3, 4, 2));
+        for axis in 0..3 {
+            let mirrored = clipboard.mirror(axis);
+            assert_ne!(mirrored.blocks(), clipboard.blocks());
+            assert_eq!(mirrored.mirror(axis).blocks(), clipboard.blocks());
+        }
+    }
+
+    #[test]
+    fn shapes_fill_the_expected_blocks() {
+        let stone = Some(Block::new(STONE));
+        assert_eq!(solid(&fill(region([2, 3, 4]), stone)), 24);
+        assert_eq!(solid(&sphere(region([3, 3, 3]), stone)), 19);
+        assert_eq!(solid(&sphere(region([5, 5, 5]), stone)), 81);
+        assert_eq!(solid(&sphere(region([1, 1, 1]), stone)), 1);
+        assert_eq!(solid(&cylinder(region([3, 4, 3]), stone)), 36);
+        assert_eq!(solid(&cylinder(region([5, 2, 5]), stone)), 42);
+
+        // Hollow shapes clear the inside as well
+        let hollow = hollow(region([4, 4, 4]), stone);
+        assert_eq!(solid(&hollow), 64 - 8);
+        assert_eq!(hollow.changes().count(), 64);
+        assert_eq!(solid(&self::hollow(region([2, 5, 2]), stone)), 20);
+    }
+
+    // The chunk around the origin, loaded and empty
+    fn chunks() -> ChunkManager {
+        let position = Vector3::new(0, 0, 0);
+        let mut chunks = HashMap::new();
+        chunks.insert(position, Chunk::new(0, position));
+        ChunkManager::new(chunks)
+    }
+
+    fn ids(chunks: &ChunkManager, region: Region) -> Vec<Option<usize>> {
+        region
+            .positions()
+            .map(|position| chunks.block(position).map(|block| block.id()))
+            .collect()
+    }
+
+    fn apply(history: &mut History, chunks: &mut ChunkManager, edit: &BlockEdit) {
+        let (_, changes) = chunks.apply_edit(edit);
+        history.record(changes);
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let mut chunks = chunks();
+        let mut history = History::default();
+        let region = Region::new(Vector3::new(-2, 0, -2), Vector3::new(2, 4, 2));
+        let empty = ids(&chunks, region);
+
+        apply(
+            &mut history,
+            &mut chunks,
+            &fill(region, Some(Block::new(STONE))),
+        );
+        let filled = ids(&chunks, region);
+        apply(
+            &mut history,
+            &mut chunks,
+            &hollow(region, Some(Block::new(DIRT))),
+        );
+        let hollowed = ids(&chunks, region);
+
+        assert_eq!(history.undo(&mut chunks).unwrap().changed, 125);
+        assert_eq!(ids(&chunks, region), filled);
+        assert_eq!(history.undo(&mut chunks).unwrap().changed, 125);
+        assert_eq!(ids(&chunks, region), empty);
+        assert_eq!(history.undo(&mut chunks).unwrap_err(), HistoryError::Empty);
+
+        history.redo(&mut chunks).unwrap();
+        history.redo(&mut chunks).unwrap();
+        assert_eq!(ids(&chunks, region), hollowed);
+        assert_eq!(history.redo(&mut chunks).unwrap_err(), HistoryError::Empty);
+
+        // A new edit drops what there was to redo
+        history.undo(&mut chunks).unwrap();
+        apply(&mut history, &mut chunks, &fill(region, None));
+        assert_eq!(history.redo(&mut chunks).unwrap_err(), HistoryError::Empty);
+    }
+
+    #[test]
+    fn unchanged_blocks_arent_recorded() {
+        let mut chunks = chunks();
+        let mut history = History::default();
+        let region = Region::new(Vector3::new(0, 0, 0), Vector3::new(1, 1, 1));
+        apply(&mut history, &mut chunks, &fill(region, None));
+        assert_eq!(history.undo(&mut chunks).unwrap_err(), HistoryError::Empty);
+    }
+
+    #[test]
+    fn oldest_edits_are_evicted_past_the_budget() {
+        let mut chunks = chunks();
+        let single = |x| {
+            fill(
+                Region::new(Vector3::new(x, 0, 0), Vector3::new(x, 0, 0)),
+                Some(Block::new(STONE)),
+            )
+        };
+        // Room for three single block edits
+        let mut history = History::new(3 * std::mem::size_of::<BlockChange>());
+        for x in 0..5 {
+            apply(&mut history, &mut chunks, &single(x));
+        }
+        for _ in 0..3 {
+            history.undo(&mut chunks).unwrap();
+        }
+        assert_eq!(history.undo(&mut chunks).unwrap_err(), HistoryError::Empty);
+        let placed: Vec<bool> = (0..5)
+            .map(|x| chunks.block(Vector3::new(x, 0, 0)).is_some())
+            .collect();
+        assert_eq!(placed, vec![true, true, false, false, false]);
+    }
+
+    #[test]
+    fn refuses_edits_in_unloaded_chunks() {
+        let mut chunks = chunks();
+        let mut history = History::default();
+        let position = Vector3::new(0, 0, 0);
+        apply(
+            &mut history,
+            &mut chunks,
+            &fill(Region::new(position, position), Some(Block::new(STONE))),
+        );
+
+        let chunk = chunks.chunks.remove(&position).unwrap();
+        assert_eq!(
+            history.undo(&mut chunks).unwrap_err(),
+            HistoryError::Unloaded
+        );
+        // Still there to undo once the chunk is back
+        chunks.chunks.insert(position, chunk);
+        history.undo(&mut chunks).unwrap();
+        assert!(chunks.block(position).is_none());
+
+        let chunk = chunks.chunks.remove(&position).unwrap();
+        assert_eq!(
+            history.redo(&mut chunks).unwrap_err(),
+            HistoryError::Unloaded
+        );
+        chunks.chunks.insert(position, chunk);
+        history.redo(&mut chunks).unwrap();
+        assert!(chunks.block(position).is_some());
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while running the game
+#[derive(Debug)]
+pub enum Error {
+    /// No GPU adapter matched the requested options
+    NoAdapter,
+    /// The shaderc compiler could not be initialized
+    #[cfg(feature = "dev")]
+    ShaderCompiler,
+    /// A shader failed to compile, with the compiler's output
+    #[cfg(feature = "dev")]
+    ShaderCompile { name: String, message: String },
+    /// A shader with no SPIR-V embedded at build time
+    Shader(String),
+    /// The UI font couldn't be parsed
+    Font(wgpu_glyph::ab_glyph::InvalidFont),
+    /// The window couldn't be created
+    Window(winit::error::OsError),
+    /// A buffer copied back from the GPU couldn't be mapped
+    Readback,
+    /// The image at a path couldn't be loaded or saved
+    Image(PathBuf, image::ImageError),
+    /// Reading or writing the file at a path failed
+    Io(PathBuf, std::io::Error),
+    /// A headless render that doesn't match the golden image it was checked against
+    GoldenMismatch {
+        path: std::path::PathBuf,
+        reason: String,
+    },
+    /// Settings couldn't be serialized
+    Toml(toml::ser::Error),
+    /// Settings couldn't be parsed
+    TomlParse(toml::de::Error),
+    /// A world already has as many block types as saved chunks can store
+    PaletteFull,
+    /// A saved chunk that couldn't be decoded
+    CorruptChunk(String),
+    /// A schematic that couldn't be read
+    CorruptSchematic(String),
+    /// A MagicaVoxel file that couldn't be read
+    CorruptVox(String),
+    /// A file to export to whose extension isn't a format we can write
+    ExportFormat(std::path::PathBuf),
+    /// A region to export with no blocks in it
+    EmptyExport,
+    /// A region to export with more blocks than an edit can change
+    ExportTooLarge(i64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoAdapter => write!(f, "no compatible graphics adapter was found"),
+            #[cfg(feature = "dev")]
+            Error::ShaderCompiler => write!(f, "failed to initialize the shader compiler"),
+            #[cfg(feature = "dev")]
+            Error::ShaderCompile { name, message } => {
+                write!(f, "failed to compile shader {}:\n{}", name, message)
+            }
+            Error::Shader(name) => write!(f, "no embedded shader named {}", name),
+            Error::Font(e) => write!(f, "failed to load font: {}", e),
+            Error::Window(e) => write!(f, "failed to create window: {}", e),
+            Error::Readback => write!(f, "failed to read back a texture from the GPU"),
+            Error::Image(path, e) => write!(f, "image error in {:?}: {}", path, e),
+            Error::Io(path, e) => write!(f, "{:?}: {}", path, e),
+            Error::GoldenMismatch { path, reason } => {
+                write!(f, "render doesn't match {:?}: {}", path, reason)
+            }
+            Error::Toml(e) => write!(f, "failed to serialize: {}", e),
+            Error::TomlParse(e) => write!(f, "failed to parse: {}", e),
+            Error::PaletteFull => write!(
+                f,
+                "can't add a block type, worlds are limited to {}",
+                crate::render::block::MAX_BLOCK_TYPES
+            ),
+            Error::CorruptChunk(reason) => write!(f, "corrupt chunk: {}", reason),
+            Error::CorruptSchematic(reason) => write!(f, "bad schematic: {}", reason),
+            Error::CorruptVox(reason) => write!(f, "bad .vox file: {}", reason),
+            Error::ExportFormat(path) => {
+                write!(
+                    f,
+                    "can't export to {:?}, expected an .obj or .glb file",
+                    path
+                )
+            }
+            Error::EmptyExport => write!(f, "nothing to export, the region has no blocks"),
+            Error::ExportTooLarge(volume) => write!(
+                f,
+                "can't export {} blocks, exports are limited to {}",
+                volume,
+                crate::edit::MAX_EDIT_VOLUME
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// For `map_err`, an I/O error on the file at `path`
+    pub fn io(path: &Path) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.to_path_buf();
+        move |e| Error::Io(path, e)
+    }
+
+    /// For `map_err`, an error loading or saving the image at `path`
+    pub fn image(path: &Path) -> impl FnOnce(image::ImageError) -> Self {
+        let path = path.to_path_buf();
+        move |e| Error::Image(path, e)
+    }
+}
+
+impl From<wgpu_glyph::ab_glyph::InvalidFont> for Error {
+    fn from(e: wgpu_glyph::ab_glyph::InvalidFont) -> Self {
+        Error::Font(e)
+    }
+}
+
+impl From<winit::error::OsError> for Error {
+    fn from(e: winit::error::OsError) -> Self {
+        Error::Window(e)
+    }
+}
+
+impl From<wgpu::BufferAsyncErr> for Error {
+    fn from(_: wgpu::BufferAsyncErr) -> Self {
+        Error::Readback
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::Toml(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::TomlParse(e)
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::edit::{Region, MAX_EDIT_VOLUME};
+use crate::error::{Error, Result};
+use crate::render::{
+    block::Palette,
+    chunk::{block_to_chunk, Chunk, ChunkManager, ChunkPosition},
+    mesh::{mesh_chunk, MeshData, Neighbors},
+};
+use crate::save::WorldSave;
+use crate::terrain::Generator;
+
+// glTF constants for the accessors and buffer views
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// What a region can be exported as
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    // Wavefront OBJ, with an MTL file next to it
+    Obj,
+    // Binary glTF
+    Glb,
+}
+
+impl Format {
+    pub const EXTENSIONS: &'static [&'static str] = &["obj", "glb"];
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "obj" => Some(Format::Obj),
+            "glb" => Some(Format::Glb),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+}
+
+/// Meshes the blocks in `region` the same way chunks are meshed for drawing,
+/// merged into one mesh in world space. Blocks outside the region are left
+/// out, so its sides are closed, and faces between chunks are hidden.
+pub fn mesh_region(chunks: &ChunkManager, palette: &Palette, region: Region) -> MeshData {
+    let mut parts: HashMap<ChunkPosition, Chunk> = HashMap::new();
+    for position in region.positions() {
+        if let Some(block) = chunks.block(position) {
+            let (chunk, local) = block_to_chunk(position);
+            parts
+                .entry(chunk)
+                .or_insert_with(|| Chunk::new(0, chunk))
+                .set_block(local, Some(block));
+        }
+    }
+
+    // Sorted so the same region always gives the same file
+    let mut positions: Vec<ChunkPosition> = parts.keys().copied().collect();
+    positions.sort_by_key(|position| (position.x, position.y, position.z));
+    let mut mesh = MeshData::default();
+    for position in positions {
+        let neighbors = Neighbors::find(position, |position| parts.get(position));
+        mesh.append(mesh_chunk(&parts[&position], &neighbors, palette));
+    }
+    mesh
+}
+
+/// Writes `mesh` to `path`. Empty meshes are refused, as glTF can't describe
+/// bounds without any vertices.
+pub fn save(path: &Path, format: Format, mesh: &MeshData, palette: &Palette) -> Result<()> {
+    if mesh.faces.is_empty() {
+        return Err(Error::EmptyExport);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+    }
+    match format {
+        Format::Obj => {
+            let material_path = path.with_extension("mtl");
+            let material_file = material_path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            let (obj, mtl) = encode_obj(mesh, palette, &material_file);
+            std::fs::write(path, obj).map_err(Error::io(path))?;
+            std::fs::write(&material_path, mtl).map_err(Error::io(&material_path))?;
+        }
+        Format::Glb => std::fs::write(path, encode_glb(mesh, palette)).map_err(Error::io(path))?,
+    }
+    Ok(())
+}
+
+/// Loads the chunks covering `region` from the save, generating those that
+/// weren't stored, and writes the region to `path` in the format its
+/// extension names. Doesn't need a window or GPU. Regions are limited to the
+/// same number of blocks as edits.
+pub fn run(world: &WorldSave, region: Region, path: &Path) -> Result<()> {
+    let format = Format::from_path(path).ok_or_else(|| Error::ExportFormat(path.into()))?;
+    if region.volume() > MAX_EDIT_VOLUME {
+        return Err(Error::ExportTooLarge(region.volume()));
+    }
+    let generator = Generator::new(world.level.seed);
+    let palette = Palette::from_colors(&world.level.block_colors);
+    let (low, _) = block_to_chunk(region.min);
+    let (high, _) = block_to_chunk(region.max);
+    let mut chunks = ChunkManager::new(HashMap::new());
+    for x in low.x..=high.x {
+        for y in low.y..=high.y {
+            for z in low.z..=high.z {
+                let position = ChunkPosition::new(x, y, z);
+                chunks.add_chunk(
+                    world
+                        .load_chunk(position, &palette)
+                        .unwrap_or_else(|| generator.generate(position)),
+                );
+            }
+        }
+    }
+
+    let mesh = mesh_region(&chunks, &palette, region);
+    save(path, format, &mesh, &palette)?;
+    log::info!("Exported {} faces to {:?}", mesh.faces.len(), path);
+    Ok(())
+}
+
+// The quads of each block type, by id
+fn quads_by_block(mesh: &MeshData) -> BTreeMap<usize, Vec<usize>> {
+    let mut quads: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (quad, face) in mesh.faces.iter().enumerate() {
+        quads.entry(face.block).or_default().push(quad);
+    }
+    quads
+}
+
+// Block names without the spaces and symbols that material names can't have
+fn material_name(palette: &Palette, id: usize) -> String {
+    palette
+        .block_type(id)
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn quad_indices(mesh: &MeshData, quad: usize) -> &[u32] {
+    &mesh.indices[quad * 6..quad * 6 + 6]
+}
+
+/// The OBJ and MTL files for a mesh. Vertex colors follow the positions, which
+/// most tools read, and each block type also gets a material of its color.
+fn encode_obj(mesh: &MeshData, palette: &Palette, material_file: &str) -> (String, String) {
+    let mut obj = format!("mtllib {}\n", material_file);
+    for vertex in &mesh.vertices {
+        let [x, y, z] = vertex.position;
+        let [r, g, b] = vertex.color;
+        obj.push_str(&format!("v {} {} {} {} {} {}\n", x, y, z, r, g, b));
+    }
+    for vertex in &mesh.vertices {
+        let [x, y, z] = vertex.normal;
+        obj.push_str(&format!("vn {} {} {}\n", x, y, z));
+    }
+
+    let mut mtl = String::new();
+    for (id, quads) in quads_by_block(mesh) {
+        let name = material_name(palette, id);
+        let [r, g, b] = palette.block_type(id).color;
+        mtl.push_str(&format!("newmtl {}\nKd {} {} {}\n\n", name, r, g, b));
+        obj.push_str(&format!("usemtl {}\n", name));
+        for quad in quads {
+            for triangle in quad_indices(mesh, quad).chunks(3) {
+                // OBJ counts vertices from one
+                let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+                obj.push_str(&format!("f {0}//{0} {1}//{1} {2}//{2}\n", a, b, c));
+            }
+        }
+    }
+    (obj, mtl)
+}
+
+/// A binary glTF file with one mesh, split into a primitive per block type so
+/// each can have its own material. The primitives share the vertices and
+/// take their color from the material, as vertex colors would be multiplied
+/// with it.
+fn encode_glb(mesh: &MeshData, palette: &Palette) -> Vec<u8> {
+    let mut bin = Vec::new();
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in &mesh.vertices {
+        for axis in 0..3 {
+            bin.extend_from_slice(&vertex.position[axis].to_le_bytes());
+            min[axis] = min[axis].min(vertex.position[axis]);
+            max[axis] = max[axis].max(vertex.position[axis]);
+        }
+    }
+    for vertex in &mesh.vertices {
+        for value in &vertex.normal {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    let vertex_bytes = mesh.vertices.len() * 12;
+
+    let mut accessors = vec![
+        format!(
+            r#"{{"bufferView":0,"componentType":{},"count":{},"type":"VEC3","min":{},"max":{}}}"#,
+            GLTF_FLOAT,
+            mesh.vertices.len(),
+            json_floats(&min),
+            json_floats(&max)
+        ),
+        format!(
+            r#"{{"bufferView":1,"componentType":{},"count":{},"type":"VEC3"}}"#,
+            GLTF_FLOAT,
+            mesh.vertices.len()
+        ),
+    ];
+    let mut primitives = Vec::new();
+    let mut materials = Vec::new();
+    for (id, quads) in quads_by_block(mesh) {
+        let offset = bin.len() - vertex_bytes * 2;
+        for &quad in &quads {
+            for index in quad_indices(mesh, quad) {
+                bin.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        accessors.push(format!(
+            r#"{{"bufferView":2,"byteOffset":{},"componentType":{},"count":{},"type":"SCALAR"}}"#,
+            offset,
+            GLTF_UNSIGNED_INT,
+            quads.len() * 6
+        ));
+        primitives.push(format!(
+            r#"{{"attributes":{{"POSITION":0,"NORMAL":1}},"indices":{},"material":{}}}"#,
+            accessors.len() - 1,
+            materials.len()
+        ));
+        let [r, g, b] = palette.block_type(id).color;
+        materials.push(format!(
+            r#"{{"name":"{}","pbrMetallicRoughness":{{"baseColorFactor":{},"metallicFactor":0,"roughnessFactor":1}}}}"#,
+            material_name(palette, id),
+            json_floats(&[r, g, b, 1.0])
+        ));
+    }
+    let index_bytes = bin.len() - vertex_bytes * 2;
+
+    let buffer_views = [
+        (0, vertex_bytes, GLTF_ARRAY_BUFFER),
+        (vertex_bytes, vertex_bytes, GLTF_ARRAY_BUFFER),
+        (vertex_bytes * 2, index_bytes, GLTF_ELEMENT_ARRAY_BUFFER),
+    ]
+    .iter()
+    .map(|(offset, length, target)| {
+        format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset, length, target
+        )
+    })
+    .collect::<Vec<_>>();
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"constructors"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{}]}}],"materials":[{}],"accessors":[{}],"#,
+            r#""bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#
+        ),
+        primitives.join(","),
+        materials.join(","),
+        accessors.join(","),
+        buffer_views.join(","),
+        bin.len()
+    );
+
+    // Both chunks have to be padded to four bytes, JSON with spaces
+    while json.len() % 4 != 0 {
+        json.push(' ');
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut data = Vec::with_capacity(length);
+    data.extend_from_slice(b"glTF");
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&(length as u32).to_le_bytes());
+    data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    data.extend_from_slice(b"JSON");
+    data.extend_from_slice(json.as_bytes());
+    data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    data.extend_from_slice(b"BIN\0");
+    data.extend_from_slice(&bin);
+    data
+}
+
+fn json_floats(values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{Block, DIRT, STONE};
+    use cgmath::Vector3;
+
+    // A stone block at the corner of the chunk at the origin, with dirt on top
+    fn two_blocks() -> MeshData {
+        let mut chunks = ChunkManager::new(HashMap::new());
+        let mut chunk = Chunk::new(0, ChunkPosition::new(0, 0, 0));
+        chunk.set_block(Vector3::new(0, 0, 0), Some(Block::new(STONE)));
+        chunk.set_block(Vector3::new(0, 1, 0), Some(Block::new(DIRT)));
+        chunks.add_chunk(chunk);
+        let region = Region::new(Vector3::new(-8, 0, -8), Vector3::new(-7, 1, -7));
+        mesh_region(&chunks, &Palette::default(), region)
+    }
+
+    fn lines<'a>(text: &'a str, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        text.lines().filter(move |line| line.starts_with(prefix))
+    }
+
+    #[test]
+    fn obj_has_a_material_per_block_type() {
+        let mesh = two_blocks();
+        // The faces between the two blocks are hidden
+        assert_eq!(mesh.faces.len(), 10);
+        let (obj, mtl) = encode_obj(&mesh, &Palette::default(), "blocks.mtl");
+        assert!(obj.starts_with("mtllib blocks.mtl\n"));
+        assert_eq!(lines(&obj, "v ").count(), 40);
+        assert_eq!(lines(&obj, "vn ").count(), 40);
+        assert_eq!(lines(&obj, "f ").count(), 20);
+        assert_eq!(
+            lines(&obj, "usemtl ").collect::<Vec<_>>(),
+            ["usemtl Stone", "usemtl Dirt"]
+        );
+        assert_eq!(lines(&mtl, "newmtl ").count(), 2);
+        assert!(mtl.contains("newmtl Stone\nKd 0.5 0.5 0.52\n"));
+
+        // Indices count from one and stay within the vertices
+        for face in lines(&obj, "f ") {
+            for corner in face.split_whitespace().skip(1) {
+                let index: usize = corner.split("//").next().unwrap().parse().unwrap();
+                assert!((1..=40).contains(&index));
+            }
+        }
+    }
+
+    #[test]
+    fn glb_chunks_are_padded_and_sized() {
+        let mesh = two_blocks();
+        let data = encode_glb(&mesh, &Palette::default());
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]) as usize
+        };
+        assert_eq!(&data[..4], b"glTF");
+        assert_eq!(u32_at(4), 2);
+        assert_eq!(u32_at(8), data.len());
+
+        let json_length = u32_at(12);
+        assert_eq!(&data[16..20], b"JSON");
+        assert_eq!(json_length % 4, 0);
+        let json = std::str::from_utf8(&data[20..20 + json_length]).unwrap();
+        assert!(json.contains(r#""count":40,"type":"VEC3","min":[-8,0,-8],"max":[-7,2,-7]"#));
+        assert_eq!(json.matches(r#""material":"#).count(), 2);
+
+        // Positions and normals, then the indices of both primitives
+        let bin = 20 + json_length;
+        assert_eq!(&data[bin + 4..bin + 8], b"BIN\0");
+        assert_eq!(u32_at(bin), 40 * 12 * 2 + 60 * 4);
+        assert_eq!(bin + 8 + u32_at(bin), data.len());
+    }
+
+    #[test]
+    fn empty_meshes_are_refused() {
+        let path = Path::new("exports/never_written.glb");
+        let result = save(path, Format::Glb, &MeshData::default(), &Palette::default());
+        assert!(matches!(result, Err(Error::EmptyExport)));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,234 @@
+use cgmath::{Deg, Point3};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+
+use crate::error::{Error, Result};
+use crate::render::{
+    camera::{Camera, Projection},
+    graphics::{Graphics, Render},
+    target::OffscreenTarget,
+};
+use crate::{player::Player, save::WorldSave, settings::Settings, world::World};
+
+// How far a channel can be off before the pixel counts as changed, as
+// adapters don't all rasterize and blend exactly alike
+const CHANNEL_TOLERANCE: u8 = 8;
+// Fraction of pixels that can change before a golden image check fails
+const CHANGED_PIXEL_TOLERANCE: f64 = 0.001;
+
+/// Renders a single frame of the world without a window and saves it as a PNG.
+/// With `golden`, the frame is then checked against that image.
+pub async fn run(
+    settings: Settings,
+    save: WorldSave,
+    spawn: Option<Point3<f32>>,
+    output: &Path,
+    golden: Option<&Path>,
+) -> Result<()> {
+    let (graphics, mut world) = load(&settings, save, spawn).await?;
+
+    let target = OffscreenTarget::new(
+        &graphics.device,
+        settings.window_width,
+        settings.window_height,
+        graphics.format,
+    );
+    world.render_offscreen(&graphics, &target);
+
+    let image = target.read_image(&graphics)?;
+    image.save(output).map_err(Error::image(output))?;
+    log::info!("Wrote {:?}", output);
+
+    if let Some(golden) = golden {
+        let expected = image::open(golden)
+            .map_err(Error::image(golden))?
+            .into_rgba8();
+        check_golden(&image, &expected).map_err(|reason| Error::GoldenMismatch {
+            path: golden.into(),
+            reason,
+        })?;
+        log::info!("Matches {:?}", golden);
+    }
+
+    Ok(())
+}
+
+/// Fails if `image` differs from the golden image in size, or in more pixels
+/// than the tolerance allows
+fn check_golden(image: &RgbaImage, expected: &RgbaImage) -> std::result::Result<(), String> {
+    if image.dimensions() != expected.dimensions() {
+        return Err(format!(
+            "rendered {}x{} but the image is {}x{}",
+            image.width(),
+            image.height(),
+            expected.width(),
+            expected.height()
+        ));
+    }
+    let changed = image
+        .pixels()
+        .zip(expected.pixels())
+        .filter(|(a, b)| {
+            a.0.iter()
+                .zip(b.0.iter())
+                .any(|(a, b)| a.max(b) - a.min(b) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let total = image.width() as usize * image.height() as usize;
+    if changed as f64 > total as f64 * CHANGED_PIXEL_TOLERANCE {
+        return Err(format!("{} of {} pixels changed", changed, total));
+    }
+    Ok(())
+}
+
+/// Renders `frames` frames offscreen while turning the camera a full circle,
+/// waiting for the GPU after each one, and logs how long they took
+pub async fn benchmark(
+    settings: Settings,
+    save: WorldSave,
+    spawn: Option<Point3<f32>>,
+    frames: u32,
+) -> Result<()> {
+    let (graphics, mut world) = load(&settings, save, spawn).await?;
+
+    let target = OffscreenTarget::new(
+        &graphics.device,
+        settings.window_width,
+        settings.window_height,
+        graphics.format,
+    );
+
+    let dt = Duration::from_secs(1) / 60;
+    let turn = Deg(360.0 / frames.max(1) as f32);
+    let mut times = Vec::with_capacity(frames as usize);
+    for _ in 0..frames {
+        let start = Instant::now();
+        world.camera_mut().yaw += turn.into();
+        world.update(dt, &graphics);
+        world.render_offscreen(&graphics, &target);
+        graphics.device.poll(wgpu::Maintain::Wait);
+        times.push(start.elapsed());
+    }
+
+    if times.is_empty() {
+        return Ok(());
+    }
+    let total: Duration = times.iter().sum();
+    let average = total / times.len() as u32;
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+    let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+    log::info!(
+        "{} frames at {}x{}: avg {:.2} ms ({:.0} fps), min {:.2} ms, max {:.2} ms",
+        times.len(),
+        settings.window_width,
+        settings.window_height,
+        ms(average),
+        1000.0 / ms(average),
+        ms(min),
+        ms(max),
+    );
+
+    Ok(())
+}
+
+async fn load(
+    settings: &Settings,
+    save: WorldSave,
+    spawn: Option<Point3<f32>>,
+) -> Result<(Graphics, World)> {
+    log::info!("Initializing headless Graphics");
+    let graphics = Graphics::headless(settings.window_width, settings.window_height).await?;
+
+    let camera = Camera::new(
+        spawn.unwrap_or_else(|| save.spawn()),
+        Deg(-90.0),
+        Deg(-10.0),
+    );
+    let projection = Projection::new(
+        settings.window_width,
+        settings.window_height,
+        Deg(settings.fov),
+        settings.znear(),
+        settings.zfar(),
+    );
+    let player = Player::new(camera, settings.movement_speed, settings.mouse_sensitivity);
+
+    log::info!("Initializing world");
+    let mut world = World::new(player, projection, settings, save, &graphics)?;
+    world.finish_loading(&graphics);
+
+    Ok((graphics, world))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use image::Rgba;
+
+    // Rendered from GOLDEN_SPAWN in a new world with GOLDEN_SEED. Set
+    // UPDATE_GOLDEN to write a new one after an intended rendering change.
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/headless.png");
+    const GOLDEN_SEED: u64 = 42;
+    const GOLDEN_SPAWN: [f32; 3] = [0.0, 40.0, 0.0];
+
+    fn image(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(40, 30, Rgba(color))
+    }
+
+    #[test]
+    fn golden_allows_small_differences() {
+        let expected = image([100, 150, 200, 255]);
+        let mut rendered = image([104, 146, 200, 255]);
+        // One pixel of 1200 is well off, within the changed pixel tolerance
+        rendered.put_pixel(3, 4, Rgba([0, 0, 0, 255]));
+        assert!(check_golden(&rendered, &expected).is_ok());
+    }
+
+    #[test]
+    fn golden_rejects_changed_images() {
+        let expected = image([100, 150, 200, 255]);
+        let mut rendered = expected.clone();
+        for x in 0..10 {
+            rendered.put_pixel(x, 0, Rgba([255, 150, 200, 255]));
+        }
+        assert!(check_golden(&rendered, &expected).is_err());
+        assert!(check_golden(&image([100, 150, 220, 255]), &expected).is_err());
+    }
+
+    #[test]
+    fn golden_rejects_other_sizes() {
+        let expected = image([0, 0, 0, 255]);
+        let rendered = RgbaImage::from_pixel(30, 40, Rgba([0, 0, 0, 255]));
+        assert!(check_golden(&rendered, &expected).is_err());
+    }
+
+    #[test]
+    fn renders_the_golden_image() {
+        let settings = Settings {
+            window_width: 320,
+            window_height: 180,
+            ..Settings::default()
+        };
+        // Never saved, so the directory isn't created
+        let dir = std::env::temp_dir().join("constructors-golden-world");
+        let save = WorldSave::open(&dir, Some(GOLDEN_SEED)).unwrap();
+        let spawn = Some(GOLDEN_SPAWN.into());
+        let golden = Path::new(GOLDEN);
+
+        let result = if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            block_on(run(settings, save, spawn, golden, None))
+        } else {
+            let output = std::env::temp_dir().join("constructors-golden.png");
+            block_on(run(settings, save, spawn, &output, Some(golden)))
+        };
+        match result {
+            Err(Error::NoAdapter) => eprintln!("Skipping, no graphics adapter"),
+            result => result
+                .unwrap_or_else(|e| panic!("{}, set UPDATE_GOLDEN if the change is intended", e)),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use winit::event::{ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+pub const HOTBAR_SLOTS: usize = 9;
+
+/// Which of the first `HOTBAR_SLOTS` inventory slots is used for placing
+pub struct Hotbar {
+    pub selected: usize,
+}
+
+impl Hotbar {
+    pub fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        if slot < HOTBAR_SLOTS {
+            self.selected = slot;
+        }
+    }
+
+    /// Moves the selection by `steps` slots, wrapping around at either end
+    pub fn scroll(&mut self, steps: i32) {
+        let slots = HOTBAR_SLOTS as i32;
+        self.selected = (self.selected as i32 + steps).rem_euclid(slots) as usize;
+    }
+
+    /// Selects slots with the number keys and scroll wheel
+    pub fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match number_key(*key) {
+                Some(slot) => {
+                    self.select(slot);
+                    true
+                }
+                None => false,
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let y = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                };
+                // Scrolling up moves left, like most games
+                if y != 0.0 {
+                    self.scroll(-y.signum() as i32);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// The slot a number key selects, 1 being the first
+fn number_key(key: VirtualKeyCode) -> Option<usize> {
+    let slot = match key {
+        VirtualKeyCode::Key1 => 0,
+        VirtualKeyCode::Key2 => 1,
+        VirtualKeyCode::Key3 => 2,
+        VirtualKeyCode::Key4 => 3,
+        VirtualKeyCode::Key5 => 4,
+        VirtualKeyCode::Key6 => 5,
+        VirtualKeyCode::Key7 => 6,
+        VirtualKeyCode::Key8 => 7,
+        VirtualKeyCode::Key9 => 8,
+        _ => return None,
+    };
+    Some(slot)
+}
@@ -0,0 +1,394 @@
+use std::time::Instant;
+use wgpu_glyph::HorizontalAlign;
+use winit::event::MouseButton;
+
+use crate::console::{Console, Line, LineKind, RECENT_TIME};
+use crate::debug::{FrameTimes, FRAME_WINDOW};
+use crate::edit::Editor;
+use crate::hotbar::HOTBAR_SLOTS;
+use crate::inventory::{ItemStack, INVENTORY_SLOTS};
+use crate::player::{GameMode, Player};
+use crate::render::{
+    block::Palette,
+    quad::{Quad, Rect, TextureId},
+};
+use crate::ui::{Ui, ACCENT_COLOR, TEXT_COLOR};
+
+const TEXT_SCALE: f32 = 24.0;
+
+const CROSSHAIR_SIZE: f32 = 20.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+const CROSSHAIR_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
+/// A plus sign in the middle of the screen
+pub fn crosshair(ui: &mut Ui) {
+    let (x, y) = ui.screen().center();
+    let (half, thickness) = (CROSSHAIR_SIZE / 2.0, CROSSHAIR_THICKNESS / 2.0);
+    ui.quad(Quad::colored(
+        Rect::new(x - half, y - thickness, CROSSHAIR_SIZE, CROSSHAIR_THICKNESS),
+        CROSSHAIR_COLOR,
+    ));
+    ui.quad(Quad::colored(
+        Rect::new(
+            x - thickness,
+            y - half,
+            CROSSHAIR_THICKNESS,
+            half - thickness,
+        ),
+        CROSSHAIR_COLOR,
+    ));
+    ui.quad(Quad::colored(
+        Rect::new(
+            x - thickness,
+            y + thickness,
+            CROSSHAIR_THICKNESS,
+            half - thickness,
+        ),
+        CROSSHAIR_COLOR,
+    ));
+}
+
+const GRAPH_HEIGHT: f32 = 80.0;
+const GRAPH_BAR_WIDTH: f32 = 2.0;
+const GRAPH_MARGIN: f32 = 8.0;
+// Frame time at the top of the graph, in milliseconds
+const GRAPH_MAX_MS: f32 = 50.0;
+const GRAPH_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const TARGET_60: f32 = 1000.0 / 60.0;
+const TARGET_30: f32 = 1000.0 / 30.0;
+
+/// Bars for the most recent frame times in the bottom left corner, colored by
+/// whether they hit 60 or 30 fps, with a line marking 60 fps
+pub fn frame_graph(ui: &mut Ui, frame_times: &FrameTimes) {
+    let screen = ui.screen();
+    let width = FRAME_WINDOW as f32 * GRAPH_BAR_WIDTH;
+    let bottom = screen.h - GRAPH_MARGIN;
+    let left = GRAPH_MARGIN;
+    ui.quad(Quad::colored(
+        Rect::new(left, bottom - GRAPH_HEIGHT, width, GRAPH_HEIGHT),
+        GRAPH_BACKGROUND,
+    ));
+
+    let to_height = |ms: f32| (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT;
+    for (i, time) in frame_times.iter().enumerate() {
+        let ms = time.as_secs_f32() * 1000.0;
+        let color = if ms <= TARGET_60 {
+            [0.3, 0.9, 0.3, 0.9]
+        } else if ms <= TARGET_30 {
+            [0.95, 0.8, 0.2, 0.9]
+        } else {
+            [0.95, 0.3, 0.25, 0.9]
+        };
+        let height = to_height(ms);
+        ui.quad(Quad::colored(
+            Rect::new(
+                left + i as f32 * GRAPH_BAR_WIDTH,
+                bottom - height,
+                GRAPH_BAR_WIDTH,
+                height,
+            ),
+            color,
+        ));
+    }
+
+    ui.quad(Quad::colored(
+        Rect::new(left, bottom - to_height(TARGET_60), width, 1.0),
+        [1.0, 1.0, 1.0, 0.5],
+    ));
+}
+
+const SLOT_SIZE: f32 = 48.0;
+const SLOT_GAP: f32 = 4.0;
+const SLOT_BORDER: f32 = 3.0;
+const SLOT_COLOR: [f32; 4] = [0.08, 0.08, 0.1, 0.7];
+const SLOT_HOVER_COLOR: [f32; 4] = [0.3, 0.3, 0.36, 0.8];
+const COUNT_SCALE: f32 = 20.0;
+
+const PALETTE_COLUMNS: usize = 6;
+// Height of the search box above the palette
+const SEARCH_HEIGHT: f32 = 36.0;
+const INVENTORY_ROWS: usize = INVENTORY_SLOTS / HOTBAR_SLOTS;
+
+/// Draws a slot with the icon of the block in it and, when `show_count` is
+/// set, how many there are
+fn slot(
+    ui: &mut Ui,
+    rect: Rect,
+    stack: Option<ItemStack>,
+    block_icons: &[TextureId],
+    show_count: bool,
+    selected: bool,
+) {
+    let background = if ui.hovered(rect) {
+        SLOT_HOVER_COLOR
+    } else {
+        SLOT_COLOR
+    };
+    if selected {
+        ui.quad(Quad::colored(rect, ACCENT_COLOR));
+        ui.quad(Quad::colored(rect.inset(SLOT_BORDER), background));
+    } else {
+        ui.quad(Quad::colored(rect, background));
+    }
+    if let Some(stack) = stack {
+        item(ui, rect, stack, block_icons, show_count);
+    }
+}
+
+fn item(ui: &mut Ui, rect: Rect, stack: ItemStack, block_icons: &[TextureId], show_count: bool) {
+    if let Some(&icon) = block_icons.get(stack.block) {
+        ui.quad(Quad::textured(
+            rect.inset(rect.w / 6.0),
+            icon,
+            Rect::new(0.0, 0.0, 1.0, 1.0),
+        ));
+    }
+    if show_count && stack.count > 1 {
+        ui.text(
+            &stack.count.to_string(),
+            (rect.x + rect.w - 4.0, rect.y + rect.h - COUNT_SCALE / 2.0),
+            COUNT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Right,
+        );
+    }
+}
+
+// Lays out rows of `HOTBAR_SLOTS` slots starting at the top left corner given
+fn slot_rect(left: f32, top: f32, index: usize) -> Rect {
+    let (row, column) = (index / HOTBAR_SLOTS, index % HOTBAR_SLOTS);
+    Rect::new(
+        left + column as f32 * (SLOT_SIZE + SLOT_GAP),
+        top + row as f32 * (SLOT_SIZE + SLOT_GAP),
+        SLOT_SIZE,
+        SLOT_SIZE,
+    )
+}
+
+fn row_width(slots: usize) -> f32 {
+    slots as f32 * (SLOT_SIZE + SLOT_GAP) - SLOT_GAP
+}
+
+/// The hotbar slots centered along the bottom of the screen, with the
+/// selected one highlighted and its block's name above
+pub fn hotbar(ui: &mut Ui, player: &Player, block_types: &Palette, block_icons: &[TextureId]) {
+    let screen = ui.screen();
+    let left = (screen.w - row_width(HOTBAR_SLOTS)) / 2.0;
+    let top = screen.h - SLOT_SIZE - GRAPH_MARGIN;
+    let show_count = player.game_mode == GameMode::Survival;
+
+    for i in 0..HOTBAR_SLOTS {
+        slot(
+            ui,
+            slot_rect(left, top, i),
+            player.inventory.slot(i),
+            block_icons,
+            show_count,
+            i == player.hotbar.selected,
+        );
+    }
+
+    if let Some(stack) = player.inventory.slot(player.hotbar.selected) {
+        ui.text(
+            &block_types.block_type(stack.block).name,
+            (screen.w / 2.0, top - SLOT_GAP - TEXT_SCALE / 2.0),
+            TEXT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Center,
+        );
+    }
+}
+
+/// Reminds the player that clicks pick corners while the selection tool is
+/// out, and shows the size of the selection
+pub fn selection_tool(ui: &mut Ui, editor: &Editor) {
+    if !editor.tool {
+        return;
+    }
+    let text = match editor.selection.region() {
+        Some(region) => {
+            let size = region.size();
+            format!("Selection: {} x {} x {}", size.x, size.y, size.z)
+        }
+        None => String::from("Left click the first corner, right click the second"),
+    };
+    let screen = ui.screen();
+    let top = screen.h - SLOT_SIZE - GRAPH_MARGIN;
+    ui.text(
+        &text,
+        (screen.w / 2.0, top - SLOT_GAP - TEXT_SCALE * 1.5),
+        TEXT_SCALE,
+        ACCENT_COLOR,
+        HorizontalAlign::Center,
+    );
+}
+
+/// The full inventory, with the hotbar as its bottom row, and in creative a
+/// searchable palette of every block type beside it. Shift clicking moves a
+/// stack between the hotbar and the rest of the inventory.
+pub fn inventory_screen(
+    ui: &mut Ui,
+    player: &mut Player,
+    block_types: &Palette,
+    search: &mut String,
+    block_icons: &[TextureId],
+    shift: bool,
+) {
+    let creative = player.game_mode == GameMode::Creative;
+    let grid_width = row_width(HOTBAR_SLOTS);
+    // An extra gap separates the hotbar from the rows above it
+    let grid_height = row_width(INVENTORY_ROWS) + SLOT_GAP * 2.0;
+    let palette_width = row_width(PALETTE_COLUMNS);
+    let width = grid_width + 24.0 + if creative { palette_width + 24.0 } else { 0.0 };
+    let height = grid_height + 24.0 + 48.0;
+
+    ui.centered_panel(width, height, |ui| {
+        ui.heading("Inventory");
+    });
+    let screen = ui.screen();
+    let left = ((screen.w - width) / 2.0).floor() + 12.0;
+    let top = ((screen.h - height) / 2.0).floor() + 12.0 + 48.0;
+
+    let inventory = &mut player.inventory;
+    for index in 0..inventory.len() {
+        // Storage rows on top, hotbar underneath
+        let (position, gap) = if index < HOTBAR_SLOTS {
+            ((INVENTORY_ROWS - 1) * HOTBAR_SLOTS + index, SLOT_GAP * 2.0)
+        } else {
+            (index - HOTBAR_SLOTS, 0.0)
+        };
+        let rect = slot_rect(left, top + gap, position);
+        match ui.click_area(&format!("slot {}", index), rect) {
+            Some(MouseButton::Left) if shift => inventory.quick_move(index),
+            Some(button) => inventory.click(index, button),
+            None => {}
+        }
+        slot(ui, rect, inventory.slot(index), block_icons, true, false);
+    }
+
+    if creative {
+        // The search box gets a panel of its own for the UI to lay it out in
+        let palette_left = left + grid_width + 24.0;
+        let search_rect = Rect::new(
+            palette_left - 12.0,
+            top - 12.0,
+            palette_width + 24.0,
+            SEARCH_HEIGHT + 24.0,
+        );
+        ui.panel(search_rect, |ui| ui.text_input("Search blocks", search));
+        let palette_top = top + SEARCH_HEIGHT + 12.0 + SLOT_GAP;
+
+        let query = search.to_lowercase();
+        let matching = block_types
+            .iter()
+            .filter(|(_, block_type)| block_type.name.to_lowercase().contains(&query));
+        for (i, (id, block_type)) in matching.enumerate() {
+            let rect = Rect::new(
+                palette_left + (i % PALETTE_COLUMNS) as f32 * (SLOT_SIZE + SLOT_GAP),
+                palette_top + (i / PALETTE_COLUMNS) as f32 * (SLOT_SIZE + SLOT_GAP),
+                SLOT_SIZE,
+                SLOT_SIZE,
+            );
+            // Clicking the palette while holding something throws it away,
+            // otherwise it picks up a full stack, or one with right click
+            match ui.click_area(&block_type.name, rect) {
+                Some(_) if inventory.held.is_some() => inventory.held = None,
+                Some(MouseButton::Right) => inventory.held = Some(ItemStack::new(id, 1)),
+                Some(_) => inventory.held = Some(ItemStack::full(id)),
+                None => {}
+            }
+            slot(
+                ui,
+                rect,
+                Some(ItemStack::new(id, 1)),
+                block_icons,
+                false,
+                false,
+            );
+        }
+    }
+
+    if let Some(held) = inventory.held {
+        let (x, y) = ui.mouse();
+        let rect = Rect::new(
+            x - SLOT_SIZE / 2.0,
+            y - SLOT_SIZE / 2.0,
+            SLOT_SIZE,
+            SLOT_SIZE,
+        );
+        item(ui, rect, held, block_icons, true);
+    }
+}
+
+const CONSOLE_WIDTH: f32 = 640.0;
+const CONSOLE_LINES: usize = 10;
+const CONSOLE_LINE_HEIGHT: f32 = 22.0;
+const CONSOLE_MARGIN: f32 = 8.0;
+// Keeps the console clear of the hotbar
+const CONSOLE_BOTTOM: f32 = 100.0;
+const CONSOLE_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/// The chat log in the bottom left corner. While open it shows the scrollback
+/// and the line being typed, otherwise only lines that arrived recently.
+pub fn console(ui: &mut Ui, console: &Console, open: bool) {
+    let now = Instant::now();
+    let lines: Vec<&Line> = if open {
+        console
+            .lines()
+            .skip(console.scroll)
+            .take(CONSOLE_LINES)
+            .collect()
+    } else {
+        console
+            .lines()
+            .take(CONSOLE_LINES)
+            .take_while(|line| now.duration_since(line.time) < RECENT_TIME)
+            .collect()
+    };
+
+    let screen = ui.screen();
+    let left = CONSOLE_MARGIN;
+    let width = CONSOLE_WIDTH.min(screen.w - 2.0 * CONSOLE_MARGIN);
+    let mut bottom = screen.h - CONSOLE_BOTTOM;
+    if open {
+        let input = Rect::new(
+            left,
+            bottom - CONSOLE_LINE_HEIGHT,
+            width,
+            CONSOLE_LINE_HEIGHT,
+        );
+        ui.quad(Quad::colored(input, CONSOLE_BACKGROUND));
+        ui.text(
+            &format!("{}_", console.input),
+            (left + CONSOLE_MARGIN, input.center().1),
+            TEXT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Left,
+        );
+        bottom -= CONSOLE_LINE_HEIGHT + CONSOLE_MARGIN / 2.0;
+    }
+    if lines.is_empty() {
+        return;
+    }
+
+    let height = lines.len() as f32 * CONSOLE_LINE_HEIGHT;
+    ui.quad(Quad::colored(
+        Rect::new(left, bottom - height, width, height),
+        CONSOLE_BACKGROUND,
+    ));
+    for (i, line) in lines.iter().enumerate() {
+        let color = match line.kind {
+            LineKind::Chat => TEXT_COLOR,
+            LineKind::Info => [0.7, 0.8, 0.9, 1.0],
+            LineKind::Error => [1.0, 0.4, 0.35, 1.0],
+        };
+        let y = bottom - (i as f32 + 0.5) * CONSOLE_LINE_HEIGHT;
+        ui.text(
+            &line.text,
+            (left + CONSOLE_MARGIN, y),
+            TEXT_SCALE,
+            color,
+            HorizontalAlign::Left,
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use winit::event::MouseButton;
+
+use crate::hotbar::HOTBAR_SLOTS;
+use crate::render::block::BLOCK_TYPES;
+
+pub const MAX_STACK: u32 = 64;
+// Three rows of storage above the hotbar, which is the first row of slots
+pub const INVENTORY_SLOTS: usize = HOTBAR_SLOTS * 4;
+
+/// A number of blocks of the same type in one slot
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub block: usize,
+    pub count: u32,
+}
+
+impl ItemStack {
+    pub fn new(block: usize, count: u32) -> Self {
+        Self {
+            block,
+            count: count.min(MAX_STACK),
+        }
+    }
+
+    pub fn full(block: usize) -> Self {
+        Self::new(block, MAX_STACK)
+    }
+
+    /// How many more can be added before the stack is full
+    pub fn space(&self) -> u32 {
+        MAX_STACK - self.count
+    }
+}
+
+/// The player's slots, plus the stack picked up with the mouse while the
+/// inventory screen is open
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    pub held: Option<ItemStack>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; INVENTORY_SLOTS],
+            held: None,
+        }
+    }
+
+    /// A full stack of each block type across the hotbar
+    pub fn creative() -> Self {
+        let mut inventory = Self::new();
+        for (slot, block) in (0..BLOCK_TYPES.len()).take(HOTBAR_SLOTS).enumerate() {
+            inventory.slots[slot] = Some(ItemStack::full(block));
+        }
+        inventory
+    }
+
+    pub fn slot(&self, slot: usize) -> Option<ItemStack> {
+        self.slots.get(slot).copied().flatten()
+    }
+
+    pub fn set_slot(&mut self, slot: usize, stack: Option<ItemStack>) {
+        if let Some(existing) = self.slots.get_mut(slot) {
+            *existing = stack.filter(|stack| stack.count > 0);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Adds `stack`, topping up stacks of the same block before filling empty
+    /// slots from the hotbar onwards. Returns how many didn't fit.
+    pub fn add(&mut self, stack: ItemStack) -> u32 {
+        self.add_to(0..self.slots.len(), stack)
+    }
+
+    fn add_to(&mut self, range: Range<usize>, stack: ItemStack) -> u32 {
+        let slots = &mut self.slots[range];
+        let mut remaining = stack.count;
+        for existing in slots.iter_mut().flatten() {
+            if remaining == 0 {
+                break;
+            }
+            if existing.block == stack.block {
+                let moved = remaining.min(existing.space());
+                existing.count += moved;
+                remaining -= moved;
+            }
+        }
+        for slot in slots.iter_mut().filter(|slot| slot.is_none()) {
+            if remaining == 0 {
+                break;
+            }
+            let moved = remaining.min(MAX_STACK);
+            *slot = Some(ItemStack::new(stack.block, moved));
+            remaining -= moved;
+        }
+        remaining
+    }
+
+    /// Takes a single block from `slot`, emptying it when it runs out
+    pub fn take_one(&mut self, slot: usize) -> Option<usize> {
+        let stack = self.slots.get_mut(slot)?;
+        let block = stack.as_ref()?.block;
+        match stack {
+            Some(existing) if existing.count > 1 => existing.count -= 1,
+            _ => *stack = None,
+        }
+        Some(block)
+    }
+
+    /// Takes half of `slot`, rounding up, and leaves the rest
+    pub fn split(&mut self, slot: usize) -> Option<ItemStack> {
+        let stack = self.slots.get_mut(slot)?;
+        let existing = stack.as_mut()?;
+        let taken = existing.count.div_ceil(2);
+        existing.count -= taken;
+        let block = existing.block;
+        if existing.count == 0 {
+            *stack = None;
+        }
+        Some(ItemStack::new(block, taken))
+    }
+
+    /// Puts `stack` into `slot`. Stacks of the same block merge up to the
+    /// limit and anything left over is returned. A different block swaps
+    /// places with `stack`.
+    pub fn merge(&mut self, slot: usize, stack: ItemStack) -> Option<ItemStack> {
+        let existing = match self.slots.get_mut(slot) {
+            Some(existing) => existing,
+            None => return Some(stack),
+        };
+        match existing {
+            None => {
+                *existing = Some(stack);
+                None
+            }
+            Some(existing) if existing.block == stack.block => {
+                let moved = stack.count.min(existing.space());
+                existing.count += moved;
+                Some(ItemStack::new(stack.block, stack.count - moved)).filter(|rest| rest.count > 0)
+            }
+            Some(_) => existing.replace(stack),
+        }
+    }
+
+    /// Moves the stack in `slot` between the hotbar and the rest of the
+    /// inventory, leaving behind whatever doesn't fit
+    pub fn quick_move(&mut self, slot: usize) {
+        let stack = match self.slots.get_mut(slot).and_then(Option::take) {
+            Some(stack) => stack,
+            None => return,
+        };
+        let range = if slot < HOTBAR_SLOTS {
+            HOTBAR_SLOTS..self.slots.len()
+        } else {
+            0..HOTBAR_SLOTS
+        };
+        let remaining = self.add_to(range, stack);
+        self.set_slot(slot, Some(ItemStack::new(stack.block, remaining)));
+    }
+
+    /// Handles a click on `slot` in the inventory screen. Left click picks up
+    /// the whole stack or puts down the held one, right click picks up half or
+    /// puts down a single block.
+    pub fn click(&mut self, slot: usize, button: MouseButton) {
+        match (self.held.take(), button) {
+            (None, MouseButton::Left) => {
+                self.held = self.slot(slot);
+                self.set_slot(slot, None);
+            }
+            (None, MouseButton::Right) => self.held = self.split(slot),
+            (Some(held), MouseButton::Left) => self.held = self.merge(slot, held),
+            (Some(held), MouseButton::Right) => match self.slot(slot) {
+                // Nowhere to put a single block
+                Some(existing) if existing.block != held.block || existing.space() == 0 => {
+                    self.held = Some(held);
+                }
+                _ => {
+                    self.merge(slot, ItemStack::new(held.block, 1));
+                    self.held = Some(ItemStack::new(held.block, held.count - 1))
+                        .filter(|rest| rest.count > 0);
+                }
+            },
+            (held, _) => self.held = held,
+        }
+    }
+
+    /// Puts the held stack back into the slots when the screen closes.
+    /// Returns how many didn't fit.
+    pub fn return_held(&mut self) -> u32 {
+        self.held.take().map_or(0, |held| self.add(held))
+    }
+
+    /// Filled slots and their contents
+    pub fn stacks(&self) -> impl Iterator<Item = (usize, ItemStack)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, stack)| stack.map(|stack| (slot, stack)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{DIRT, STONE};
+
+    #[test]
+    fn split_takes_the_larger_half() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(3, Some(ItemStack::new(STONE, 5)));
+        assert_eq!(inventory.split(3), Some(ItemStack::new(STONE, 3)));
+        assert_eq!(inventory.slot(3), Some(ItemStack::new(STONE, 2)));
+        assert_eq!(inventory.split(3), Some(ItemStack::new(STONE, 1)));
+        assert_eq!(inventory.split(3), Some(ItemStack::new(STONE, 1)));
+        assert_eq!(inventory.slot(3), None);
+        assert_eq!(inventory.split(3), None);
+    }
+
+    #[test]
+    fn merge_fills_up_to_the_max_stack() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(0, Some(ItemStack::new(STONE, 60)));
+        let rest = inventory.merge(0, ItemStack::new(STONE, 10));
+        assert_eq!(inventory.slot(0), Some(ItemStack::full(STONE)));
+        assert_eq!(rest, Some(ItemStack::new(STONE, 6)));
+
+        // Exactly filling it leaves nothing over
+        inventory.set_slot(1, Some(ItemStack::new(STONE, 32)));
+        assert_eq!(inventory.merge(1, ItemStack::new(STONE, 32)), None);
+
+        // A different block swaps places
+        let swapped = inventory.merge(0, ItemStack::new(DIRT, 4));
+        assert_eq!(swapped, Some(ItemStack::full(STONE)));
+        assert_eq!(inventory.slot(0), Some(ItemStack::new(DIRT, 4)));
+    }
+
+    #[test]
+    fn add_tops_up_before_filling_empty_slots() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(5, Some(ItemStack::new(STONE, 50)));
+        assert_eq!(inventory.add(ItemStack::new(STONE, 20)), 0);
+        assert_eq!(inventory.slot(5), Some(ItemStack::full(STONE)));
+        assert_eq!(inventory.slot(0), Some(ItemStack::new(STONE, 6)));
+    }
+
+    #[test]
+    fn add_returns_what_overflows() {
+        let mut inventory = Inventory::new();
+        for slot in 0..INVENTORY_SLOTS {
+            inventory.set_slot(slot, Some(ItemStack::new(DIRT, 1)));
+        }
+        inventory.set_slot(7, Some(ItemStack::new(STONE, 60)));
+        assert_eq!(inventory.add(ItemStack::new(STONE, 10)), 6);
+        assert_eq!(inventory.slot(7), Some(ItemStack::full(STONE)));
+        assert_eq!(inventory.return_held(), 0);
+    }
+
+    #[test]
+    fn quick_move_between_hotbar_and_storage() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(2, Some(ItemStack::new(STONE, 10)));
+        inventory.quick_move(2);
+        assert_eq!(inventory.slot(2), None);
+        assert_eq!(
+            inventory.slot(HOTBAR_SLOTS),
+            Some(ItemStack::new(STONE, 10))
+        );
+
+        inventory.set_slot(0, Some(ItemStack::new(STONE, 60)));
+        inventory.quick_move(HOTBAR_SLOTS);
+        assert_eq!(inventory.slot(0), Some(ItemStack::full(STONE)));
+        assert_eq!(inventory.slot(1), Some(ItemStack::new(STONE, 6)));
+        assert_eq!(inventory.slot(HOTBAR_SLOTS), None);
+    }
+
+    #[test]
+    fn quick_move_leaves_what_doesnt_fit() {
+        let mut inventory = Inventory::new();
+        for slot in 0..HOTBAR_SLOTS {
+            inventory.set_slot(slot, Some(ItemStack::new(DIRT, 1)));
+        }
+        inventory.set_slot(0, Some(ItemStack::new(STONE, 62)));
+        let storage = INVENTORY_SLOTS - 1;
+        inventory.set_slot(storage, Some(ItemStack::new(STONE, 5)));
+        inventory.quick_move(storage);
+        assert_eq!(inventory.slot(0), Some(ItemStack::full(STONE)));
+        assert_eq!(inventory.slot(storage), Some(ItemStack::new(STONE, 3)));
+    }
+
+    #[test]
+    fn take_one_empties_the_slot() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(0, Some(ItemStack::new(STONE, 2)));
+        assert_eq!(inventory.take_one(0), Some(STONE));
+        assert_eq!(inventory.take_one(0), Some(STONE));
+        assert_eq!(inventory.slot(0), None);
+        assert_eq!(inventory.take_one(0), None);
+    }
+
+    #[test]
+    fn right_click_picks_up_half_and_puts_down_one() {
+        let mut inventory = Inventory::new();
+        inventory.set_slot(0, Some(ItemStack::new(STONE, 7)));
+        inventory.click(0, MouseButton::Right);
+        assert_eq!(inventory.held, Some(ItemStack::new(STONE, 4)));
+        inventory.click(1, MouseButton::Right);
+        assert_eq!(inventory.slot(1), Some(ItemStack::new(STONE, 1)));
+        assert_eq!(inventory.held, Some(ItemStack::new(STONE, 3)));
+        inventory.click(0, MouseButton::Left);
+        assert_eq!(inventory.slot(0), Some(ItemStack::new(STONE, 6)));
+        assert_eq!(inventory.held, None);
+    }
+}
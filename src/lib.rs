@@ -0,0 +1,28 @@
+//! The game as a library, so that benchmarks can use the engine. The window
+//! and event loop are in main.rs, which uses the public modules here.
+
+pub mod cli;
+mod command;
+mod console;
+pub mod context;
+mod debug;
+mod edit;
+pub mod error;
+pub mod export;
+pub mod headless;
+mod hotbar;
+mod hud;
+mod inventory;
+mod menu;
+mod player;
+mod profiler;
+pub mod raycast;
+pub mod render;
+pub mod save;
+mod schematic;
+mod screenshot;
+pub mod settings;
+pub mod terrain;
+mod ui;
+mod vox;
+mod world;
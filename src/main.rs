@@ -1,36 +1,74 @@
-mod context;
-mod player;
-mod render;
-mod world;
-
 use futures::executor::block_on;
+use structopt::StructOpt;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 
-use crate::context::Context;
+use constructor::cli::{Cli, Mode};
+use constructor::context::Context;
+use constructor::error::Result;
+use constructor::save::WorldSave;
+use constructor::settings::Settings;
+use constructor::{export, headless};
 
 fn main() {
     env_logger::init();
 
+    if let Err(e) = run() {
+        log::error!("{}", e);
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::from_args();
+    let file_settings = Settings::load(&cli.config);
+    let mut settings = file_settings.clone();
+    cli.apply(&mut settings);
+    let save = WorldSave::open(&cli.world, cli.seed)?;
+
+    match cli.mode() {
+        Mode::Headless(output, golden) => {
+            return block_on(headless::run(
+                settings,
+                save,
+                cli.spawn,
+                &output,
+                golden.as_deref(),
+            ));
+        }
+        Mode::Benchmark(frames) => {
+            return block_on(headless::benchmark(settings, save, cli.spawn, frames));
+        }
+        Mode::Export(path, region) => return export::run(&save, region, &path),
+        Mode::Play => {}
+    }
+
     log::info!("Building window");
     let event_loop = EventLoop::new();
+    let fullscreen = settings.fullscreen(
+        event_loop.available_monitors(),
+        event_loop.primary_monitor(),
+    );
     let window = WindowBuilder::new()
         .with_title("Constructors")
-        .with_inner_size(PhysicalSize::new(1280, 720))
-        .build(&event_loop)
-        .unwrap();
-
-    window.set_cursor_visible(false);
+        .with_inner_size(PhysicalSize::new(
+            settings.window_width,
+            settings.window_height,
+        ))
+        .with_fullscreen(fullscreen)
+        .build(&event_loop)?;
 
     log::info!("Initializing Context");
-    let mut context = block_on(Context::new(&window));
+    let mut context = block_on(Context::new(&window, cli, file_settings, save))?;
 
     let mut last_time = std::time::Instant::now();
     let mut focused = true;
+    let mut cursor_grabbed = false;
     log::info!("Begin loop");
     event_loop.run(move |event, _, control_flow| {
         *control_flow = if cfg!(feature = "metal-auto-capture") {
@@ -54,32 +92,40 @@ fn main() {
                             context.resize(**new_inner_size);
                         }
 
-                        WindowEvent::CursorMoved { .. } if focused => {
-                            window
-                                .set_cursor_position(PhysicalPosition::new(
-                                    context.size.width as f32 / 2.0,
-                                    context.size.height as f32 / 2.0,
-                                ))
-                                .unwrap();
+                        WindowEvent::CursorMoved { .. } if cursor_grabbed => {
+                            if let Err(e) = window.set_cursor_position(PhysicalPosition::new(
+                                context.size.width as f32 / 2.0,
+                                context.size.height as f32 / 2.0,
+                            )) {
+                                log::warn!("Failed to center cursor: {}", e);
+                            }
                         }
 
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::KeyboardInput { input, .. } => match input {
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            } => {
-                                log::info!("Escape pressed - exiting");
-                                *control_flow = ControlFlow::Exit
-                            }
-                            _ => {}
-                        },
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::F11),
+                                    ..
+                                },
+                            ..
+                        } => context.toggle_fullscreen(&window),
                         _ => {}
                     }
                 }
             }
             Event::MainEventsCleared => {
+                if context.quit_requested() {
+                    log::info!("Quitting");
+                    *control_flow = ControlFlow::Exit;
+                }
+
+                let grab = focused && context.cursor_grabbed();
+                if grab != cursor_grabbed {
+                    cursor_grabbed = grab;
+                    grab_cursor(&window, grab);
+                }
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
@@ -89,7 +135,26 @@ fn main() {
                 context.update(dt);
                 context.render();
             }
+            Event::LoopDestroyed => context.save_world(),
             _ => {}
         }
     });
 }
+
+// Hides the cursor and keeps it in the window while playing, centering it so
+// the first mouse movement after grabbing doesn't jump the camera
+fn grab_cursor(window: &Window, grab: bool) {
+    if let Err(e) = window.set_cursor_grab(grab) {
+        log::warn!("Failed to grab cursor: {}", e);
+    }
+    window.set_cursor_visible(!grab);
+    if grab {
+        let size = window.inner_size();
+        if let Err(e) = window.set_cursor_position(PhysicalPosition::new(
+            size.width as f32 / 2.0,
+            size.height as f32 / 2.0,
+        )) {
+            log::warn!("Failed to center cursor: {}", e);
+        }
+    }
+}
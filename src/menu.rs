@@ -0,0 +1,116 @@
+use crate::settings::{Settings, MAX_RENDER_DISTANCE};
+use crate::ui::Ui;
+
+const MENU_WIDTH: f32 = 360.0;
+const MAX_VIEW_DISTANCE: f32 = 64.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MenuAction {
+    Play,
+    Quit,
+    Resume,
+    OpenSettings,
+    SaveAndQuit,
+    Back,
+    // A value in the settings screen changed and should be applied
+    SettingsChanged,
+}
+
+pub fn main_menu(ui: &mut Ui) -> Option<MenuAction> {
+    ui.centered_panel(MENU_WIDTH, 152.0, |ui| {
+        ui.heading("Constructors");
+        if ui.button("Play") {
+            return Some(MenuAction::Play);
+        }
+        if ui.button("Quit") {
+            return Some(MenuAction::Quit);
+        }
+        None
+    })
+}
+
+pub fn pause_menu(ui: &mut Ui) -> Option<MenuAction> {
+    ui.centered_panel(MENU_WIDTH, 196.0, |ui| {
+        ui.heading("Paused");
+        if ui.button("Resume") {
+            return Some(MenuAction::Resume);
+        }
+        if ui.button("Settings") {
+            return Some(MenuAction::OpenSettings);
+        }
+        if ui.button("Save and quit") {
+            return Some(MenuAction::SaveAndQuit);
+        }
+        None
+    })
+}
+
+pub fn settings_menu(ui: &mut Ui, settings: &mut Settings) -> Option<MenuAction> {
+    ui.centered_panel(MENU_WIDTH + 120.0, 380.0, |ui| {
+        ui.heading("Settings");
+        let mut changed = false;
+
+        let mut render_distance = settings.render_distance as f32;
+        if ui.slider(
+            "Render distance",
+            &mut render_distance,
+            1.0,
+            MAX_RENDER_DISTANCE as f32,
+            1.0,
+        ) {
+            settings.render_distance = render_distance as u16;
+            changed = true;
+        }
+        let mut view_distance = settings.view_distance as f32;
+        if ui.slider(
+            "View distance",
+            &mut view_distance,
+            1.0,
+            MAX_VIEW_DISTANCE,
+            1.0,
+        ) {
+            settings.view_distance = view_distance as u16;
+            changed = true;
+        }
+        changed |= ui.slider("Field of view", &mut settings.fov, 30.0, 110.0, 5.0);
+        changed |= ui.slider(
+            "Mouse sensitivity",
+            &mut settings.mouse_sensitivity,
+            0.01,
+            0.5,
+            0.01,
+        );
+        changed |= ui.slider(
+            "Movement speed",
+            &mut settings.movement_speed,
+            1.0,
+            32.0,
+            1.0,
+        );
+        changed |= ui.checkbox("VSync", &mut settings.vsync);
+
+        ui.space(8.0);
+        let back = ui.columns(2, |ui| {
+            if ui.button("Defaults") {
+                settings.reset();
+                changed = true;
+            }
+            ui.button("Back")
+        });
+
+        if back {
+            Some(MenuAction::Back)
+        } else if changed {
+            Some(MenuAction::SettingsChanged)
+        } else {
+            None
+        }
+    })
+}
+
+pub fn loading_screen(ui: &mut Ui, pending_chunks: usize) {
+    ui.centered_panel(MENU_WIDTH, 108.0, |ui| {
+        ui.heading("Loading");
+        ui.label(&format!("{} chunks left to build", pending_chunks));
+    });
+}
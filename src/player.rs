@@ -1,11 +1,40 @@
-use cgmath::{InnerSpace, Rad, Vector3};
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use std::time::Duration;
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, VirtualKeyCode},
 };
 
-use crate::render::camera::Camera;
+use crate::hotbar::Hotbar;
+use crate::inventory::{Inventory, ItemStack};
+use crate::render::{block::Block, camera::Camera};
+use crate::save::{PlayerData, SavedStack};
+
+/// Creative gives every block without using them up, survival only places
+/// what's been collected by breaking blocks
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    Creative,
+    Survival,
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "creative" => Ok(GameMode::Creative),
+            "survival" => Ok(GameMode::Survival),
+            _ => Err(format!(
+                "unknown game mode {:?}, expected creative or survival",
+                text
+            )),
+        }
+    }
+}
 
 #[derive(PartialEq)]
 pub enum Action {
@@ -15,7 +44,7 @@ pub enum Action {
     Left,
     Right,
 
-    // Flight
+    // Flight, or jumping while walking
     Up,
     Down,
 }
@@ -25,21 +54,98 @@ pub struct Player {
     pub sensitivity: f32,
     pub camera: Camera,
     pub actions: Vec<Action>,
+    pub hotbar: Hotbar,
+    pub inventory: Inventory,
+    pub game_mode: GameMode,
+    // Walking players fall and collide with blocks
+    pub flying: bool,
+    pub velocity_y: f32,
+    pub on_ground: bool,
 
     pub mouse_d: (f32, f32),
 }
 
 impl Player {
-    pub fn new(camera: Camera) -> Self {
+    pub fn new(camera: Camera, speed: f32, sensitivity: f32) -> Self {
         Self {
-            speed: 8.0,
-            sensitivity: 0.05,
+            speed,
+            sensitivity,
             camera,
             actions: Vec::new(),
+            hotbar: Hotbar::new(),
+            inventory: Inventory::creative(),
+            game_mode: GameMode::Creative,
+            flying: true,
+            velocity_y: 0.0,
+            on_ground: false,
             mouse_d: (0.0, 0.0),
         }
     }
 
+    /// The block in the selected hotbar slot
+    pub fn selected_block(&self) -> Option<Block> {
+        self.inventory
+            .slot(self.hotbar.selected)
+            .map(|stack| Block::new(stack.block))
+    }
+
+    /// Uses up one of the selected block after placing it, in survival
+    pub fn consume_selected(&mut self) {
+        if self.game_mode == GameMode::Survival {
+            self.inventory.take_one(self.hotbar.selected);
+        }
+    }
+
+    /// Collects a broken block, in survival
+    pub fn pick_up(&mut self, block: Block) {
+        if self.game_mode == GameMode::Survival
+            && self.inventory.add(ItemStack::new(block.id(), 1)) > 0
+        {
+            log::debug!("Inventory full, dropped block {}", block.id());
+        }
+    }
+
+    pub fn to_data(&self) -> PlayerData {
+        let position = self.camera.position;
+        PlayerData {
+            position: [position.x, position.y, position.z],
+            yaw: Deg::from(self.camera.yaw).0,
+            pitch: Deg::from(self.camera.pitch).0,
+            game_mode: self.game_mode,
+            selected: self.hotbar.selected,
+            flying: self.flying,
+            inventory: self
+                .inventory
+                .stacks()
+                .map(|(slot, stack)| SavedStack {
+                    slot,
+                    block: stack.block,
+                    count: stack.count,
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the position, inventory and mode saved with `to_data`
+    pub fn load(&mut self, data: &PlayerData) {
+        self.camera.position = data.position.into();
+        self.camera.yaw = Deg(data.yaw).into();
+        self.camera.pitch = Deg(data.pitch).into();
+        self.game_mode = data.game_mode;
+        self.hotbar.select(data.selected);
+        self.flying = data.flying;
+        self.inventory = Inventory::new();
+        for saved in &data.inventory {
+            self.inventory
+                .set_slot(saved.slot, Some(ItemStack::new(saved.block, saved.count)));
+        }
+    }
+
+    /// Whether jump is held, which only matters while walking
+    pub fn jumping(&self) -> bool {
+        !self.flying && self.actions.contains(&Action::Up)
+    }
+
     pub fn new_action(&mut self, action: Action) {
         if !self.actions.contains(&action) {
             self.actions.push(action);
@@ -68,8 +174,9 @@ impl Player {
                 Action::Backward => self.camera.position -= forward * self.speed * dt,
                 Action::Left => self.camera.position -= right * self.speed * dt,
                 Action::Right => self.camera.position += right * self.speed * dt,
-                Action::Up => self.camera.position.y += self.speed * dt,
-                Action::Down => self.camera.position.y -= self.speed * dt,
+                Action::Up if self.flying => self.camera.position.y += self.speed * dt,
+                Action::Down if self.flying => self.camera.position.y -= self.speed * dt,
+                Action::Up | Action::Down => {}
             }
         }
 
@@ -0,0 +1,212 @@
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+// Recording stops adding events past this, so leaving it on can't eat all memory
+const MAX_RECORDED_EVENTS: usize = 1_000_000;
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::new());
+}
+
+/// Total time spent in a scope during a frame
+#[derive(Copy, Clone, Debug)]
+pub struct ScopeTiming {
+    pub name: &'static str,
+    pub total: Duration,
+    pub calls: u32,
+}
+
+struct Event {
+    name: &'static str,
+    start: Instant,
+    end: Instant,
+}
+
+struct Profiler {
+    frame_start: Instant,
+    current: Vec<ScopeTiming>,
+    last: Vec<ScopeTiming>,
+    recording: Option<Vec<Event>>,
+}
+
+impl Profiler {
+    fn new() -> Self {
+        Self {
+            frame_start: Instant::now(),
+            current: Vec::new(),
+            last: Vec::new(),
+            recording: None,
+        }
+    }
+
+    fn record(&mut self, name: &'static str, start: Instant, end: Instant) {
+        let time = end - start;
+        match self.current.iter_mut().find(|scope| scope.name == name) {
+            Some(scope) => {
+                scope.total += time;
+                scope.calls += 1;
+            }
+            None => self.current.push(ScopeTiming {
+                name,
+                total: time,
+                calls: 1,
+            }),
+        }
+
+        if let Some(events) = &mut self.recording {
+            push_event(events, Event { name, start, end });
+        }
+    }
+}
+
+fn push_event(events: &mut Vec<Event>, event: Event) {
+    if events.len() < MAX_RECORDED_EVENTS {
+        events.push(event);
+    }
+}
+
+/// Times everything until it's dropped, e.g. `let _scope = profiler::scope("update");`.
+/// wgpu 0.5 has no timestamp queries, so render work is timed on the CPU as
+/// it's encoded and submitted.
+pub struct Scope {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let end = Instant::now();
+        PROFILER.with(|profiler| profiler.borrow_mut().record(self.name, self.start, end));
+    }
+}
+
+pub fn scope(name: &'static str) -> Scope {
+    Scope {
+        name,
+        start: Instant::now(),
+    }
+}
+
+/// Ends the current frame, making its scopes available from `last_frame`
+pub fn begin_frame() {
+    PROFILER.with(|profiler| {
+        let mut profiler = profiler.borrow_mut();
+        let now = Instant::now();
+        let start = std::mem::replace(&mut profiler.frame_start, now);
+        if let Some(events) = &mut profiler.recording {
+            push_event(
+                events,
+                Event {
+                    name: "frame",
+                    start,
+                    end: now,
+                },
+            );
+        }
+        profiler.last = std::mem::take(&mut profiler.current);
+    });
+}
+
+/// Scopes from the last finished frame, in the order they first ran
+pub fn last_frame() -> Vec<ScopeTiming> {
+    PROFILER.with(|profiler| profiler.borrow().last.clone())
+}
+
+pub fn is_recording() -> bool {
+    PROFILER.with(|profiler| profiler.borrow().recording.is_some())
+}
+
+pub fn start_recording() {
+    PROFILER.with(|profiler| profiler.borrow_mut().recording = Some(Vec::new()));
+}
+
+/// Stops recording and returns everything timed since `start_recording`
+pub fn stop_recording() -> Option<Trace> {
+    PROFILER
+        .with(|profiler| profiler.borrow_mut().recording.take())
+        .map(|events| Trace { events })
+}
+
+/// Scopes recorded over an interval
+pub struct Trace {
+    events: Vec<Event>,
+}
+
+impl Trace {
+    /// Writes the trace in the Chrome trace event format, which can be opened
+    /// in chrome://tracing or Perfetto
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+        }
+        std::fs::write(path, self.to_json()).map_err(Error::io(path))?;
+        Ok(())
+    }
+
+    fn to_json(&self) -> String {
+        let origin = match self.events.iter().map(|event| event.start).min() {
+            Some(origin) => origin,
+            None => return String::from("{\"traceEvents\":[]}"),
+        };
+        let micros = |time: Duration| time.as_nanos() as f64 / 1000.0;
+
+        let mut json = String::from("{\"traceEvents\":[\n");
+        for (i, event) in self.events.iter().enumerate() {
+            if i > 0 {
+                json.push_str(",\n");
+            }
+            // Writing to a String can't fail
+            let _ = write!(
+                json,
+                "{{\"name\":\"{}\",\"cat\":\"cpu\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1}}",
+                event.name,
+                micros(event.start - origin),
+                micros(event.end - event.start),
+            );
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_trace_has_no_events() {
+        let trace = Trace { events: Vec::new() };
+        assert_eq!(trace.to_json(), "{\"traceEvents\":[]}");
+    }
+
+    #[test]
+    fn events_are_relative_to_the_first() {
+        let origin = Instant::now();
+        let at = |micros: u64| origin + Duration::from_micros(micros);
+        let trace = Trace {
+            events: vec![
+                Event {
+                    name: "update",
+                    start: at(1500),
+                    end: at(1750),
+                },
+                Event {
+                    name: "frame",
+                    start: at(1000),
+                    end: at(17000),
+                },
+            ],
+        };
+        assert_eq!(
+            trace.to_json(),
+            "{\"traceEvents\":[\n\
+             {\"name\":\"update\",\"cat\":\"cpu\",\"ph\":\"X\",\"ts\":500.000,\"dur\":250.000,\"pid\":1,\"tid\":1},\n\
+             {\"name\":\"frame\",\"cat\":\"cpu\",\"ph\":\"X\",\"ts\":0.000,\"dur\":16000.000,\"pid\":1,\"tid\":1}\n\
+             ]}\n"
+        );
+    }
+}
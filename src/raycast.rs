@@ -0,0 +1,201 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::render::chunk::ChunkManager;
+
+/// The first solid block along a ray
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    // World position of the block
+    pub block: Vector3<i32>,
+    // Outward normal of the face the ray entered through, zero if the ray
+    // started inside the block
+    pub normal: Vector3<i32>,
+    pub distance: f32,
+}
+
+impl RayHit {
+    /// Where a block placed against the hit face goes
+    pub fn adjacent(&self) -> Vector3<i32> {
+        self.block + self.normal
+    }
+}
+
+/// Steps through the block grid from `origin` along `direction` one block at a
+/// time, returning the first active block within `max_distance`
+pub fn raycast(
+    chunks: &ChunkManager,
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+) -> Option<RayHit> {
+    if direction.magnitude2() == 0.0 {
+        return None;
+    }
+    let direction = direction.normalize();
+
+    let mut block = Vector3::new(
+        origin.x.floor() as i32,
+        origin.y.floor() as i32,
+        origin.z.floor() as i32,
+    );
+    let mut normal = Vector3::new(0, 0, 0);
+
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+    let mut step = [0; 3];
+    // Distance along the ray to the next boundary on each axis, and between
+    // boundaries
+    let mut next = [f32::INFINITY; 3];
+    let mut delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            delta[axis] = 1.0 / direction[axis];
+            next[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * delta[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            delta[axis] = -1.0 / direction[axis];
+            next[axis] = (origin[axis] - block[axis] as f32) * delta[axis];
+        }
+    }
+
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if chunks.block_active(block) {
+            return Some(RayHit {
+                block,
+                normal,
+                distance,
+            });
+        }
+
+        let axis = if next[0] < next[1] {
+            if next[0] < next[2] {
+                0
+            } else {
+                2
+            }
+        } else if next[1] < next[2] {
+            1
+        } else {
+            2
+        };
+
+        distance = next[axis];
+        next[axis] += delta[axis];
+        block[axis] += step[axis];
+        normal = Vector3::new(0, 0, 0);
+        normal[axis] = -step[axis];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{Block, STONE};
+    use crate::render::chunk::{block_to_chunk, Chunk};
+    use std::collections::HashMap;
+
+    // Loaded chunks holding only `blocks`, plus any empty `loaded` chunks
+    fn chunks(blocks: &[Vector3<i32>], loaded: &[Vector3<i32>]) -> ChunkManager {
+        let mut chunks = HashMap::new();
+        for &position in loaded {
+            chunks.insert(position, Chunk::new(0, position));
+        }
+        for &block in blocks {
+            let (position, local) = block_to_chunk(block);
+            chunks
+                .entry(position)
+                .or_insert_with(|| Chunk::new(0, position))
+                .insert_block(Block::new(STONE), local);
+        }
+        ChunkManager::new(chunks)
+    }
+
+    #[test]
+    fn hits_the_first_block() {
+        let chunks = chunks(&[Vector3::new(0, 2, -4), Vector3::new(0, 2, -6)], &[]);
+        let hit = raycast(
+            &chunks,
+            Point3::new(0.5, 2.5, 0.5),
+            Vector3::new(0.0, 0.0, -1.0),
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(hit.block, Vector3::new(0, 2, -4));
+        assert_eq!(hit.normal, Vector3::new(0, 0, 1));
+        assert!((hit.distance - 3.5).abs() < 1e-5);
+        assert_eq!(hit.adjacent(), Vector3::new(0, 2, -3));
+    }
+
+    #[test]
+    fn misses_past_the_distance() {
+        let chunks = chunks(&[Vector3::new(0, 2, -4)], &[]);
+        let origin = Point3::new(0.5, 2.5, 0.5);
+        assert!(raycast(&chunks, origin, Vector3::new(0.0, 0.0, -1.0), 3.0).is_none());
+        assert!(raycast(&chunks, origin, Vector3::new(0.0, 0.0, 1.0), 10.0).is_none());
+        assert!(raycast(&chunks, origin, Vector3::new(0.0, 0.0, 0.0), 10.0).is_none());
+    }
+
+    #[test]
+    fn normal_faces_back_along_the_ray() {
+        let block = Vector3::new(3, 5, 2);
+        let chunks = chunks(&[block], &[]);
+        let center = Point3::new(3.5, 5.5, 2.5);
+        for axis in 0..3 {
+            for &sign in &[-1.0, 1.0] {
+                let mut direction = Vector3::new(0.0, 0.0, 0.0);
+                direction[axis] = sign;
+                let hit = raycast(&chunks, center - direction * 3.0, direction, 10.0).unwrap();
+                assert_eq!(hit.block, block);
+                assert_eq!(hit.normal, (-direction).cast::<i32>().unwrap());
+                assert!(!chunks.block_active(hit.adjacent()));
+            }
+        }
+    }
+
+    #[test]
+    fn starts_inside_a_block() {
+        let chunks = chunks(&[Vector3::new(1, 1, 1)], &[]);
+        let hit = raycast(
+            &chunks,
+            Point3::new(1.5, 1.5, 1.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            5.0,
+        )
+        .unwrap();
+        assert_eq!(hit.normal, Vector3::new(0, 0, 0));
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn crosses_chunk_boundaries() {
+        // Chunk 0 ends at x = 7 and chunk 1 starts at x = 8
+        let (start, _) = block_to_chunk(Vector3::new(7, 0, 0));
+        let (end, _) = block_to_chunk(Vector3::new(8, 0, 0));
+        assert_ne!(start, end);
+
+        let chunks = chunks(&[Vector3::new(8, 3, 0)], &[start]);
+        let hit = raycast(
+            &chunks,
+            Point3::new(5.5, 3.5, 0.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(hit.block, Vector3::new(8, 3, 0));
+        assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        // Unloaded chunks are passed through as air
+        let chunks = self::chunks(&[], &[start]);
+        assert!(raycast(
+            &chunks,
+            Point3::new(5.5, 3.5, 0.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            10.0
+        )
+        .is_none());
+    }
+}
@@ -1,21 +1,83 @@
 use cgmath::Vector3;
+use std::borrow::Cow;
 
 use super::chunk::CHUNK_SIZE;
 use super::Vertex;
+use crate::error::{Error, Result};
 
 // const BLOCK_SIZE: f32 = 1.0 / 2.0;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct BlockVertex {
-    position: [f32; 3],
-    color: [f32; 3],
-    normal: [f32; 3],
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub normal: [f32; 3],
 }
 
 unsafe impl bytemuck::Pod for BlockVertex {}
 unsafe impl bytemuck::Zeroable for BlockVertex {}
 
+/// What a kind of block is called and looks like, indexed by `Block::id`
+#[derive(Clone, Debug)]
+pub struct BlockType {
+    pub name: Cow<'static, str>,
+    pub color: [f32; 3],
+}
+
+/// The most block types a world can have, so that saved chunks can store
+/// each id plus one as a `u16`
+pub const MAX_BLOCK_TYPES: usize = u16::MAX as usize;
+
+pub const STONE: usize = 0;
+pub const DIRT: usize = 1;
+pub const GRASS: usize = 2;
+
+pub const BLOCK_TYPES: &[BlockType] = &[
+    BlockType {
+        name: Cow::Borrowed("Stone"),
+        color: [0.5, 0.5, 0.52],
+    },
+    BlockType {
+        name: Cow::Borrowed("Dirt"),
+        color: [0.45, 0.3, 0.18],
+    },
+    BlockType {
+        name: Cow::Borrowed("Grass"),
+        color: [0.3, 0.6, 0.2],
+    },
+    BlockType {
+        name: Cow::Borrowed("Sand"),
+        color: [0.86, 0.8, 0.55],
+    },
+    BlockType {
+        name: Cow::Borrowed("Planks"),
+        color: [0.7, 0.53, 0.3],
+    },
+    BlockType {
+        name: Cow::Borrowed("Log"),
+        color: [0.38, 0.26, 0.14],
+    },
+    BlockType {
+        name: Cow::Borrowed("Leaves"),
+        color: [0.18, 0.42, 0.14],
+    },
+    BlockType {
+        name: Cow::Borrowed("Brick"),
+        color: [0.62, 0.25, 0.2],
+    },
+    BlockType {
+        name: Cow::Borrowed("Snow"),
+        color: [0.93, 0.95, 0.97],
+    },
+];
+
+// Stands in for ids saved by a newer version that this one doesn't know
+static MISSING: BlockType = BlockType {
+    name: Cow::Borrowed("Missing"),
+    color: [0.8, 0.0, 0.5],
+};
+
 #[derive(Copy, Clone)]
 pub struct Block {
     id: usize,
@@ -30,14 +92,17 @@ impl Block {
         }
     }
 
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
     pub fn quad(
         width: Vector3<f32>,
         height: Vector3<f32>,
         position: Vector3<i32>,
         normal: Vector3<f32>,
+        color: [f32; 3],
     ) -> (Vec<BlockVertex>, Vec<u32>) {
-        let color: [f32; 3] = [0.8, 0.0, 0.5];
-
         let offset = (CHUNK_SIZE / 2) as i32;
         let position = Vector3::new(
             (position.x - offset) as f32,
@@ -84,6 +149,99 @@ impl Block {
     }
 }
 
+/// The block types of one world: the built-in ones, followed by types that
+/// only have a color, added for colors no built-in type has, such as those of
+/// imported .vox models. Their ids follow the built-in types in the order they
+/// were added, so the palette is saved with the world.
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    colors: Vec<BlockType>,
+}
+
+impl Palette {
+    /// A palette with a color-only type for each of `colors`, in id order
+    pub fn from_colors(colors: &[[u8; 3]]) -> Self {
+        Self {
+            colors: colors.iter().map(|&color| color_type(color)).collect(),
+        }
+    }
+
+    /// The colors of the color-only types, in id order
+    pub fn colors(&self) -> Vec<[u8; 3]> {
+        self.colors
+            .iter()
+            .map(|block_type| to_rgb(block_type.color))
+            .collect()
+    }
+
+    /// The number of block types, including the built-in ones, so it's
+    /// never empty
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        BLOCK_TYPES.len() + self.colors.len()
+    }
+
+    pub fn block_type(&self, id: usize) -> &BlockType {
+        BLOCK_TYPES
+            .get(id)
+            .or_else(|| self.colors.get(id - BLOCK_TYPES.len()))
+            .unwrap_or(&MISSING)
+    }
+
+    /// Every block type and its id
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &BlockType)> {
+        BLOCK_TYPES.iter().chain(&self.colors).enumerate()
+    }
+
+    /// The id of a block type with exactly this color, adding a color-only
+    /// type when no type has it yet. Fails once the palette has
+    /// `MAX_BLOCK_TYPES` types.
+    pub fn color_block(&mut self, color: [u8; 3]) -> Result<usize> {
+        let matches = |block_type: &BlockType| to_rgb(block_type.color) == color;
+        if let Some((id, _)) = self.iter().find(|(_, block_type)| matches(block_type)) {
+            return Ok(id);
+        }
+        if self.len() >= MAX_BLOCK_TYPES {
+            return Err(Error::PaletteFull);
+        }
+        self.colors.push(color_type(color));
+        Ok(self.len() - 1)
+    }
+
+    /// Looks up a block type by its exact name, including color-only types,
+    /// which are added if needed. Names that aren't a block type are `None`.
+    pub fn block_by_name(&mut self, name: &str) -> Result<Option<usize>> {
+        if let Some((id, _)) = self.iter().find(|(_, block_type)| block_type.name == name) {
+            return Ok(Some(id));
+        }
+        // from_str_radix alone would also take a sign
+        let rgb = match name.strip_prefix("Color #") {
+            Some(hex) if hex.len() == 6 && hex.bytes().all(|c| c.is_ascii_hexdigit()) => {
+                u32::from_str_radix(hex, 16).ok()
+            }
+            _ => None,
+        };
+        match rgb.map(u32::to_be_bytes) {
+            Some(rgb) => self.color_block([rgb[1], rgb[2], rgb[3]]).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn color_type(color: [u8; 3]) -> BlockType {
+    BlockType {
+        name: Cow::Owned(format!(
+            "Color #{:02x}{:02x}{:02x}",
+            color[0], color[1], color[2]
+        )),
+        color: color.map(|v| v as f32 / 255.0),
+    }
+}
+
+pub fn to_rgb(color: [f32; 3]) -> [u8; 3] {
+    color.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
 impl BlockVertex {
     pub fn new(position: [f32; 3], color: [f32; 3], normal: [f32; 3]) -> Self {
         BlockVertex {
@@ -120,3 +278,39 @@ impl Vertex for BlockVertex {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_names_need_six_hex_digits() {
+        let mut palette = Palette::default();
+        let id = palette.block_by_name("Color #9b3d7a").unwrap().unwrap();
+        assert_eq!(to_rgb(palette.block_type(id).color), [0x9b, 0x3d, 0x7a]);
+        assert_eq!(palette.block_by_name("Stone").unwrap(), Some(STONE));
+        for bad in &[
+            "Color #+12345",
+            "Color #12345",
+            "Color #12345g",
+            "Color 123456",
+        ] {
+            assert_eq!(palette.block_by_name(bad).unwrap(), None, "found {:?}", bad);
+        }
+        assert_eq!(palette.len(), BLOCK_TYPES.len() + 1);
+    }
+
+    #[test]
+    fn full_palettes_refuse_new_colors() {
+        let colors: Vec<_> = (0..(MAX_BLOCK_TYPES - BLOCK_TYPES.len()) as u32)
+            .map(|n| {
+                let [_, r, g, b] = (n + 1).to_be_bytes();
+                [r, g, b]
+            })
+            .collect();
+        let mut palette = Palette::from_colors(&colors);
+        assert_eq!(palette.len(), MAX_BLOCK_TYPES);
+        assert!(palette.color_block([0xff, 0xff, 0xff]).is_err());
+        assert_eq!(palette.color_block(colors[0]).unwrap(), BLOCK_TYPES.len());
+    }
+}
@@ -28,12 +28,13 @@ impl Camera {
         }
     }
 
+    /// The unit vector the camera is looking along
+    pub fn direction(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.0.cos(), self.pitch.0.sin(), self.yaw.0.sin()).normalize()
+    }
+
     pub fn build_matrix(&self) -> Matrix4<f32> {
-        Matrix4::look_at_dir(
-            self.position,
-            Vector3::new(self.yaw.0.cos(), self.pitch.0.sin(), self.yaw.0.sin()).normalize(),
-            Vector3::unit_y(),
-        )
+        Matrix4::look_at_dir(self.position, self.direction(), Vector3::unit_y())
     }
 }
 
@@ -51,6 +52,14 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn set_fovy<F: Into<Rad<f32>>>(&mut self, fovy: F) {
+        self.fovy = fovy.into();
+    }
+
+    pub fn set_zfar(&mut self, zfar: f32) {
+        self.zfar = zfar;
+    }
+
     pub fn build_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * cgmath::perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
@@ -1,14 +1,22 @@
-use cgmath::Vector3;
+use cgmath::{Point3, Vector3};
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 
-use super::{block::Block, camera::Camera};
+use crate::edit::{BlockChange, BlockEdit, ChangeSet, EditSummary};
+use crate::profiler;
+
+use super::{
+    block::{Block, BlockVertex, Palette},
+    camera::Camera,
+    mesh::{mesh_chunk, Neighbors},
+};
 
 pub const CHUNK_SIZE: usize = 16;
 const CHUNK_3D_SIZE: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
 const MAX_REBUILD_FRAME: usize = 2;
 
-type ChunkPosition = Vector3<i32>;
+pub type ChunkPosition = Vector3<i32>;
 
 pub struct ChunkManager {
     // Main list:
@@ -19,7 +27,13 @@ pub struct ChunkManager {
     // The list of chunks to be rendered
     render: HashSet<ChunkPosition>,
 
+    // Meshes of chunks past the render distance, at a lower level of detail
+    lods: HashMap<ChunkPosition, LodMesh>,
+
     render_dist: u16,
+    // How far LOD meshes are drawn, in chunks
+    view_dist: u16,
+    camera_chunk: ChunkPosition,
     old_chunk_pos: Option<Vector3<i32>>,
 }
 
@@ -29,56 +43,57 @@ pub struct Chunk {
     pub is_active: bool,
     pub blocks: [Option<Block>; CHUNK_3D_SIZE],
     pub mesh: Option<ChunkMesh>,
+    // Changed since it was generated or last saved
+    pub modified: bool,
+}
+
+/// A distant chunk's mesh, built from its blocks downsampled to `level`
+pub struct LodMesh {
+    pub level: u32,
+    pub mesh: Option<ChunkMesh>,
 }
 
 pub struct ChunkMesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
+    pub num_vertices: u32,
     pub num_elements: u32,
 }
 
+/// Chunk counts and sizes for the debug overlay
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChunkStats {
+    pub loaded: usize,
+    pub meshed: usize,
+    pub rendered: usize,
+    // Chunks waiting to be meshed
+    pub queued: usize,
+    // LOD meshes built and drawn
+    pub lods: usize,
+    pub lods_rendered: usize,
+    // Triangles in the meshes of rendered chunks, LOD meshes included
+    pub triangles: usize,
+    pub block_bytes: usize,
+    pub mesh_bytes: usize,
+}
+
 impl ChunkManager {
     pub fn new(chunks: HashMap<ChunkPosition, Chunk>) -> Self {
         Self {
             chunks,
             rebuild: HashSet::new(),
             render: HashSet::new(),
+            lods: HashMap::new(),
             render_dist: 2,
+            view_dist: 2,
+            camera_chunk: Vector3::new(0, 0, 0),
             old_chunk_pos: None,
         }
     }
 
-    pub fn default(width: i32) -> Self {
-        let mut chunks = HashMap::new();
-        for x in (-width / 2)..((width / 2) + 1) {
-            for z in (-width / 2)..((width / 2) + 1) {
-                let pos = Vector3::new(x, 0, z);
-                chunks.insert(pos, Chunk::full(0, pos));
-            }
-        }
-
-        Self::new(chunks)
-    }
-
-    pub fn update(&mut self, camera: &Camera, device: &wgpu::Device) {
-        let camera_chunk_pos: Vector3<i32> = (
-            if camera.position.x.is_sign_positive() {
-                ((camera.position.x + 8.0) / 16.0).floor() as i32
-            } else {
-                ((camera.position.x - 8.0) / 16.0).ceil() as i32
-            },
-            if camera.position.y.is_sign_positive() {
-                ((camera.position.y) / 16.0).floor() as i32
-            } else {
-                ((camera.position.y) / 16.0).ceil() as i32
-            },
-            if camera.position.z.is_sign_positive() {
-                ((camera.position.z + 8.0) / 16.0).floor() as i32
-            } else {
-                ((camera.position.z - 8.0) / 16.0).ceil() as i32
-            },
-        )
-            .into();
+    pub fn update(&mut self, camera: &Camera, palette: &Palette, device: &wgpu::Device) {
+        let _scope = profiler::scope("ChunkManager::update");
+        let camera_chunk_pos = chunk_position(camera.position);
 
         let old_chunk_pos = if let Some(op) = self.old_chunk_pos {
             op
@@ -95,9 +110,7 @@ impl ChunkManager {
         let render_dist = self.render_dist as i32;
         if old_chunk_pos != camera_chunk_pos {
             for x in (camera_chunk_pos.x - render_dist)..(camera_chunk_pos.x + render_dist + 1) {
-                for y in
-                    (camera_chunk_pos.y - render_dist).max(0)..(camera_chunk_pos.y + render_dist)
-                {
+                for y in self.y_range(camera_chunk_pos) {
                     for z in
                         (camera_chunk_pos.z - render_dist)..(camera_chunk_pos.z + render_dist + 1)
                     {
@@ -118,11 +131,13 @@ impl ChunkManager {
         }
 
         self.old_chunk_pos = Some(camera_chunk_pos);
+        self.camera_chunk = camera_chunk_pos;
 
-        self.rebuild_chunks(device);
+        self.rebuild_chunks(palette, device);
     }
 
-    pub fn rebuild_chunks(&mut self, device: &wgpu::Device) {
+    pub fn rebuild_chunks(&mut self, palette: &Palette, device: &wgpu::Device) {
+        let _scope = profiler::scope("rebuild_chunks");
         // Rebuild the mesh of chunks that were modified
         let positions = self.rebuild.clone();
 
@@ -131,8 +146,16 @@ impl ChunkManager {
             if rebuilt >= MAX_REBUILD_FRAME {
                 break;
             }
-            if let Some(chunk) = self.get_chunk_mut(&position) {
-                chunk.greedy_mesh(device);
+            if let Some(chunk) = self.get_chunk(&position) {
+                let data = {
+                    let _scope = profiler::scope("mesh_chunk");
+                    mesh_chunk(chunk, &self.neighbors(position), palette)
+                };
+                let mesh = data.upload(device);
+                if let Some(chunk) = self.get_chunk_mut(&position) {
+                    chunk.mesh = mesh;
+                    chunk.is_active = true;
+                }
                 rebuilt += 1;
             }
             self.rebuild.remove(&position);
@@ -146,6 +169,185 @@ impl ChunkManager {
         }
 
         self.chunks.insert(chunk.position, chunk);
+        // Picks up the new chunk on the next update
+        self.old_chunk_pos = None;
+    }
+
+    pub fn stats(&self) -> ChunkStats {
+        let mut stats = ChunkStats {
+            loaded: self.chunks.len(),
+            rendered: self.render.len(),
+            queued: self.rebuild.len(),
+            block_bytes: self.chunks.len() * std::mem::size_of::<Chunk>(),
+            ..ChunkStats::default()
+        };
+        for (position, chunk) in &self.chunks {
+            if let Some(mesh) = &chunk.mesh {
+                stats.meshed += 1;
+                stats.mesh_bytes += mesh.bytes();
+                if self.render.contains(position) {
+                    stats.triangles += mesh.num_elements as usize / 3;
+                }
+            }
+        }
+        stats.lods = self.lods.len();
+        for (position, lod) in &self.lods {
+            if let Some(mesh) = &lod.mesh {
+                stats.mesh_bytes += mesh.bytes();
+                if self.lod_range(*position) {
+                    stats.lods_rendered += 1;
+                    stats.triangles += mesh.num_elements as usize / 3;
+                }
+            }
+        }
+        stats
+    }
+
+    pub fn render_distance(&self) -> u16 {
+        self.render_dist
+    }
+
+    pub fn set_render_distance(&mut self, render_dist: u16) {
+        self.render_dist = render_dist;
+        // Forces the render list to be rebuilt on the next update
+        self.old_chunk_pos = None;
+    }
+
+    pub fn view_distance(&self) -> u16 {
+        self.view_dist
+    }
+
+    pub fn set_view_distance(&mut self, view_dist: u16) {
+        self.view_dist = view_dist;
+    }
+
+    // The chunk heights that are drawn around a camera in the chunk at `center`
+    fn y_range(&self, center: ChunkPosition) -> Range<i32> {
+        let render_dist = self.render_dist as i32;
+        (center.y - render_dist).max(0)..(center.y + render_dist)
+    }
+
+    // Whether a chunk is past the render distance but within the view
+    // distance, where it's drawn from its LOD mesh
+    fn lod_range(&self, position: ChunkPosition) -> bool {
+        let offset = position - self.camera_chunk;
+        let distance = offset.x.abs().max(offset.z.abs());
+        distance > self.render_dist as i32
+            && distance <= self.view_dist as i32
+            && self.y_range(self.camera_chunk).contains(&position.y)
+    }
+
+    /// The level of the LOD mesh built for a chunk, if there is one
+    pub fn lod(&self, position: ChunkPosition) -> Option<u32> {
+        self.lods.get(&position).map(|lod| lod.level)
+    }
+
+    /// Replaces the LOD mesh of a chunk, which doesn't have to be loaded
+    pub fn set_lod(&mut self, position: ChunkPosition, level: u32, mesh: Option<ChunkMesh>) {
+        self.lods.insert(position, LodMesh { level, mesh });
+    }
+
+    /// Drops the LOD meshes of chunks past the view distance
+    pub fn unload_lods(&mut self) {
+        let center = self.camera_chunk;
+        let view_dist = self.view_dist as i32;
+        self.lods.retain(|position, _| {
+            let offset = position - center;
+            offset.x.abs().max(offset.z.abs()) <= view_dist
+        });
+    }
+
+    /// Whether the block at a world position exists and is active
+    pub fn block_active(&self, block: Vector3<i32>) -> bool {
+        let (position, local) = block_to_chunk(block);
+        matches!(self.get_chunk(&position), Some(chunk) if chunk.block_active(local))
+    }
+
+    /// The block at a world position, if its chunk is loaded
+    pub fn block(&self, block: Vector3<i32>) -> Option<Block> {
+        let (position, local) = block_to_chunk(block);
+        self.get_chunk(&position)?.block(local)
+    }
+
+    /// Places a block at a world position if it's empty, queueing its chunk to
+    /// be remeshed. Returns false if the chunk isn't loaded or the spot is taken.
+    pub fn insert_block(&mut self, block: Block, position: Vector3<i32>) -> bool {
+        let (chunk_position, local) = block_to_chunk(position);
+        match self.chunks.get_mut(&chunk_position) {
+            Some(chunk) if chunk.block(local).is_none() => {
+                chunk.insert_block(block, local);
+                self.queue_rebuild(chunk_position, local);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the block at a world position, returning it
+    pub fn remove_block(&mut self, position: Vector3<i32>) -> Option<Block> {
+        let (chunk_position, local) = block_to_chunk(position);
+        let chunk = self.chunks.get_mut(&chunk_position)?;
+        let block = chunk.block(local)?;
+        chunk.remove_block(local);
+        self.queue_rebuild(chunk_position, local);
+        Some(block)
+    }
+
+    /// The loaded chunks around `position`, for meshing it
+    pub fn neighbors(&self, position: ChunkPosition) -> Neighbors<'_> {
+        Neighbors::find(position, |position| self.get_chunk(position))
+    }
+
+    // Queues the chunk of a changed block to be remeshed, along with any
+    // meshed neighbor whose faces on their shared side it hides or uncovers
+    fn queue_rebuild(&mut self, chunk_position: ChunkPosition, local: Vector3<usize>) {
+        self.rebuild.insert(chunk_position);
+        // Rebuilt from the changed blocks when the chunk is next far away
+        self.lods.remove(&chunk_position);
+        for axis in 0..3 {
+            let mut offset = Vector3::new(0, 0, 0);
+            offset[axis] = match local[axis] {
+                0 => -1,
+                l if l == CHUNK_SIZE - 1 => 1,
+                _ => continue,
+            };
+            let neighbor = chunk_position + offset;
+            if matches!(self.get_chunk(&neighbor), Some(chunk) if chunk.is_active) {
+                self.rebuild.insert(neighbor);
+            }
+        }
+    }
+
+    /// Applies every change in `edit`, then queues each chunk that changed,
+    /// and neighbors it touches, to be remeshed once. Also returns the blocks
+    /// that changed, to undo it.
+    pub fn apply_edit(&mut self, edit: &BlockEdit) -> (EditSummary, ChangeSet) {
+        let _scope = profiler::scope("ChunkManager::apply_edit");
+        let mut summary = EditSummary::default();
+        let mut changes = ChangeSet::new();
+        let mut changed_blocks = HashSet::new();
+        for (position, block) in edit.changes() {
+            let (chunk_position, local) = block_to_chunk(position);
+            match self.chunks.get_mut(&chunk_position) {
+                Some(chunk) => {
+                    let old = chunk.block(local);
+                    if chunk.set_block(local, block) {
+                        summary.changed += 1;
+                        changes.push(BlockChange {
+                            position,
+                            old,
+                            new: block,
+                        });
+                        changed_blocks.insert((chunk_position, local));
+                    }
+                }
+                None => summary.unloaded += 1,
+            }
+        }
+        for (chunk_position, local) in changed_blocks {
+            self.queue_rebuild(chunk_position, local);
+        }
+        (summary, changes)
     }
 
     pub fn get_chunk(&self, position: &ChunkPosition) -> Option<&Chunk> {
@@ -156,6 +358,14 @@ impl ChunkManager {
     }
 }
 
+impl ChunkMesh {
+    // Size of the vertex and index buffers
+    fn bytes(&self) -> usize {
+        self.num_vertices as usize * std::mem::size_of::<BlockVertex>()
+            + self.num_elements as usize * std::mem::size_of::<u32>()
+    }
+}
+
 impl Chunk {
     pub fn new(id: usize, position: ChunkPosition) -> Self {
         let blocks: [Option<Block>; CHUNK_3D_SIZE] = [None; CHUNK_3D_SIZE];
@@ -165,155 +375,7 @@ impl Chunk {
             is_active: false,
             blocks,
             mesh: None,
-        }
-    }
-
-    pub fn full(id: usize, position: ChunkPosition) -> Self {
-        let block = Block::new(0);
-        let blocks: [Option<Block>; CHUNK_3D_SIZE] = [Some(block); CHUNK_3D_SIZE];
-
-        Self {
-            id,
-            position,
-            is_active: false,
-            blocks,
-            mesh: None,
-        }
-    }
-
-    pub fn greedy_mesh(&mut self, device: &wgpu::Device) {
-        // Adapted from https://github.com/roboleary/GreedyMesh
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
-
-        let mut offset = 0;
-        for d in 0..3 {
-            let u = (d + 1) % 3;
-            let v = (d + 2) % 3;
-            let mut x: [f32; 3] = [0.0; 3];
-
-            // Determines the direction
-            let mut q: [f32; 3] = [0.0; 3];
-            q[d] = 1.0;
-
-            let size = CHUNK_SIZE as f32;
-            let mut mask: [bool; CHUNK_SIZE * CHUNK_SIZE] = [false; CHUNK_SIZE * CHUNK_SIZE];
-            x[d] = -1.0;
-            while x[d] < size {
-                // Compute the mask.
-                x[v] = 0.0;
-                x[u] = 0.0;
-                let mut n = 0;
-                while x[v] < size {
-                    while x[u] < size {
-                        let block_current = if 0.0 <= x[d] {
-                            self.block_active((x[0] as usize, x[1] as usize, x[2] as usize).into())
-                        } else {
-                            false
-                        };
-                        let block_compare = if x[d] < CHUNK_SIZE as f32 - 1.0 {
-                            self.block_active(
-                                (
-                                    (x[0] + q[0]) as usize,
-                                    (x[1] + q[1]) as usize,
-                                    (x[2] + q[2]) as usize,
-                                )
-                                    .into(),
-                            )
-                        } else {
-                            false
-                        };
-                        mask[n] = block_current != block_compare;
-                        n += 1;
-                        x[u] += 1.0;
-                    }
-                    x[v] += 1.0;
-                    x[u] = 0.0;
-                }
-                x[d] += 1.0;
-                n = 0;
-
-                let mut i;
-                for j in 0..CHUNK_SIZE {
-                    i = 0;
-                    while i < CHUNK_SIZE {
-                        if mask[n] {
-                            // Calculate width and height.
-                            let mut w = 1;
-                            while (i + w) < CHUNK_SIZE && mask[n + w] {
-                                w += 1;
-                            }
-
-                            let mut h = 1;
-                            'outer: while (j + h) < CHUNK_SIZE {
-                                for k in 0..w {
-                                    if !mask[n + k + h * CHUNK_SIZE] {
-                                        break 'outer;
-                                    }
-                                }
-                                h += 1;
-                            }
-
-                            x[u] = i as f32;
-                            x[v] = j as f32;
-
-                            let mut du: [f32; 3] = [0.0; 3];
-                            du[u] = w as f32;
-                            let mut dv: [f32; 3] = [0.0; 3];
-                            dv[v] = h as f32;
-
-                            let chunk_pos = self.position * CHUNK_SIZE as i32;
-                            let mut quad = Block::quad(
-                                Vector3::new(du[0], du[1], du[2]),
-                                Vector3::new(dv[0], dv[1], dv[2]),
-                                Vector3::new(
-                                    x[0] as i32 + chunk_pos.x,
-                                    x[1] as i32 + chunk_pos.y,
-                                    x[2] as i32 + chunk_pos.z,
-                                ),
-                                (q[0], q[1], q[2]).into(),
-                            );
-
-                            vertices.append(&mut quad.0);
-                            for val in &mut quad.1 {
-                                *val += offset;
-                            }
-                            offset += 4;
-                            indices.append(&mut quad.1);
-
-                            for l in 0..h {
-                                for k in 0..w {
-                                    mask[n + k + l * CHUNK_SIZE] = false;
-                                }
-                            }
-
-                            i += w;
-                            n += w;
-                        } else {
-                            i += 1;
-                            n += 1;
-                        }
-                    }
-                }
-            }
-        }
-        self.is_active = true;
-
-        if !vertices.is_empty() && !indices.is_empty() {
-            let vertex_buffer = device.create_buffer_with_data(
-                bytemuck::cast_slice(&vertices),
-                wgpu::BufferUsage::VERTEX,
-            );
-            let index_buffer = device
-                .create_buffer_with_data(bytemuck::cast_slice(&indices), wgpu::BufferUsage::INDEX);
-
-            self.mesh = Some(ChunkMesh {
-                vertex_buffer,
-                index_buffer,
-                num_elements: indices.len() as u32,
-            });
-        } else {
-            self.mesh = None;
+            modified: false,
         }
     }
 
@@ -327,6 +389,7 @@ impl Chunk {
             let index = ((x * CHUNK_SIZE + y) * CHUNK_SIZE) + z;
             if self.blocks[index].is_none() {
                 self.blocks[index] = Some(block);
+                self.modified = true;
             }
         }
     }
@@ -341,10 +404,45 @@ impl Chunk {
             let index = ((x * CHUNK_SIZE + y) * CHUNK_SIZE) + z;
             if self.blocks[index].is_some() {
                 self.blocks[index] = None;
+                self.modified = true;
             }
         }
     }
 
+    /// Replaces the block at `position`. Returns false if it was already the
+    /// same type, or empty when clearing.
+    pub fn set_block(&mut self, position: Vector3<usize>, block: Option<Block>) -> bool {
+        let limit = CHUNK_SIZE - 1;
+        if position.x > limit || position.y > limit || position.z > limit {
+            return false;
+        }
+        let index = ((position.x * CHUNK_SIZE + position.y) * CHUNK_SIZE) + position.z;
+        let same = match (self.blocks[index], block) {
+            (Some(old), Some(new)) => old.id() == new.id(),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            self.blocks[index] = block;
+            self.modified = true;
+        }
+        !same
+    }
+
+    pub fn block(&self, position: Vector3<usize>) -> Option<Block> {
+        let limit = CHUNK_SIZE - 1;
+        if position.x <= limit && position.y <= limit && position.z <= limit {
+            self.blocks[((position.x * CHUNK_SIZE + position.y) * CHUNK_SIZE) + position.z]
+        } else {
+            None
+        }
+    }
+
+    /// The block at `position` if there is one and it's active
+    pub fn active_block(&self, position: Vector3<usize>) -> Option<Block> {
+        self.block(position).filter(|block| block.is_active)
+    }
+
     pub fn block_active(&self, position: Vector3<usize>) -> bool {
         let x = position.x;
         let y = position.y;
@@ -361,6 +459,46 @@ impl Chunk {
     }
 }
 
+/// Splits a world block position into its chunk and the position within it.
+/// Chunks are centered on x and z, so chunk 0 covers -8..8 on those axes.
+pub fn block_to_chunk(block: Vector3<i32>) -> (ChunkPosition, Vector3<usize>) {
+    let size = CHUNK_SIZE as i32;
+    let offset = size / 2;
+    let chunk = Vector3::new(
+        (block.x + offset).div_euclid(size),
+        block.y.div_euclid(size),
+        (block.z + offset).div_euclid(size),
+    );
+    let local = Vector3::new(
+        (block.x + offset).rem_euclid(size) as usize,
+        block.y.rem_euclid(size) as usize,
+        (block.z + offset).rem_euclid(size) as usize,
+    );
+    (chunk, local)
+}
+
+/// The chunk containing a point in world space
+pub fn chunk_position(point: Point3<f32>) -> ChunkPosition {
+    (
+        if point.x.is_sign_positive() {
+            ((point.x + 8.0) / 16.0).floor() as i32
+        } else {
+            ((point.x - 8.0) / 16.0).ceil() as i32
+        },
+        if point.y.is_sign_positive() {
+            ((point.y) / 16.0).floor() as i32
+        } else {
+            ((point.y) / 16.0).ceil() as i32
+        },
+        if point.z.is_sign_positive() {
+            ((point.z + 8.0) / 16.0).floor() as i32
+        } else {
+            ((point.z - 8.0) / 16.0).ceil() as i32
+        },
+    )
+        .into()
+}
+
 pub trait DrawBlock<'a, 'b>
 where
     'b: 'a,
@@ -392,5 +530,15 @@ where
                 self.draw_mesh(mesh, uniforms);
             }
         }
+
+        // Drawn until they're replaced, even if the camera has since moved
+        // and they're at the wrong level
+        for (position, lod) in &chunk_manager.lods {
+            if let Some(mesh) = &lod.mesh {
+                if chunk_manager.lod_range(*position) {
+                    self.draw_mesh(mesh, uniforms);
+                }
+            }
+        }
     }
 }
@@ -1,5 +1,11 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::Duration;
 
+use super::shader;
+use crate::error::{Error, Result};
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -8,122 +14,310 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// How a pipeline tests against and writes to the depth buffer
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DepthState {
+    pub format: wgpu::TextureFormat,
+    pub write_enabled: bool,
+    pub compare: wgpu::CompareFunction,
+    // Constant added to each fragment's depth, negative to pull it towards the camera
+    pub bias: i32,
+}
+
+impl DepthState {
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            write_enabled: true,
+            compare: wgpu::CompareFunction::Less,
+            bias: 0,
+        }
+    }
+
+    pub fn with_bias(self, bias: i32) -> Self {
+        Self { bias, ..self }
+    }
+
+    /// Keeps the depth attachment bound without testing or writing to it
+    pub fn disabled(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            write_enabled: false,
+            compare: wgpu::CompareFunction::Always,
+            bias: 0,
+        }
+    }
+}
+
+/// Everything needed to build a render pipeline. Shaders are referred to by
+/// file name, e.g. `"shader.vert"`, and bind group layouts by their entries,
+/// the same ones given to `Graphics::bind_group_layout`.
+pub struct PipelineDesc<'a> {
+    pub bind_group_layouts: &'a [&'a [wgpu::BindGroupLayoutEntry]],
+    pub vertex_shader: &'static str,
+    pub fragment_shader: &'static str,
+    pub depth_state: Option<DepthState>,
+    // Blends with what's already drawn using the fragment's alpha
+    pub alpha_blend: bool,
+    pub primitive_topology: wgpu::PrimitiveTopology,
+    pub vertex_buffers: &'a [wgpu::VertexBufferDescriptor<'a>],
+}
+
+// The entries of a bind group layout, which don't implement Eq themselves
+type LayoutKey = Vec<(u32, wgpu::ShaderStage, wgpu::BindingType)>;
+
+fn layout_key(entries: &[wgpu::BindGroupLayoutEntry]) -> LayoutKey {
+    entries
+        .iter()
+        .map(|entry| (entry.binding, entry.visibility, entry.ty))
+        .collect()
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    bind_group_layouts: Vec<LayoutKey>,
+    vertex_shader: &'static str,
+    fragment_shader: &'static str,
+    depth_state: Option<DepthState>,
+    alpha_blend: bool,
+    primitive_topology: wgpu::PrimitiveTopology,
+    vertex_buffers: Vec<(
+        wgpu::BufferAddress,
+        wgpu::InputStepMode,
+        Vec<wgpu::VertexAttributeDescriptor>,
+    )>,
+}
+
+impl PipelineKey {
+    fn new(desc: &PipelineDesc) -> Self {
+        Self {
+            bind_group_layouts: desc
+                .bind_group_layouts
+                .iter()
+                .map(|entries| layout_key(entries))
+                .collect(),
+            vertex_shader: desc.vertex_shader,
+            fragment_shader: desc.fragment_shader,
+            depth_state: desc.depth_state,
+            alpha_blend: desc.alpha_blend,
+            primitive_topology: desc.primitive_topology,
+            vertex_buffers: desc
+                .vertex_buffers
+                .iter()
+                .map(|buffer| (buffer.stride, buffer.step_mode, buffer.attributes.to_vec()))
+                .collect(),
+        }
+    }
+}
+
 pub trait Render {
     fn update(&mut self, dt: Duration, graphics: &Graphics);
-    fn render(&mut self, graphics: &mut Graphics);
+    fn render(&mut self, graphics: &Graphics, frame: &wgpu::TextureView);
 }
 
-#[allow(dead_code)]
 pub struct Graphics {
     pub size: winit::dpi::PhysicalSize<u32>,
     adapter: wgpu::Adapter,
-    pub sc_desc: wgpu::SwapChainDescriptor,
-    pub swap_chain: wgpu::SwapChain,
+    pub format: wgpu::TextureFormat,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+
+    shader_modules: RefCell<HashMap<&'static str, Rc<wgpu::ShaderModule>>>,
+    bind_group_layouts: RefCell<HashMap<LayoutKey, Rc<wgpu::BindGroupLayout>>>,
+    pipelines: RefCell<HashMap<PipelineKey, Rc<wgpu::RenderPipeline>>>,
 }
 
 impl Graphics {
-    pub fn new(
+    pub async fn new(
         size: winit::dpi::PhysicalSize<u32>,
         adapter: wgpu::Adapter,
-        sc_desc: wgpu::SwapChainDescriptor,
-        swap_chain: wgpu::SwapChain,
-        device: wgpu::Device,
-        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
     ) -> Self {
+        // Request access to GPU
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                extensions: wgpu::Extensions {
+                    anisotropic_filtering: false,
+                },
+                limits: Default::default(),
+            })
+            .await;
+
         Self {
             size,
             adapter,
-            sc_desc,
-            swap_chain,
+            format,
             device,
             queue,
+            shader_modules: RefCell::new(HashMap::new()),
+            bind_group_layouts: RefCell::new(HashMap::new()),
+            pipelines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Creates graphics without a window, accepting any adapter including
+    /// software and fallback ones
+    pub async fn headless(width: u32, height: u32) -> Result<Self> {
+        let adapter = wgpu::Adapter::request(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::Default,
+                compatible_surface: None,
+            },
+            wgpu::BackendBit::PRIMARY | wgpu::BackendBit::SECONDARY,
+        )
+        .await
+        .ok_or(Error::NoAdapter)?;
+
+        let size = winit::dpi::PhysicalSize::new(width, height);
+        Ok(Self::new(size, adapter, wgpu::TextureFormat::Rgba8Unorm).await)
+    }
+
+    fn shader_module(&self, name: &'static str) -> Result<Rc<wgpu::ShaderModule>> {
+        if let Some(module) = self.shader_modules.borrow().get(name) {
+            return Ok(module.clone());
+        }
+
+        let spirv = shader::load_spirv(name)?;
+        let module = Rc::new(self.device.create_shader_module(&spirv));
+        self.shader_modules
+            .borrow_mut()
+            .insert(name, module.clone());
+        Ok(module)
+    }
+
+    /// Returns the bind group layout with these entries, creating it the first
+    /// time it's asked for. Bind groups for a pipeline have to be created with
+    /// layouts from here, so they match the ones the pipeline was built with.
+    pub fn bind_group_layout(
+        &self,
+        label: &'static str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Rc<wgpu::BindGroupLayout> {
+        let key = layout_key(entries);
+        if let Some(layout) = self.bind_group_layouts.borrow().get(&key) {
+            return layout.clone();
         }
+
+        let layout = Rc::new(self.device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                bindings: entries,
+                label: Some(label),
+            },
+        ));
+        self.bind_group_layouts
+            .borrow_mut()
+            .insert(key, layout.clone());
+        layout
     }
 
-    pub fn create_render_pipeline(
+    /// Returns a pipeline matching `desc`, only building it and its shader
+    /// modules the first time they're asked for
+    pub fn create_render_pipeline(&self, desc: &PipelineDesc) -> Result<Rc<wgpu::RenderPipeline>> {
+        let key = PipelineKey::new(desc);
+        if let Some(pipeline) = self.pipelines.borrow().get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        let vs_module = self.shader_module(desc.vertex_shader)?;
+        let fs_module = self.shader_module(desc.fragment_shader)?;
+
+        let bind_group_layouts: Vec<_> = desc
+            .bind_group_layouts
+            .iter()
+            .map(|entries| self.bind_group_layout("pipeline_bind_group_layout", entries))
+            .collect();
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &bind_group_layouts
+                    .iter()
+                    .map(|layout| layout.as_ref())
+                    .collect::<Vec<_>>(),
+            });
+
+        let pipeline = Rc::new(self.build_render_pipeline(desc, &layout, &vs_module, &fs_module));
+        self.pipelines.borrow_mut().insert(key, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    /// Drops cached modules and pipelines built from the shader `name` so the
+    /// next request recompiles it
+    #[cfg(feature = "dev")]
+    pub fn invalidate_shader(&self, name: &str) {
+        self.shader_modules.borrow_mut().remove(name);
+        self.pipelines
+            .borrow_mut()
+            .retain(|key, _| key.vertex_shader != name && key.fragment_shader != name);
+    }
+
+    fn build_render_pipeline(
         &self,
+        desc: &PipelineDesc,
         layout: &wgpu::PipelineLayout,
-        depth_format: Option<wgpu::TextureFormat>,
-        vertex_descs: &[wgpu::VertexBufferDescriptor],
-        vs_src: &str,
-        fs_src: &str,
+        vs_module: &wgpu::ShaderModule,
+        fs_module: &wgpu::ShaderModule,
     ) -> wgpu::RenderPipeline {
-        let mut compiler = shaderc::Compiler::new().unwrap();
-        let vs_spirv = compiler
-            .compile_into_spirv(
-                vs_src,
-                shaderc::ShaderKind::Vertex,
-                "shader.vert",
-                "main",
-                None,
-            )
-            .unwrap();
-        let fs_spirv = compiler
-            .compile_into_spirv(
-                fs_src,
-                shaderc::ShaderKind::Fragment,
-                "shader.frag",
-                "main",
-                None,
-            )
-            .unwrap();
-
-        let vs_data = wgpu::read_spirv(std::io::Cursor::new(vs_spirv.as_binary_u8())).unwrap();
-        let fs_data = wgpu::read_spirv(std::io::Cursor::new(fs_spirv.as_binary_u8())).unwrap();
-
-        let vs_module = self.device.create_shader_module(&vs_data);
-        let fs_module = self.device.create_shader_module(&fs_data);
+        let color_blend = if desc.alpha_blend {
+            wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            }
+        } else {
+            wgpu::BlendDescriptor::REPLACE
+        };
 
         self.device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                layout: &layout,
+                layout,
                 vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vs_module,
+                    module: vs_module,
                     entry_point: "main",
                 },
                 fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &fs_module,
+                    module: fs_module,
                     entry_point: "main",
                 }),
                 rasterization_state: Some(wgpu::RasterizationStateDescriptor {
                     front_face: wgpu::FrontFace::Ccw,
                     cull_mode: wgpu::CullMode::None,
-                    depth_bias: 0,
+                    depth_bias: desc.depth_state.map_or(0, |depth| depth.bias),
                     depth_bias_slope_scale: 0.0,
                     depth_bias_clamp: 0.0,
                 }),
-                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                primitive_topology: desc.primitive_topology,
                 color_states: &[wgpu::ColorStateDescriptor {
-                    format: self.sc_desc.format,
-                    color_blend: wgpu::BlendDescriptor::REPLACE,
+                    format: self.format,
+                    color_blend,
                     alpha_blend: wgpu::BlendDescriptor::REPLACE,
                     write_mask: wgpu::ColorWrite::ALL,
                 }],
-                depth_stencil_state: depth_format.map(|format| wgpu::DepthStencilStateDescriptor {
-                    format,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
-                    stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
-                    stencil_read_mask: 0,
-                    stencil_write_mask: 0,
+                depth_stencil_state: desc.depth_state.map(|depth| {
+                    wgpu::DepthStencilStateDescriptor {
+                        format: depth.format,
+                        depth_write_enabled: depth.write_enabled,
+                        depth_compare: depth.compare,
+                        stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                        stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                        stencil_read_mask: 0,
+                        stencil_write_mask: 0,
+                    }
                 }),
                 sample_count: 1,
                 sample_mask: !0,
                 alpha_to_coverage_enabled: false,
                 vertex_state: wgpu::VertexStateDescriptor {
                     index_format: wgpu::IndexFormat::Uint32,
-                    vertex_buffers: vertex_descs,
+                    vertex_buffers: desc.vertex_buffers,
                 },
             })
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>, surface: &wgpu::Surface) {
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.adapter.get_info()
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.size = new_size;
-        self.sc_desc.width = new_size.width;
-        self.sc_desc.height = new_size.height;
-        self.swap_chain = self.device.create_swap_chain(&surface, &self.sc_desc);
     }
 }
@@ -0,0 +1,47 @@
+use super::block::BlockType;
+
+// How much light each visible face of the cube gets
+const TOP_SHADE: f32 = 1.0;
+const LEFT_SHADE: f32 = 0.8;
+const RIGHT_SHADE: f32 = 0.6;
+
+/// Draws an isometric cube in the block's color, `size` pixels square, with
+/// the top and two side faces shaded differently
+pub fn block_icon(block_type: &BlockType, size: u32) -> image::RgbaImage {
+    let s = size as f32;
+    let top = (s / 2.0, 0.0);
+    let left = (0.0, s / 4.0);
+    let right = (s, s / 4.0);
+    let center = (s / 2.0, s / 2.0);
+    let left_bottom = (0.0, s * 0.75);
+    let right_bottom = (s, s * 0.75);
+    let bottom = (s / 2.0, s);
+
+    let faces = [
+        ([top, right, center, left], TOP_SHADE),
+        ([left, center, bottom, left_bottom], LEFT_SHADE),
+        ([center, right, right_bottom, bottom], RIGHT_SHADE),
+    ];
+
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let point = (x as f32 + 0.5, y as f32 + 0.5);
+        for (corners, shade) in faces.iter() {
+            if inside(corners, point) {
+                let [r, g, b] = block_type.color;
+                let channel = |c: f32| (c * shade * 255.0).round().clamp(0.0, 255.0) as u8;
+                return image::Rgba([channel(r), channel(g), channel(b), 255]);
+            }
+        }
+        image::Rgba([0, 0, 0, 0])
+    })
+}
+
+// Whether `point` is inside the convex polygon, with corners going clockwise
+// in screen space
+fn inside(corners: &[(f32, f32); 4], point: (f32, f32)) -> bool {
+    (0..corners.len()).all(|i| {
+        let a = corners[i];
+        let b = corners[(i + 1) % corners.len()];
+        (b.0 - a.0) * (point.1 - a.1) - (b.1 - a.1) * (point.0 - a.0) >= 0.0
+    })
+}
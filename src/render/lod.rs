@@ -0,0 +1,249 @@
+use cgmath::Vector3;
+
+use super::{
+    block::Palette,
+    chunk::{Chunk, CHUNK_SIZE},
+    mesh::{greedy_mesh, MeshData},
+};
+
+/// The coarsest level of detail, where each cell is 8 blocks across
+pub const MAX_LOD: u32 = 3;
+
+/// The level of detail for a chunk `distance` chunks from the camera. Chunks
+/// within the render distance are drawn in full, at level 0, and each level
+/// after that halves the resolution out to twice the distance of the last.
+pub fn lod_level(distance: i32, render_distance: i32) -> u32 {
+    let mut level = 0;
+    while level < MAX_LOD && distance > render_distance.max(1) << level {
+        level += 1;
+    }
+    level
+}
+
+/// The columns of chunks `distance` chunks from the center, as x and z
+/// offsets going around the square once
+pub fn ring(distance: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-distance..distance).flat_map(move |i| {
+        vec![
+            (i, -distance),
+            (distance, i),
+            (-i, distance),
+            (-distance, -i),
+        ]
+    })
+}
+
+/// A chunk's blocks at a lower resolution, as a cube of cells that are each
+/// `scale` blocks across
+pub struct LodGrid {
+    pub size: usize,
+    pub scale: usize,
+    // Block ids, indexed like the blocks of a chunk
+    pub cells: Vec<Option<usize>>,
+}
+
+impl LodGrid {
+    /// Downsamples a chunk to `level`. A cell is solid if any block in it is,
+    /// so each level covers everything the finer ones do and a coarser chunk
+    /// next to a finer one never leaves a hole. The cell takes the most common
+    /// block facing up in it, falling back to the most common one, so hills
+    /// keep the color of their surface rather than what's under it.
+    pub fn downsample(chunk: &Chunk, level: u32) -> Self {
+        let scale = 1 << level;
+        let size = CHUNK_SIZE / scale;
+        let mut cells = Vec::with_capacity(size * size * size);
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    // Block ids in the cell, with how many face up and in total
+                    let mut counts: Vec<(usize, usize, usize)> = Vec::new();
+                    for bx in x * scale..(x + 1) * scale {
+                        for by in y * scale..(y + 1) * scale {
+                            for bz in z * scale..(z + 1) * scale {
+                                let block = match chunk.active_block(Vector3::new(bx, by, bz)) {
+                                    Some(block) => block.id(),
+                                    None => continue,
+                                };
+                                let up = by + 1 == CHUNK_SIZE
+                                    || chunk.active_block(Vector3::new(bx, by + 1, bz)).is_none();
+                                match counts.iter_mut().find(|(id, _, _)| *id == block) {
+                                    Some((_, facing, total)) => {
+                                        *facing += up as usize;
+                                        *total += 1;
+                                    }
+                                    None => counts.push((block, up as usize, 1)),
+                                }
+                            }
+                        }
+                    }
+                    cells.push(
+                        counts
+                            .iter()
+                            .max_by_key(|(_, facing, total)| (*facing, *total))
+                            .map(|(id, _, _)| *id),
+                    );
+                }
+            }
+        }
+        Self { size, scale, cells }
+    }
+
+    pub fn cell(&self, position: [i32; 3]) -> Option<usize> {
+        let size = self.size as i32;
+        if position.iter().any(|&v| v < 0 || v >= size) {
+            return None;
+        }
+        let [x, y, z] = position.map(|v| v as usize);
+        self.cells[(x * self.size + y) * self.size + z]
+    }
+}
+
+/// Builds the mesh of a chunk downsampled to `level`. Unlike full detail
+/// chunks, every face on the chunk's sides is kept whatever is next to it.
+/// These close off the mesh as skirts, hiding the steps where chunks at two
+/// levels, or a LOD chunk and a full one, meet.
+pub fn mesh_lod(chunk: &Chunk, level: u32, palette: &Palette) -> MeshData {
+    let grid = LodGrid::downsample(chunk, level);
+    greedy_mesh(
+        chunk.position,
+        grid.size,
+        grid.scale,
+        palette,
+        |_, position| grid.cell(position),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{Block, DIRT, GRASS, STONE};
+    use crate::render::mesh::{mesh_chunk, Neighbors};
+    use crate::terrain::Generator;
+    use std::collections::HashSet;
+
+    fn chunk(blocks: impl Fn(Vector3<usize>) -> Option<usize>) -> Chunk {
+        let mut chunk = Chunk::new(0, Vector3::new(0, 0, 0));
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let local = Vector3::new(x, y, z);
+                    chunk.set_block(local, blocks(local).map(Block::new));
+                }
+            }
+        }
+        chunk
+    }
+
+    // The same every run
+    fn random_chunk() -> Chunk {
+        let mut state = 42u32;
+        let mut blocks = Vec::new();
+        for _ in 0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            // Sparse, so coarse cells aren't all solid
+            blocks.push(match state % 40 {
+                0 => Some(STONE),
+                _ => None,
+            });
+        }
+        chunk(|p| blocks[(p.x * CHUNK_SIZE + p.y) * CHUNK_SIZE + p.z])
+    }
+
+    #[test]
+    fn levels_double_with_distance() {
+        let levels: Vec<u32> = (0..=20).map(|distance| lod_level(distance, 2)).collect();
+        assert_eq!(&levels[..3], &[0, 0, 0]);
+        assert_eq!(&levels[3..5], &[1, 1]);
+        assert_eq!(&levels[5..9], &[2, 2, 2, 2]);
+        assert!(levels[9..].iter().all(|&level| level == MAX_LOD));
+    }
+
+    #[test]
+    fn ring_visits_each_column_once() {
+        for distance in 1..6 {
+            let columns: Vec<(i32, i32)> = ring(distance).collect();
+            let unique: HashSet<(i32, i32)> = columns.iter().copied().collect();
+            assert_eq!(columns.len(), unique.len());
+            assert_eq!(columns.len() as i32, distance * 8);
+            assert!(columns
+                .iter()
+                .all(|&(x, z)| x.abs().max(z.abs()) == distance));
+        }
+    }
+
+    #[test]
+    fn level_zero_matches_full_detail() {
+        let chunk = random_chunk();
+        let full = mesh_chunk(&chunk, &Neighbors::default(), &Palette::default());
+        let lod = mesh_lod(&chunk, 0, &Palette::default());
+        assert_eq!(full.faces, lod.faces);
+    }
+
+    #[test]
+    fn single_block_fills_its_cell() {
+        let chunk = chunk(|p| (p == Vector3::new(5, 9, 14)).then_some(STONE));
+        for level in 1..=MAX_LOD {
+            let scale = 1 << level;
+            let mesh = mesh_lod(&chunk, level, &Palette::default());
+            assert_eq!(mesh.faces.len(), 6);
+            assert!(mesh
+                .faces
+                .iter()
+                .all(|face| face.width == scale && face.height == scale));
+            let corner = Vector3::new(5, 9, 14).map(|v| v / scale as i32 * scale as i32);
+            assert!(mesh
+                .faces
+                .iter()
+                .filter(|face| !face.positive)
+                .all(|face| face.position == corner));
+        }
+    }
+
+    #[test]
+    fn coarser_levels_cover_finer_ones() {
+        let chunk = random_chunk();
+        for level in 0..MAX_LOD {
+            let fine = LodGrid::downsample(&chunk, level);
+            let coarse = LodGrid::downsample(&chunk, level + 1);
+            for x in 0..fine.size as i32 {
+                for y in 0..fine.size as i32 {
+                    for z in 0..fine.size as i32 {
+                        if fine.cell([x, y, z]).is_some() {
+                            assert!(coarse.cell([x / 2, y / 2, z / 2]).is_some());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cells_take_the_surface_block() {
+        // Grass over a dirt layer over stone, mostly stone in every cell
+        let chunk = chunk(|p| match p.y {
+            0..=10 => Some(STONE),
+            11 => Some(DIRT),
+            12 => Some(GRASS),
+            _ => None,
+        });
+        let grid = LodGrid::downsample(&chunk, 2);
+        assert_eq!(grid.cell([0, 2, 0]), Some(STONE));
+        assert_eq!(grid.cell([0, 3, 0]), Some(GRASS));
+        assert_eq!(grid.cell([1, 3, 2]), Some(GRASS));
+    }
+
+    #[test]
+    fn terrain_gets_cheaper_with_each_level() {
+        let chunk = Generator::new(42).generate(Vector3::new(0, 1, 0));
+        let mut faces = mesh_lod(&chunk, 0, &Palette::default()).faces.len();
+        for level in 1..=MAX_LOD {
+            let mesh = mesh_lod(&chunk, level, &Palette::default());
+            assert!(mesh.faces.len() < faces);
+            // The sides are closed, so the mesh is never empty
+            assert!(!mesh.faces.is_empty());
+            faces = mesh.faces.len();
+        }
+    }
+}
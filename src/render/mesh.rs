@@ -0,0 +1,505 @@
+use cgmath::Vector3;
+
+use super::{
+    block::{Block, BlockVertex, Palette},
+    chunk::{Chunk, ChunkMesh, ChunkPosition, CHUNK_SIZE},
+};
+
+/// A quad of a chunk's mesh, covering a rectangle of faces of one block type
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Face {
+    pub block: usize,
+    // The axis the face points along, 0 for x, 1 for y and 2 for z, and
+    // whether it points towards positive
+    pub axis: usize,
+    pub positive: bool,
+    // The quad's lowest corner within the chunk, in blocks. Along `axis` this
+    // is the plane the face lies on, so a face on the far side of the chunk
+    // is at CHUNK_SIZE.
+    pub position: Vector3<i32>,
+    // Blocks covered along the next two axes, `(axis + 1) % 3` then
+    // `(axis + 2) % 3`
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A mesh built on the CPU, ready to be uploaded. Each face is a quad of four
+/// vertices and six indices, in the same order as `faces`.
+#[derive(Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<BlockVertex>,
+    pub indices: Vec<u32>,
+    pub faces: Vec<Face>,
+}
+
+impl MeshData {
+    /// Adds the quad for `face` of the chunk at `chunk`, in `color`
+    pub fn push_face(&mut self, face: Face, chunk: ChunkPosition, color: [f32; 3]) {
+        let u = (face.axis + 1) % 3;
+        let v = (face.axis + 2) % 3;
+        let mut width = Vector3::new(0.0, 0.0, 0.0);
+        width[u] = face.width as f32;
+        let mut height = Vector3::new(0.0, 0.0, 0.0);
+        height[v] = face.height as f32;
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[face.axis] = if face.positive { 1.0 } else { -1.0 };
+
+        let (mut vertices, indices) = Block::quad(
+            width,
+            height,
+            chunk * CHUNK_SIZE as i32 + face.position,
+            normal,
+            color,
+        );
+        let offset = self.vertices.len() as u32;
+        self.vertices.append(&mut vertices);
+        self.indices
+            .extend(indices.iter().map(|index| index + offset));
+        self.faces.push(face);
+    }
+
+    /// Adds another mesh's faces after this one's
+    pub fn append(&mut self, mut other: MeshData) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.append(&mut other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|index| index + offset));
+        self.faces.append(&mut other.faces);
+    }
+
+    /// Creates the GPU buffers for the mesh, or None when it's empty and
+    /// there's nothing to draw
+    pub fn upload(&self, device: &wgpu::Device) -> Option<ChunkMesh> {
+        if self.vertices.is_empty() || self.indices.is_empty() {
+            return None;
+        }
+        let vertex_buffer = device.create_buffer_with_data(
+            bytemuck::cast_slice(&self.vertices),
+            wgpu::BufferUsage::VERTEX,
+        );
+        let index_buffer = device.create_buffer_with_data(
+            bytemuck::cast_slice(&self.indices),
+            wgpu::BufferUsage::INDEX,
+        );
+
+        Some(ChunkMesh {
+            vertex_buffer,
+            index_buffer,
+            num_vertices: self.vertices.len() as u32,
+            num_elements: self.indices.len() as u32,
+        })
+    }
+}
+
+/// The chunks next to the one being meshed. Faces against a block in a
+/// neighbor are hidden, while faces on a side without a neighbor are kept.
+#[derive(Copy, Clone, Default)]
+pub struct Neighbors<'a> {
+    // -x, +x, -y, +y, -z then +z
+    chunks: [Option<&'a Chunk>; 6],
+}
+
+impl<'a> Neighbors<'a> {
+    /// The chunks around `position` that `get` finds
+    pub fn find(
+        position: ChunkPosition,
+        get: impl Fn(&ChunkPosition) -> Option<&'a Chunk>,
+    ) -> Self {
+        let mut chunks = [None; 6];
+        for (side, chunk) in chunks.iter_mut().enumerate() {
+            let mut offset = Vector3::new(0, 0, 0);
+            offset[side / 2] = if side % 2 == 0 { -1 } else { 1 };
+            *chunk = get(&(position + offset));
+        }
+        Self { chunks }
+    }
+
+    // The block at a position just outside the chunk, past one of its sides
+    fn block(&self, axis: usize, position: [i32; 3]) -> Option<Block> {
+        let size = CHUNK_SIZE as i32;
+        let side = axis * 2 + (position[axis] >= size) as usize;
+        let local = position.map(|v| v.rem_euclid(size) as usize);
+        self.chunks[side]?.active_block(local.into())
+    }
+}
+
+// The active block at a position relative to the chunk, which may be one
+// step outside it along `axis`
+fn block_at(
+    chunk: &Chunk,
+    neighbors: &Neighbors,
+    axis: usize,
+    position: [i32; 3],
+) -> Option<Block> {
+    if position[axis] < 0 || position[axis] >= CHUNK_SIZE as i32 {
+        neighbors.block(axis, position)
+    } else {
+        chunk.active_block(position.map(|v| v as usize).into())
+    }
+}
+
+/// Builds a chunk's mesh in world space, merging faces of the same block type
+/// into as few quads as possible. Faces between two blocks aren't drawn, even
+/// across the chunk's sides when `neighbors` has the chunk there. Colors come
+/// from the world's `palette`.
+pub fn mesh_chunk(chunk: &Chunk, neighbors: &Neighbors, palette: &Palette) -> MeshData {
+    greedy_mesh(chunk.position, CHUNK_SIZE, 1, palette, |axis, position| {
+        block_at(chunk, neighbors, axis, position).map(|block| block.id())
+    })
+}
+
+/// Greedy meshes a cube of `size` cells across, each `scale` blocks wide, for
+/// the chunk at `chunk`. `cell` gives the block id filling a cell, and is also
+/// asked for cells one step outside the cube along `axis`, whose faces are
+/// left for whoever meshes them.
+pub(super) fn greedy_mesh(
+    chunk: ChunkPosition,
+    size: usize,
+    scale: usize,
+    palette: &Palette,
+    cell: impl Fn(usize, [i32; 3]) -> Option<usize>,
+) -> MeshData {
+    // Adapted from https://github.com/roboleary/GreedyMesh
+    let mut mesh = MeshData::default();
+    for axis in 0..3 {
+        let u = (axis + 1) % 3;
+        let v = (axis + 2) % 3;
+
+        // Each plane lies between the cells at `plane - 1` and `plane`
+        for plane in 0..=size as i32 {
+            // The id of the block each visible face on the plane belongs to,
+            // and whether it faces towards positive
+            let mut mask: [Option<(usize, bool)>; CHUNK_SIZE * CHUNK_SIZE] =
+                [None; CHUNK_SIZE * CHUNK_SIZE];
+            let mut n = 0;
+            for j in 0..size as i32 {
+                for i in 0..size as i32 {
+                    let mut front = [0; 3];
+                    front[axis] = plane;
+                    front[u] = i;
+                    front[v] = j;
+                    let mut back = front;
+                    back[axis] -= 1;
+                    // Faces of cells outside are theirs to draw
+                    mask[n] = match (cell(axis, back), cell(axis, front)) {
+                        (Some(block), None) if plane > 0 => Some((block, true)),
+                        (None, Some(block)) if plane < size as i32 => Some((block, false)),
+                        _ => None,
+                    };
+                    n += 1;
+                }
+            }
+
+            n = 0;
+            for j in 0..size {
+                let mut i = 0;
+                while i < size {
+                    let (block, positive) = match mask[n] {
+                        Some(face) => face,
+                        None => {
+                            i += 1;
+                            n += 1;
+                            continue;
+                        }
+                    };
+
+                    // Calculate width and height.
+                    let mut w = 1;
+                    while (i + w) < size && mask[n + w] == mask[n] {
+                        w += 1;
+                    }
+                    let mut h = 1;
+                    'outer: while (j + h) < size {
+                        for k in 0..w {
+                            if mask[n + k + h * size] != mask[n] {
+                                break 'outer;
+                            }
+                        }
+                        h += 1;
+                    }
+
+                    let mut position = Vector3::new(0, 0, 0);
+                    position[axis] = plane;
+                    position[u] = i as i32;
+                    position[v] = j as i32;
+                    mesh.push_face(
+                        Face {
+                            block,
+                            axis,
+                            positive,
+                            position: position * scale as i32,
+                            width: (w * scale) as u32,
+                            height: (h * scale) as u32,
+                        },
+                        chunk,
+                        palette.block_type(block).color,
+                    );
+
+                    for l in 0..h {
+                        for k in 0..w {
+                            mask[n + k + l * size] = None;
+                        }
+                    }
+                    i += w;
+                    n += w;
+                }
+            }
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::block::{DIRT, STONE};
+    use std::collections::{HashMap, HashSet};
+
+    fn chunk(position: ChunkPosition, blocks: impl Fn(Vector3<usize>) -> Option<usize>) -> Chunk {
+        let mut chunk = Chunk::new(0, position);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let local = Vector3::new(x, y, z);
+                    chunk.set_block(local, blocks(local).map(Block::new));
+                }
+            }
+        }
+        chunk
+    }
+
+    fn origin() -> ChunkPosition {
+        Vector3::new(0, 0, 0)
+    }
+
+    // A unit face: the block it belongs to and the direction it points in,
+    // as an axis and whether it's positive
+    type UnitFace = (Vector3<i32>, usize, bool);
+
+    // Splits every quad into the block faces it covers, checking that none is
+    // covered twice
+    fn unit_faces(mesh: &MeshData) -> HashSet<UnitFace> {
+        let mut faces = HashSet::new();
+        for face in &mesh.faces {
+            let u = (face.axis + 1) % 3;
+            let v = (face.axis + 2) % 3;
+            for i in 0..face.width as i32 {
+                for j in 0..face.height as i32 {
+                    let mut block = face.position;
+                    block[u] += i;
+                    block[v] += j;
+                    if face.positive {
+                        block[face.axis] -= 1;
+                    }
+                    assert!(faces.insert((block, face.axis, face.positive)));
+                }
+            }
+        }
+        faces
+    }
+
+    // Every side of a block that's against air or, without a neighbor there,
+    // the chunk's edge, found one block at a time
+    fn exposed_faces(chunk: &Chunk, solid_outside: bool) -> HashSet<UnitFace> {
+        let size = CHUNK_SIZE as i32;
+        let solid = |p: Vector3<i32>| {
+            if (0..3).all(|axis| p[axis] >= 0 && p[axis] < size) {
+                chunk.active_block(p.map(|v| v as usize)).is_some()
+            } else {
+                solid_outside
+            }
+        };
+        let mut faces = HashSet::new();
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let block = Vector3::new(x, y, z);
+                    if !solid(block) {
+                        continue;
+                    }
+                    for axis in 0..3 {
+                        for &positive in &[false, true] {
+                            let mut next = block;
+                            next[axis] += if positive { 1 } else { -1 };
+                            if !solid(next) {
+                                faces.insert((block, axis, positive));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        faces
+    }
+
+    // The quads' vertices have to match their faces, so the mesh that's drawn
+    // has the same surface that's checked
+    fn check_vertices(mesh: &MeshData, chunk: ChunkPosition) {
+        assert_eq!(mesh.vertices.len(), mesh.faces.len() * 4);
+        assert_eq!(mesh.indices.len(), mesh.faces.len() * 6);
+        assert!(mesh
+            .indices
+            .iter()
+            .all(|&index| (index as usize) < mesh.vertices.len()));
+
+        let palette = Palette::default();
+        let offset = Vector3::new(CHUNK_SIZE as f32 / 2.0, 0.0, CHUNK_SIZE as f32 / 2.0);
+        let origin = (chunk * CHUNK_SIZE as i32).map(|v| v as f32) - offset;
+        for (face, vertices) in mesh.faces.iter().zip(mesh.vertices.chunks(4)) {
+            let mut normal = [0.0; 3];
+            normal[face.axis] = if face.positive { 1.0 } else { -1.0 };
+            let corner = origin + face.position.map(|v| v as f32);
+            let mut area = [f32::MAX, f32::MIN, f32::MAX, f32::MIN];
+            for vertex in vertices {
+                assert_eq!(vertex.normal, normal);
+                assert_eq!(vertex.color, palette.block_type(face.block).color);
+                assert_eq!(vertex.position[face.axis], corner[face.axis]);
+                for (k, &axis) in [(face.axis + 1) % 3, (face.axis + 2) % 3]
+                    .iter()
+                    .enumerate()
+                {
+                    area[k * 2] = area[k * 2].min(vertex.position[axis] - corner[axis]);
+                    area[k * 2 + 1] = area[k * 2 + 1].max(vertex.position[axis] - corner[axis]);
+                }
+            }
+            assert_eq!(area, [0.0, face.width as f32, 0.0, face.height as f32]);
+        }
+    }
+
+    fn check_watertight(chunk: &Chunk, neighbors: &Neighbors, solid_outside: bool) -> MeshData {
+        let mesh = mesh_chunk(chunk, neighbors, &Palette::default());
+        check_vertices(&mesh, chunk.position);
+        assert_eq!(unit_faces(&mesh), exposed_faces(chunk, solid_outside));
+        mesh
+    }
+
+    #[test]
+    fn empty_chunk_has_no_faces() {
+        let mesh = mesh_chunk(
+            &chunk(origin(), |_| None),
+            &Neighbors::default(),
+            &Palette::default(),
+        );
+        assert!(mesh.faces.is_empty());
+        assert!(mesh.vertices.is_empty());
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let single = chunk(origin(), |p| (p == Vector3::new(3, 4, 5)).then_some(STONE));
+        let mesh = check_watertight(&single, &Neighbors::default(), false);
+        assert_eq!(mesh.faces.len(), 6);
+    }
+
+    #[test]
+    fn full_chunk_merges_each_side() {
+        let full = chunk(origin(), |_| Some(STONE));
+        let mesh = check_watertight(&full, &Neighbors::default(), false);
+        assert_eq!(mesh.faces.len(), 6);
+        assert!(mesh
+            .faces
+            .iter()
+            .all(|face| face.width == 16 && face.height == 16));
+    }
+
+    #[test]
+    fn different_blocks_do_not_merge() {
+        let pair = |second| {
+            chunk(origin(), move |p| match (p.x, p.y, p.z) {
+                (0, 0, 0) => Some(STONE),
+                (1, 0, 0) => Some(second),
+                _ => None,
+            })
+        };
+        let same = check_watertight(&pair(STONE), &Neighbors::default(), false);
+        assert_eq!(same.faces.len(), 6);
+        let different = check_watertight(&pair(DIRT), &Neighbors::default(), false);
+        assert_eq!(different.faces.len(), 10);
+    }
+
+    #[test]
+    fn checkerboard_has_a_face_per_side() {
+        let checkerboard = chunk(origin(), |p| ((p.x + p.y + p.z) % 2 == 0).then_some(STONE));
+        let mesh = check_watertight(&checkerboard, &Neighbors::default(), false);
+        assert_eq!(
+            mesh.faces.len(),
+            CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE / 2 * 6
+        );
+    }
+
+    #[test]
+    fn random_blocks_are_watertight() {
+        let mut state = 0x2545_f491_u32;
+        let mut blocks = Vec::new();
+        for _ in 0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            blocks.push(match state % 4 {
+                0 => Some(STONE),
+                1 => Some(DIRT),
+                _ => None,
+            });
+        }
+        let random = chunk(origin(), |p| {
+            blocks[(p.x * CHUNK_SIZE + p.y) * CHUNK_SIZE + p.z]
+        });
+        check_watertight(&random, &Neighbors::default(), false);
+    }
+
+    #[test]
+    fn neighbors_hide_border_faces() {
+        let mut chunks = HashMap::new();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let position = Vector3::new(x, y, z);
+                    chunks.insert(position, chunk(position, |_| Some(STONE)));
+                }
+            }
+        }
+        let neighbors = Neighbors::find(origin(), |position| chunks.get(position));
+        let mesh = check_watertight(&chunks[&origin()], &neighbors, true);
+        assert!(mesh.faces.is_empty());
+
+        // A tunnel through the middle opens up faces along it, but the ends
+        // still face solid neighbors
+        let tunnel = chunk(origin(), |p| (p.x != 8 || p.y != 8).then_some(STONE));
+        let mesh = check_watertight(&tunnel, &neighbors, true);
+        assert_eq!(mesh.faces.len(), 4);
+    }
+
+    #[test]
+    fn neighbor_block_shares_a_border() {
+        let left = chunk(origin(), |p| (p == Vector3::new(15, 0, 0)).then_some(STONE));
+        let right_position = Vector3::new(1, 0, 0);
+        let right = chunk(right_position, |p| {
+            (p == Vector3::new(0, 0, 0)).then_some(STONE)
+        });
+        let chunks: HashMap<_, _> = vec![(origin(), left), (right_position, right)]
+            .into_iter()
+            .collect();
+
+        let left_mesh = mesh_chunk(
+            &chunks[&origin()],
+            &Neighbors::find(origin(), |p| chunks.get(p)),
+            &Palette::default(),
+        );
+        let right_mesh = mesh_chunk(
+            &chunks[&right_position],
+            &Neighbors::find(right_position, |p| chunks.get(p)),
+            &Palette::default(),
+        );
+        assert_eq!(left_mesh.faces.len(), 5);
+        assert_eq!(right_mesh.faces.len(), 5);
+        // Neither draws the side they share
+        assert!(!left_mesh
+            .faces
+            .iter()
+            .any(|face| face.axis == 0 && face.positive));
+        assert!(!right_mesh
+            .faces
+            .iter()
+            .any(|face| face.axis == 0 && !face.positive));
+    }
+}
@@ -2,6 +2,15 @@ pub mod block;
 pub mod camera;
 pub mod chunk;
 pub mod graphics;
+pub mod icon;
+pub mod lod;
+pub mod mesh;
+pub mod outline;
+pub mod quad;
+pub mod screen;
+pub mod shader;
+pub mod sky;
+pub mod target;
 pub mod texture;
 pub mod txt;
 
@@ -14,6 +23,13 @@ pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a>;
 }
 
+/// Bindings of the camera uniforms, the first bind group of every world pass
+pub const UNIFORM_BINDINGS: &[wgpu::BindGroupLayoutEntry] = &[wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStage::VERTEX,
+    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+}];
+
 #[derive(Copy, Clone)]
 pub struct Uniforms {
     view_position: Vector4<f32>,
@@ -23,6 +39,12 @@ pub struct Uniforms {
 unsafe impl bytemuck::Pod for Uniforms {}
 unsafe impl bytemuck::Zeroable for Uniforms {}
 
+impl Default for Uniforms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Uniforms {
     pub fn new() -> Self {
         Self {
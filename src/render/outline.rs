@@ -0,0 +1,279 @@
+use cgmath::Vector3;
+use std::rc::Rc;
+
+use crate::edit::Region;
+use crate::error::Result;
+
+use super::graphics::{DepthState, Graphics, PipelineDesc};
+use super::texture::Texture;
+use super::{Vertex, UNIFORM_BINDINGS};
+
+const OUTLINE_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const CRACK_COLOR: [f32; 4] = [0.05, 0.05, 0.05, 0.85];
+const SELECTION_COLOR: [f32; 4] = [1.0, 0.8, 0.3, 0.9];
+
+// Pulls the lines in front of the block's faces. Vulkan ignores depth bias for
+// lines, so the box is also grown slightly past the block.
+const DEPTH_BIAS: i32 = -16;
+const EXPAND: f32 = 0.002;
+
+// Break progress is shown in this many steps, each adding more cracks
+const CRACK_STAGES: u32 = 8;
+const CRACK_SEGMENTS: u32 = 3;
+const CRACK_SEGMENT_LENGTH: f32 = 0.14;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct OutlineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for OutlineVertex {}
+unsafe impl bytemuck::Zeroable for OutlineVertex {}
+
+impl Vertex for OutlineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        use std::mem;
+        wgpu::VertexBufferDescriptor {
+            stride: mem::size_of::<OutlineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float4,
+                },
+            ],
+        }
+    }
+}
+
+/// A wireframe box around the targeted block, with cracks across its faces
+/// showing how far it is from breaking, and another around the selected region
+pub struct Outline {
+    // The block and crack stage the vertex buffer was built for
+    shown: Option<(Vector3<i32>, u32)>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    num_vertices: u32,
+
+    selection: Option<Region>,
+    selection_buffer: Option<wgpu::Buffer>,
+
+    pipeline: Rc<wgpu::RenderPipeline>,
+}
+
+impl Outline {
+    pub fn new(graphics: &Graphics) -> Result<Self> {
+        let pipeline = Self::create_pipeline(graphics)?;
+
+        Ok(Self {
+            shown: None,
+            vertex_buffer: None,
+            num_vertices: 0,
+            selection: None,
+            selection_buffer: None,
+            pipeline,
+        })
+    }
+
+    fn create_pipeline(graphics: &Graphics) -> Result<Rc<wgpu::RenderPipeline>> {
+        let depth_state = DepthState {
+            write_enabled: false,
+            compare: wgpu::CompareFunction::LessEqual,
+            ..DepthState::new(Texture::DEPTH_FORMAT)
+        };
+        graphics.create_render_pipeline(&PipelineDesc {
+            bind_group_layouts: &[UNIFORM_BINDINGS],
+            vertex_shader: "outline.vert",
+            fragment_shader: "outline.frag",
+            depth_state: Some(depth_state.with_bias(DEPTH_BIAS)),
+            alpha_blend: true,
+            primitive_topology: wgpu::PrimitiveTopology::LineList,
+            vertex_buffers: &[OutlineVertex::desc()],
+        })
+    }
+
+    /// Recompiles the outline shaders, keeping the current pipeline if that fails
+    #[cfg(feature = "dev")]
+    pub fn reload(&mut self, graphics: &Graphics) -> Result<()> {
+        self.pipeline = Self::create_pipeline(graphics)?;
+        Ok(())
+    }
+
+    /// Moves the outline to `block`, or hides it, with `progress` from 0.0 to
+    /// 1.0 deciding how cracked it looks
+    pub fn update(&mut self, graphics: &Graphics, block: Option<Vector3<i32>>, progress: f32) {
+        let stage = (progress.clamp(0.0, 1.0) * CRACK_STAGES as f32) as u32;
+        let shown = block.map(|block| (block, stage));
+        if shown == self.shown {
+            return;
+        }
+        self.shown = shown;
+
+        let (block, stage) = match shown {
+            Some(shown) => shown,
+            None => {
+                self.vertex_buffer = None;
+                self.num_vertices = 0;
+                return;
+            }
+        };
+
+        let origin = Vector3::new(block.x as f32, block.y as f32, block.z as f32);
+        let mut vertices = box_lines(origin, Vector3::new(1.0, 1.0, 1.0), OUTLINE_COLOR);
+        crack_lines(block, stage, &mut vertices);
+        self.num_vertices = vertices.len() as u32;
+        self.vertex_buffer =
+            Some(graphics.device.create_buffer_with_data(
+                bytemuck::cast_slice(&vertices),
+                wgpu::BufferUsage::VERTEX,
+            ));
+    }
+
+    /// Moves the selection box to `region`, or hides it
+    pub fn update_selection(&mut self, graphics: &Graphics, region: Option<Region>) {
+        if region == self.selection {
+            return;
+        }
+        self.selection = region;
+        self.selection_buffer = region.map(|region| {
+            let min = region.min.map(|v| v as f32);
+            let size = region.size().map(|v| v as f32);
+            let vertices = box_lines(min, size, SELECTION_COLOR);
+            graphics
+                .device
+                .create_buffer_with_data(bytemuck::cast_slice(&vertices), wgpu::BufferUsage::VERTEX)
+        });
+    }
+}
+
+// The edges of a box from `origin` spanning `size`, grown by `EXPAND`
+fn box_lines(origin: Vector3<f32>, size: Vector3<f32>, color: [f32; 4]) -> Vec<OutlineVertex> {
+    let min = origin - Vector3::new(EXPAND, EXPAND, EXPAND);
+    let size = size + Vector3::new(EXPAND, EXPAND, EXPAND) * 2.0;
+    let corner = |i: usize| OutlineVertex {
+        position: [
+            min.x + (i & 1) as f32 * size.x,
+            min.y + ((i >> 1) & 1) as f32 * size.y,
+            min.z + ((i >> 2) & 1) as f32 * size.z,
+        ],
+        color,
+    };
+
+    // Pairs of corners that differ in exactly one axis
+    const EDGES: [(usize, usize); 12] = [
+        (0, 1),
+        (2, 3),
+        (4, 5),
+        (6, 7),
+        (0, 2),
+        (1, 3),
+        (4, 6),
+        (5, 7),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ];
+    EDGES
+        .iter()
+        .flat_map(|&(a, b)| vec![corner(a), corner(b)])
+        .collect()
+}
+
+/// Adds `stage` jagged cracks to each face of the block, spreading out from
+/// the face's center. The same block always cracks the same way.
+fn crack_lines(block: Vector3<i32>, stage: u32, vertices: &mut Vec<OutlineVertex>) {
+    let origin = Vector3::new(block.x as f32, block.y as f32, block.z as f32);
+    for axis in 0..3 {
+        // The two axes spanning the face
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in 0..2 {
+            let face = (axis * 2 + side) as u64;
+            let to_world = |a: f32, b: f32| {
+                let mut position = origin;
+                position[axis] += side as f32 * (1.0 + EXPAND * 2.0) - EXPAND;
+                position[u] += a;
+                position[v] += b;
+                position.into()
+            };
+
+            for crack in 0..stage {
+                let mut seed = hash(block, face * CRACK_STAGES as u64 + crack as u64);
+                let mut angle = random(&mut seed) * std::f32::consts::PI * 2.0;
+                let mut point = (0.5, 0.5);
+                for _ in 0..CRACK_SEGMENTS {
+                    angle += (random(&mut seed) - 0.5) * 1.5;
+                    let length = CRACK_SEGMENT_LENGTH * (0.6 + random(&mut seed) * 0.8);
+                    let next = (
+                        (point.0 + angle.cos() * length).clamp(0.02, 0.98),
+                        (point.1 + angle.sin() * length).clamp(0.02, 0.98),
+                    );
+                    vertices.push(OutlineVertex {
+                        position: to_world(point.0, point.1),
+                        color: CRACK_COLOR,
+                    });
+                    vertices.push(OutlineVertex {
+                        position: to_world(next.0, next.1),
+                        color: CRACK_COLOR,
+                    });
+                    point = next;
+                }
+            }
+        }
+    }
+}
+
+fn hash(block: Vector3<i32>, salt: u64) -> u64 {
+    let mut h = salt.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    h ^= (block.x as u32 as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= (block.y as u32 as u64).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= (block.z as u32 as u64).wrapping_mul(0xff51_afd7_ed55_8ccd) << 1;
+    h
+}
+
+// Advances `seed` and returns a value in [0, 1)
+fn random(seed: &mut u64) -> f32 {
+    // splitmix64
+    *seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut h = *seed;
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+pub trait DrawOutline<'a, 'b>
+where
+    'b: 'a,
+{
+    fn draw_outline(&mut self, outline: &'b Outline, uniforms: &'b wgpu::BindGroup);
+}
+
+impl<'a, 'b> DrawOutline<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_outline(&mut self, outline: &'b Outline, uniforms: &'b wgpu::BindGroup) {
+        if outline.vertex_buffer.is_none() && outline.selection_buffer.is_none() {
+            return;
+        }
+        self.set_pipeline(&outline.pipeline);
+        self.set_bind_group(0, uniforms, &[]);
+        if let Some(vertex_buffer) = &outline.vertex_buffer {
+            self.set_vertex_buffer(0, vertex_buffer, 0, 0);
+            self.draw(0..outline.num_vertices, 0..1);
+        }
+        if let Some(selection_buffer) = &outline.selection_buffer {
+            self.set_vertex_buffer(0, selection_buffer, 0, 0);
+            // Always the 12 edges of a box
+            self.draw(0..24, 0..1);
+        }
+    }
+}
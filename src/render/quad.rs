@@ -0,0 +1,274 @@
+use std::rc::Rc;
+
+use super::graphics::{Graphics, PipelineDesc};
+use super::texture::Texture;
+use super::Vertex;
+use crate::error::Result;
+
+// A texture and its sampler, one bind group per texture
+const QUAD_BINDINGS: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+            component_type: wgpu::TextureComponentType::Float,
+        },
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: false },
+    },
+];
+
+/// A rectangle in pixels from the top left of the screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.w / 2.0, self.y + self.h / 2.0)
+    }
+
+    /// Shrinks the rectangle by `amount` on every side
+    pub fn inset(&self, amount: f32) -> Self {
+        Self::new(
+            self.x + amount,
+            self.y + amount,
+            (self.w - amount * 2.0).max(0.0),
+            (self.h - amount * 2.0).max(0.0),
+        )
+    }
+}
+
+/// A texture registered with the `QuadRenderer`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureId(usize);
+
+impl TextureId {
+    // A single white pixel, for quads that are only colored
+    pub const WHITE: TextureId = TextureId(0);
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Quad {
+    pub rect: Rect,
+    pub texture: TextureId,
+    // The part of the texture to show, in texture coordinates
+    pub tex_coords: Rect,
+    // Multiplied with the texture
+    pub color: [f32; 4],
+}
+
+impl Quad {
+    pub fn colored(rect: Rect, color: [f32; 4]) -> Self {
+        Self {
+            rect,
+            texture: TextureId::WHITE,
+            tex_coords: Rect::new(0.0, 0.0, 1.0, 1.0),
+            color,
+        }
+    }
+
+    pub fn textured(rect: Rect, texture: TextureId, tex_coords: Rect) -> Self {
+        Self {
+            rect,
+            texture,
+            tex_coords,
+            color: [1.0; 4],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct QuadVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for QuadVertex {}
+unsafe impl bytemuck::Zeroable for QuadVertex {}
+
+impl Vertex for QuadVertex {
+    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+        use std::mem;
+        wgpu::VertexBufferDescriptor {
+            stride: mem::size_of::<QuadVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (mem::size_of::<[f32; 2]>() * 2) as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float4,
+                },
+            ],
+        }
+    }
+}
+
+/// Draws batches of screen space quads over whatever is already in the frame
+pub struct QuadRenderer {
+    bind_group_layout: Rc<wgpu::BindGroupLayout>,
+    textures: Vec<(Texture, wgpu::BindGroup)>,
+    pipeline: Rc<wgpu::RenderPipeline>,
+}
+
+impl QuadRenderer {
+    pub fn new(graphics: &Graphics) -> Result<Self> {
+        let bind_group_layout = graphics.bind_group_layout("quad_bind_group_layout", QUAD_BINDINGS);
+        let pipeline = graphics.create_render_pipeline(&PipelineDesc {
+            bind_group_layouts: &[QUAD_BINDINGS],
+            vertex_shader: "quad.vert",
+            fragment_shader: "quad.frag",
+            depth_state: None,
+            alpha_blend: true,
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            vertex_buffers: &[QuadVertex::desc()],
+        })?;
+
+        let mut renderer = Self {
+            bind_group_layout,
+            textures: Vec::new(),
+            pipeline,
+        };
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        renderer.add_texture(graphics, &white, "quad_white");
+        Ok(renderer)
+    }
+
+    pub fn add_texture(
+        &mut self,
+        graphics: &Graphics,
+        image: &image::RgbaImage,
+        label: &str,
+    ) -> TextureId {
+        let texture = Texture::from_image(&graphics.device, &graphics.queue, image, label);
+        let bind_group = graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                bindings: &[
+                    wgpu::Binding {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::Binding {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                ],
+                label: Some(label),
+            });
+
+        self.textures.push((texture, bind_group));
+        TextureId(self.textures.len() - 1)
+    }
+
+    /// Draws `quads` in order, switching textures only between runs of quads
+    /// that use different ones
+    pub fn draw(
+        &self,
+        graphics: &Graphics,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &wgpu::TextureView,
+        quads: &[Quad],
+        width: u32,
+        height: u32,
+    ) {
+        if quads.is_empty() {
+            return;
+        }
+
+        let (width, height) = (width as f32, height as f32);
+        let to_ndc = |x: f32, y: f32| [x / width * 2.0 - 1.0, 1.0 - y / height * 2.0];
+
+        let mut vertices = Vec::with_capacity(quads.len() * 4);
+        let mut indices: Vec<u32> = Vec::with_capacity(quads.len() * 6);
+        for quad in quads {
+            let (r, t) = (quad.rect, quad.tex_coords);
+            let offset = vertices.len() as u32;
+            let corners = [
+                (r.x, r.y, t.x, t.y),
+                (r.x + r.w, r.y, t.x + t.w, t.y),
+                (r.x + r.w, r.y + r.h, t.x + t.w, t.y + t.h),
+                (r.x, r.y + r.h, t.x, t.y + t.h),
+            ];
+            for (x, y, u, v) in corners.iter() {
+                vertices.push(QuadVertex {
+                    position: to_ndc(*x, *y),
+                    tex_coords: [*u, *v],
+                    color: quad.color,
+                });
+            }
+            indices.extend_from_slice(&[
+                offset,
+                offset + 1,
+                offset + 2,
+                offset,
+                offset + 2,
+                offset + 3,
+            ]);
+        }
+
+        let vertex_buffer = graphics
+            .device
+            .create_buffer_with_data(bytemuck::cast_slice(&vertices), wgpu::BufferUsage::VERTEX);
+        let index_buffer = graphics
+            .device
+            .create_buffer_with_data(bytemuck::cast_slice(&indices), wgpu::BufferUsage::INDEX);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: frame,
+                resolve_target: None,
+                load_op: wgpu::LoadOp::Load,
+                store_op: wgpu::StoreOp::Store,
+                clear_color: wgpu::Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, &vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&index_buffer, 0, 0);
+
+        let mut start = 0;
+        while start < quads.len() {
+            let texture = quads[start].texture;
+            let end = quads[start..]
+                .iter()
+                .position(|quad| quad.texture != texture)
+                .map_or(quads.len(), |count| start + count);
+
+            render_pass.set_bind_group(0, &self.textures[texture.0].1, &[]);
+            render_pass.draw_indexed(start as u32 * 6..end as u32 * 6, 0, 0..1);
+            start = end;
+        }
+    }
+}
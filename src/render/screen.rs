@@ -0,0 +1,57 @@
+/// The window surface and the swap chain presenting to it
+pub struct Screen {
+    surface: wgpu::Surface,
+    pub sc_desc: wgpu::SwapChainDescriptor,
+    pub swap_chain: wgpu::SwapChain,
+}
+
+impl Screen {
+    pub fn new(
+        surface: wgpu::Surface,
+        device: &wgpu::Device,
+        size: winit::dpi::PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+        present_mode: wgpu::PresentMode,
+    ) -> Self {
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode,
+        };
+        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+
+        Self {
+            surface,
+            sc_desc,
+            swap_chain,
+        }
+    }
+
+    /// Acquires the next frame, recreating the swap chain once if it timed out
+    /// or the surface was lost
+    pub fn next_frame(&mut self, device: &wgpu::Device) -> Option<wgpu::SwapChainOutput> {
+        match self.swap_chain.get_next_texture() {
+            Ok(frame) => Some(frame),
+            Err(wgpu::TimeOut) => {
+                log::warn!("Timed out acquiring a frame, recreating the swap chain");
+                self.swap_chain = device.create_swap_chain(&self.surface, &self.sc_desc);
+                self.swap_chain.get_next_texture().ok()
+            }
+        }
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode, device: &wgpu::Device) {
+        if self.sc_desc.present_mode != present_mode {
+            self.sc_desc.present_mode = present_mode;
+            self.swap_chain = device.create_swap_chain(&self.surface, &self.sc_desc);
+        }
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>, device: &wgpu::Device) {
+        self.sc_desc.width = new_size.width;
+        self.sc_desc.height = new_size.height;
+        self.swap_chain = device.create_swap_chain(&self.surface, &self.sc_desc);
+    }
+}
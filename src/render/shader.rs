@@ -0,0 +1,139 @@
+use std::path::Path;
+#[cfg(feature = "dev")]
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "dev")]
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders");
+
+// How often the shader directory is checked for changes
+#[cfg(feature = "dev")]
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+macro_rules! spirv {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv"))
+    };
+}
+
+// Compiled by build.rs
+fn embedded(name: &str) -> Result<Vec<u32>> {
+    let spirv: &[u8] = match name {
+        "shader.vert" => spirv!("shader.vert"),
+        "shader.frag" => spirv!("shader.frag"),
+        "sky.vert" => spirv!("sky.vert"),
+        "sky.frag" => spirv!("sky.frag"),
+        "quad.vert" => spirv!("quad.vert"),
+        "quad.frag" => spirv!("quad.frag"),
+        "outline.vert" => spirv!("outline.vert"),
+        "outline.frag" => spirv!("outline.frag"),
+        _ => return Err(Error::Shader(name.to_string())),
+    };
+    wgpu::read_spirv(std::io::Cursor::new(spirv)).map_err(Error::io(Path::new(name)))
+}
+
+/// Returns the SPIR-V for the shader file `name`
+#[cfg(not(feature = "dev"))]
+pub fn load_spirv(name: &str) -> Result<Vec<u32>> {
+    embedded(name)
+}
+
+/// Returns the SPIR-V for the shader file `name`, compiling it from the GLSL on
+/// disk so edits are picked up without rebuilding. Falls back to the SPIR-V
+/// embedded at build time if the file can't be read.
+#[cfg(feature = "dev")]
+pub fn load_spirv(name: &str) -> Result<Vec<u32>> {
+    let glsl = match std::fs::read_to_string(PathBuf::from(SHADER_DIR).join(name)) {
+        Ok(glsl) => glsl,
+        Err(e) => {
+            log::warn!("Failed to read {}, using embedded SPIR-V: {}", name, e);
+            return embedded(name);
+        }
+    };
+
+    let kind = if name.ends_with(".vert") {
+        shaderc::ShaderKind::Vertex
+    } else {
+        shaderc::ShaderKind::Fragment
+    };
+
+    let mut compiler = shaderc::Compiler::new().ok_or(Error::ShaderCompiler)?;
+    let spirv = compiler
+        .compile_into_spirv(&glsl, kind, name, "main", None)
+        .map_err(|e| Error::ShaderCompile {
+            name: name.to_string(),
+            message: e.to_string(),
+        })?;
+
+    Ok(spirv.as_binary().to_vec())
+}
+
+/// Watches the shader directory for modified files
+#[cfg(feature = "dev")]
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    modified: HashMap<String, SystemTime>,
+    last_poll: Instant,
+}
+
+#[cfg(feature = "dev")]
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "dev")]
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        let mut watcher = Self {
+            dir: PathBuf::from(SHADER_DIR),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        watcher.scan();
+        watcher
+    }
+
+    /// Returns the names of shaders that changed since the last call
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<String> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("Failed to read {:?}: {}", self.dir, e);
+                return Vec::new();
+            }
+        };
+
+        let mut changed = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let is_shader = matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("vert") | Some("frag")
+            );
+            let modified = entry.metadata().and_then(|meta| meta.modified());
+            let name = path.file_name().and_then(|name| name.to_str());
+
+            if let (true, Ok(modified), Some(name)) = (is_shader, modified, name) {
+                if self.modified.insert(name.to_string(), modified) != Some(modified) {
+                    changed.push(name.to_string());
+                }
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,231 @@
+use cgmath::{InnerSpace, Vector4};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+use super::graphics::{DepthState, Graphics, PipelineDesc};
+use super::texture::Texture;
+use super::UNIFORM_BINDINGS;
+
+// Length of a full day/night cycle in seconds
+const DAY_LENGTH: f32 = 600.0;
+
+const CUBEMAP_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+// The sky uniforms, cubemap and its sampler
+const SKY_BINDINGS: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            dimension: wgpu::TextureViewDimension::Cube,
+            component_type: wgpu::TextureComponentType::Float,
+            multisampled: false,
+        },
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler { comparison: false },
+    },
+];
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SkyUniforms {
+    sun_direction: Vector4<f32>,
+    params: Vector4<f32>,
+}
+
+unsafe impl bytemuck::Pod for SkyUniforms {}
+unsafe impl bytemuck::Zeroable for SkyUniforms {}
+
+pub enum SkyMode {
+    Procedural,
+    Cubemap,
+}
+
+pub struct Sky {
+    // 0.0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+    pub time: f32,
+    pub mode: SkyMode,
+
+    uniforms: SkyUniforms,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+
+    // Kept alive for the bind group
+    _cubemap: Texture,
+
+    pipeline: Rc<wgpu::RenderPipeline>,
+}
+
+impl Sky {
+    /// Uses the cubemap in `cubemap_dir` if it can be loaded, otherwise falls
+    /// back to the procedural sky
+    pub fn new(graphics: &Graphics, cubemap_dir: Option<&Path>) -> Result<Self> {
+        let loaded = cubemap_dir.and_then(|dir| match Self::load_faces(dir) {
+            Ok(faces) => Some(faces),
+            Err(e) => {
+                log::warn!("Failed to load skybox from {:?}: {}", dir, e);
+                None
+            }
+        });
+
+        let (mode, faces) = match loaded {
+            Some(faces) => (SkyMode::Cubemap, faces),
+            None => (
+                SkyMode::Procedural,
+                vec![image::RgbaImage::new(1, 1); CUBEMAP_FACES.len()],
+            ),
+        };
+        let cubemap =
+            Texture::create_cubemap(&graphics.device, &graphics.queue, &faces, "sky_cubemap");
+
+        let time = 0.35;
+        let uniforms = SkyUniforms::new(time, &mode);
+        let uniform_buffer = graphics.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[uniforms]),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let bind_group_layout = graphics.bind_group_layout("sky_bind_group_layout", SKY_BINDINGS);
+
+        let bind_group = graphics
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &bind_group_layout,
+                bindings: &[
+                    wgpu::Binding {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer {
+                            buffer: &uniform_buffer,
+                            range: 0..std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
+                        },
+                    },
+                    wgpu::Binding {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&cubemap.view),
+                    },
+                    wgpu::Binding {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+                    },
+                ],
+                label: Some("sky_bind_group"),
+            });
+
+        let pipeline = Self::create_pipeline(graphics)?;
+
+        Ok(Self {
+            time,
+            mode,
+            uniforms,
+            uniform_buffer,
+            bind_group,
+            _cubemap: cubemap,
+            pipeline,
+        })
+    }
+
+    fn create_pipeline(graphics: &Graphics) -> Result<Rc<wgpu::RenderPipeline>> {
+        graphics.create_render_pipeline(&PipelineDesc {
+            bind_group_layouts: &[UNIFORM_BINDINGS, SKY_BINDINGS],
+            vertex_shader: "sky.vert",
+            fragment_shader: "sky.frag",
+            depth_state: Some(DepthState::disabled(Texture::DEPTH_FORMAT)),
+            alpha_blend: false,
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            vertex_buffers: &[],
+        })
+    }
+
+    /// Recompiles the sky shaders, keeping the current pipeline if that fails
+    #[cfg(feature = "dev")]
+    pub fn reload(&mut self, graphics: &Graphics) -> Result<()> {
+        self.pipeline = Self::create_pipeline(graphics)?;
+        Ok(())
+    }
+
+    fn load_faces(dir: &Path) -> Result<Vec<image::RgbaImage>> {
+        CUBEMAP_FACES
+            .iter()
+            .map(|face| {
+                let path = dir.join(format!("{}.png", face));
+                Ok(image::open(&path)
+                    .map_err(Error::image(&path))?
+                    .into_rgba8())
+            })
+            .collect()
+    }
+
+    pub fn update(&mut self, dt: Duration, graphics: &Graphics) {
+        self.time = (self.time + dt.as_secs_f32() / DAY_LENGTH).fract();
+        self.uniforms = SkyUniforms::new(self.time, &self.mode);
+
+        let mut encoder = graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("sky update encoder"),
+            });
+
+        let staging_buffer = graphics.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[self.uniforms]),
+            wgpu::BufferUsage::COPY_SRC,
+        );
+
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.uniform_buffer,
+            0,
+            std::mem::size_of::<SkyUniforms>() as wgpu::BufferAddress,
+        );
+
+        graphics.queue.submit(&[encoder.finish()]);
+    }
+}
+
+impl SkyUniforms {
+    fn new(time: f32, mode: &SkyMode) -> Self {
+        // The sun rises in +x, passes overhead at noon and sets in -x
+        let angle = (time - 0.25) * std::f32::consts::PI * 2.0;
+        let sun = cgmath::Vector3::new(angle.cos(), angle.sin(), 0.25).normalize();
+
+        let cubemap = match mode {
+            SkyMode::Procedural => 0.0,
+            SkyMode::Cubemap => 1.0,
+        };
+
+        Self {
+            sun_direction: sun.extend(0.0),
+            params: Vector4::new(cubemap, time, 0.0, 0.0),
+        }
+    }
+}
+
+pub trait DrawSky<'a, 'b>
+where
+    'b: 'a,
+{
+    fn draw_sky(&mut self, sky: &'b Sky, uniforms: &'b wgpu::BindGroup);
+}
+
+impl<'a, 'b> DrawSky<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_sky(&mut self, sky: &'b Sky, uniforms: &'b wgpu::BindGroup) {
+        self.set_pipeline(&sky.pipeline);
+        self.set_bind_group(0, uniforms, &[]);
+        self.set_bind_group(1, &sky.bind_group, &[]);
+        self.draw(0..36, 0..1);
+    }
+}
@@ -0,0 +1,248 @@
+use futures::executor::block_on;
+use std::future::Future;
+
+use super::graphics::Graphics;
+use super::texture::Texture;
+use crate::error::Result;
+
+// Rows copied out of a texture must be padded to a multiple of this
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Color and depth textures that can be rendered to in place of the swap chain
+pub struct OffscreenTarget {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub depth: Texture,
+}
+
+/// A copy of a texture into a mappable buffer
+pub struct Readback {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    padded_bytes_per_row: u32,
+    buffer: wgpu::Buffer,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let view = texture.create_default_view();
+        let depth = Texture::create_depth_texture(device, width, height, "offscreen_depth");
+
+        Self {
+            width,
+            height,
+            format,
+            texture,
+            view,
+            depth,
+        }
+    }
+
+    /// Starts copying the target into a buffer, returning once the copy is submitted
+    pub fn readback(&self, graphics: &Graphics) -> Readback {
+        Readback::new(
+            graphics,
+            &self.texture,
+            self.width,
+            self.height,
+            self.format,
+        )
+    }
+
+    /// Copies the target back to the CPU, blocking until the GPU is done
+    pub fn read_image(&self, graphics: &Graphics) -> Result<image::RgbaImage> {
+        let readback = self.readback(graphics);
+        let mapping = readback.map();
+        graphics.device.poll(wgpu::Maintain::Wait);
+
+        let data = block_on(mapping)?;
+        Ok(readback.to_image(data.as_slice()))
+    }
+}
+
+impl Readback {
+    fn new(
+        graphics: &Graphics,
+        texture: &wgpu::Texture,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let padded_bytes_per_row = padded_bytes_per_row(width);
+        let buffer = graphics.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback_buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let mut encoder = graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("readback encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                offset: 0,
+                bytes_per_row: padded_bytes_per_row,
+                rows_per_image: 0,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        graphics.queue.submit(&[encoder.finish()]);
+
+        Self {
+            width,
+            height,
+            format,
+            padded_bytes_per_row,
+            buffer,
+        }
+    }
+
+    /// Resolves once the GPU has finished the copy. The device has to be polled
+    /// for this to make progress.
+    pub fn map(
+        &self,
+    ) -> impl Future<Output = std::result::Result<wgpu::BufferReadMapping, wgpu::BufferAsyncErr>>
+    {
+        self.buffer.map_read(
+            0,
+            (self.padded_bytes_per_row * self.height) as wgpu::BufferAddress,
+        )
+    }
+
+    /// Strips the row padding and converts the mapped data to RGBA
+    pub fn to_image(&self, data: &[u8]) -> image::RgbaImage {
+        unpad_image(
+            data,
+            self.width,
+            self.height,
+            self.padded_bytes_per_row,
+            self.format,
+        )
+    }
+}
+
+// Bytes in each row of a copy out of a texture `width` pixels wide
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded_bytes_per_row = width * 4;
+    let padding = (COPY_BYTES_PER_ROW_ALIGNMENT
+        - unpadded_bytes_per_row % COPY_BYTES_PER_ROW_ALIGNMENT)
+        % COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded_bytes_per_row + padding
+}
+
+fn unpad_image(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+) -> image::RgbaImage {
+    let row_bytes = (width * 4) as usize;
+    let mut pixels = Vec::with_capacity(row_bytes * height as usize);
+    for row in data
+        .chunks(padded_bytes_per_row as usize)
+        .take(height as usize)
+    {
+        pixels.extend_from_slice(&row[..row_bytes]);
+    }
+
+    if let wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb = format {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::RgbaImage::from_raw(width, height, pixels)
+        .expect("Readback buffer is smaller than the image")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A BGRA copy of a 3×2 image, each row padded out to the alignment with
+    // bytes that mustn't end up in the image
+    fn padded_bgra() -> Vec<u8> {
+        let mut data = vec![0xee; COPY_BYTES_PER_ROW_ALIGNMENT as usize * 2];
+        for y in 0..2 {
+            for x in 0..3 {
+                let offset = y * COPY_BYTES_PER_ROW_ALIGNMENT as usize + x * 4;
+                let value = (y * 3 + x) as u8;
+                // Blue, green, red then alpha
+                data[offset..offset + 4].copy_from_slice(&[value, 100 + value, 200 + value, 255]);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_alignment() {
+        assert_eq!(padded_bytes_per_row(1), 256);
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+    }
+
+    #[test]
+    fn strips_padding_and_swaps_bgra() {
+        let image = unpad_image(
+            &padded_bgra(),
+            3,
+            2,
+            256,
+            wgpu::TextureFormat::Bgra8UnormSrgb,
+        );
+        assert_eq!(image.dimensions(), (3, 2));
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let value = (y * 3 + x) as u8;
+            assert_eq!(pixel.0, [200 + value, 100 + value, value, 255]);
+        }
+    }
+
+    #[test]
+    fn keeps_rgba_channels() {
+        let image = unpad_image(
+            &padded_bgra(),
+            3,
+            2,
+            256,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        );
+        assert_eq!(image.get_pixel(2, 1).0, [5, 105, 205, 255]);
+    }
+}
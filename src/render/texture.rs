@@ -9,12 +9,13 @@ impl Texture {
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
-        sc_desc: &wgpu::SwapChainDescriptor,
+        width: u32,
+        height: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: sc_desc.width,
-            height: sc_desc.height,
+            width,
+            height,
             depth: 1,
         };
 
@@ -51,4 +52,148 @@ impl Texture {
             sampler,
         }
     }
+
+    /// Creates a cubemap from six square faces ordered +X, -X, +Y, -Y, +Z, -Z
+    pub fn create_cubemap(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::RgbaImage],
+        label: &str,
+    ) -> Self {
+        let (width, height) = faces[0].dimensions();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            array_layer_count: 6,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("cubemap upload encoder"),
+        });
+
+        for (layer, face) in faces.iter().enumerate() {
+            let buffer = device.create_buffer_with_data(face.as_raw(), wgpu::BufferUsage::COPY_SRC);
+            encoder.copy_buffer_to_texture(
+                wgpu::BufferCopyView {
+                    buffer: &buffer,
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: 0,
+                },
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: 0,
+                    array_layer: layer as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                size,
+            );
+        }
+        queue.submit(&[encoder.finish()]);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            dimension: wgpu::TextureViewDimension::Cube,
+            aspect: wgpu::TextureAspect::All,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            array_layer_count: 6,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: wgpu::CompareFunction::Always,
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Creates a texture from an image, sampled without filtering so small
+    /// pixel art stays sharp
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+        label: &str,
+    ) -> Self {
+        let (width, height) = image.dimensions();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("texture upload encoder"),
+        });
+        let buffer = device.create_buffer_with_data(image.as_raw(), wgpu::BufferUsage::COPY_SRC);
+        encoder.copy_buffer_to_texture(
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                offset: 0,
+                bytes_per_row: 4 * width,
+                rows_per_image: 0,
+            },
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            size,
+        );
+        queue.submit(&[encoder.finish()]);
+
+        let view = texture.create_default_view();
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: wgpu::CompareFunction::Always,
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
 }
@@ -1,28 +1,38 @@
+use std::collections::BTreeMap;
 use wgpu_glyph::{ab_glyph::FontArc, GlyphBrush, GlyphBrushBuilder};
 
+use crate::error::Result;
+
 pub struct Txt {
     pub debug_text: String,
+    // Shader compile errors keyed by the pipeline that failed
+    pub shader_errors: BTreeMap<&'static str, String>,
     pub glyph_brush: GlyphBrush<()>,
 }
 
 impl Txt {
-    pub fn new(debug_text: String, device: &wgpu::Device) -> Self {
-        let font = FontArc::try_from_slice(include_bytes!("../munro.ttf")).expect("Font not found");
+    pub fn new(
+        debug_text: String,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        Ok(Self {
+            debug_text,
+            shader_errors: BTreeMap::new(),
+            glyph_brush: Self::create_glyph_brush(device, format)?,
+        })
+    }
 
-        let glyph_brush =
-            GlyphBrushBuilder::using_font(font).build(&device, wgpu::TextureFormat::Bgra8Unorm);
+    pub fn create_glyph_brush(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Result<GlyphBrush<()>> {
+        let font = FontArc::try_from_slice(include_bytes!("../munro.ttf"))?;
 
-        Self {
-            debug_text,
-            glyph_brush,
-        }
+        Ok(GlyphBrushBuilder::using_font(font).build(device, format))
     }
 
-    pub fn update_debug(&mut self, player: &crate::player::Player) {
-        let new_text = format!(
-            "x: {:.3}, y: {:.3}, z: {:.3}",
-            player.camera.position.x, player.camera.position.y, player.camera.position.z
-        );
-        self.debug_text = new_text;
+    pub fn update_debug(&mut self, stats: &crate::debug::DebugStats) {
+        self.debug_text = stats.to_string();
     }
 }
@@ -0,0 +1,306 @@
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::player::GameMode;
+use crate::render::{
+    block::{Block, Palette},
+    chunk::{Chunk, ChunkPosition},
+};
+use crate::terrain::Generator;
+
+const CHUNK_MAGIC: &[u8; 4] = b"CHNK";
+const CHUNK_VERSION: u8 = 1;
+
+/// Everything about a world that isn't stored in its chunks
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Level {
+    pub seed: u64,
+    pub spawn: [f32; 3],
+    // Color-only block types, in id order, so that blocks using them load
+    // with the same ids
+    #[serde(default)]
+    pub block_colors: Vec<[u8; 3]>,
+}
+
+/// Where the player was and what they were carrying
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerData {
+    pub position: [f32; 3],
+    // In degrees
+    pub yaw: f32,
+    pub pitch: f32,
+    pub game_mode: GameMode,
+    pub selected: usize,
+    #[serde(default = "default_flying")]
+    pub flying: bool,
+    // Only filled slots are stored. This has to come last, as TOML tables
+    // can't be followed by plain values.
+    pub inventory: Vec<SavedStack>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedStack {
+    pub slot: usize,
+    pub block: usize,
+    pub count: u32,
+}
+
+/// A world directory holding `level.toml`, `player.toml` and a file per
+/// modified chunk. Chunks that were never changed aren't stored, they're
+/// generated again from the seed.
+pub struct WorldSave {
+    pub dir: PathBuf,
+    pub level: Level,
+    // None until the player has been saved once
+    pub player: Option<PlayerData>,
+}
+
+impl WorldSave {
+    /// Opens the world in `dir`, creating a new one if there isn't one yet.
+    /// `seed` is only used for new worlds. Nothing is written until the world
+    /// is saved, so runs that never save leave no directory behind.
+    pub fn open(dir: &Path, seed: Option<u64>) -> Result<Self> {
+        let level_path = dir.join("level.toml");
+        let level = if level_path.exists() {
+            let text = std::fs::read_to_string(&level_path).map_err(Error::io(&level_path))?;
+            let level: Level = toml::from_str(&text)?;
+            if matches!(seed, Some(seed) if seed != level.seed) {
+                log::warn!("Ignoring seed, {:?} already has seed {}", dir, level.seed);
+            }
+            log::info!("Loaded world {:?}", dir);
+            level
+        } else {
+            let seed = seed.unwrap_or_else(random_seed);
+            let generator = Generator::new(seed);
+            let level = Level {
+                seed,
+                spawn: [0.0, generator.surface_height(8, 8) as f32 + 2.5, 0.0],
+                block_colors: Vec::new(),
+            };
+            log::info!("Creating world {:?} with seed {}", dir, seed);
+            level
+        };
+
+        let player_path = dir.join("player.toml");
+        let player = if player_path.exists() {
+            let text = std::fs::read_to_string(&player_path).map_err(Error::io(&player_path))?;
+            Some(toml::from_str(&text)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            level,
+            player,
+        })
+    }
+
+    pub fn spawn(&self) -> Point3<f32> {
+        self.level.spawn.into()
+    }
+
+    pub fn save_level(&mut self) -> Result<()> {
+        let chunks = self.dir.join("chunks");
+        std::fs::create_dir_all(&chunks).map_err(Error::io(&chunks))?;
+        let path = self.dir.join("level.toml");
+        std::fs::write(&path, toml::to_string_pretty(&self.level)?).map_err(Error::io(&path))?;
+        Ok(())
+    }
+
+    pub fn save_player(&mut self, player: PlayerData) -> Result<()> {
+        let path = self.dir.join("player.toml");
+        std::fs::write(&path, toml::to_string_pretty(&player)?).map_err(Error::io(&path))?;
+        self.player = Some(player);
+        Ok(())
+    }
+
+    /// Reads a stored chunk. Missing chunks return `None`, as do unreadable
+    /// ones after logging, so they get regenerated.
+    pub fn load_chunk(&self, position: ChunkPosition, palette: &Palette) -> Option<Chunk> {
+        let path = self.chunk_path(position);
+        let data = std::fs::read(&path).ok()?;
+        match decode_chunk(position, &data, palette) {
+            Ok(chunk) => Some(chunk),
+            Err(e) => {
+                log::warn!("Failed to load {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn save_chunk(&self, chunk: &Chunk) -> Result<()> {
+        let path = self.chunk_path(chunk.position);
+        std::fs::write(&path, encode_chunk(chunk)).map_err(Error::io(&path))?;
+        Ok(())
+    }
+
+    fn chunk_path(&self, position: ChunkPosition) -> PathBuf {
+        self.dir.join("chunks").join(format!(
+            "{}.{}.{}.chunk",
+            position.x, position.y, position.z
+        ))
+    }
+}
+
+/// Run-length encodes the blocks of a chunk. Each run is a little endian `u16`
+/// count followed by a `u16` block id plus one, with zero for air.
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(CHUNK_MAGIC);
+    data.push(CHUNK_VERSION);
+
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for block in chunk.blocks.iter() {
+        let id = block.map_or(0, |block| block.id() as u16 + 1);
+        match runs.last_mut() {
+            Some((count, run_id)) if *run_id == id && *count < u16::MAX => *count += 1,
+            _ => runs.push((1, id)),
+        }
+    }
+    for (count, id) in runs {
+        data.extend_from_slice(&count.to_le_bytes());
+        data.extend_from_slice(&id.to_le_bytes());
+    }
+    data
+}
+
+/// Decodes a chunk written by `encode_chunk`, whose block ids all have to be
+/// in `palette`
+pub fn decode_chunk(position: ChunkPosition, data: &[u8], palette: &Palette) -> Result<Chunk> {
+    let corrupt = |reason: &str| Error::CorruptChunk(reason.to_string());
+    if data.len() < 5 || &data[..4] != CHUNK_MAGIC {
+        return Err(corrupt("missing header"));
+    }
+    if data[4] != CHUNK_VERSION {
+        return Err(corrupt("unknown version"));
+    }
+
+    let mut chunk = Chunk::new(0, position);
+    let mut index = 0;
+    for run in data[5..].chunks(4) {
+        if run.len() != 4 {
+            return Err(corrupt("truncated run"));
+        }
+        let count = u16::from_le_bytes([run[0], run[1]]) as usize;
+        let id = u16::from_le_bytes([run[2], run[3]]);
+        if index + count > chunk.blocks.len() {
+            return Err(corrupt("too many blocks"));
+        }
+        if id as usize > palette.len() {
+            return Err(corrupt("unknown block id"));
+        }
+        if id != 0 {
+            let block = Some(Block::new(id as usize - 1));
+            for slot in &mut chunk.blocks[index..index + count] {
+                *slot = block;
+            }
+        }
+        index += count;
+    }
+    if index != chunk.blocks.len() {
+        return Err(corrupt("too few blocks"));
+    }
+    Ok(chunk)
+}
+
+// Players saved before walking was added were always flying
+fn default_flying() -> bool {
+    true
+}
+
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{block::BLOCK_TYPES, chunk::CHUNK_SIZE};
+    use cgmath::Vector3;
+
+    fn block_ids(chunk: &Chunk) -> Vec<Option<usize>> {
+        chunk
+            .blocks
+            .iter()
+            .map(|block| block.map(|b| b.id()))
+            .collect()
+    }
+
+    fn run(count: u16, id: u16) -> Vec<u8> {
+        let mut data = count.to_le_bytes().to_vec();
+        data.extend_from_slice(&id.to_le_bytes());
+        data
+    }
+
+    fn header() -> Vec<u8> {
+        let mut data = CHUNK_MAGIC.to_vec();
+        data.push(CHUNK_VERSION);
+        data
+    }
+
+    #[test]
+    fn round_trips_terrain() {
+        let position = Vector3::new(3, 0, -2);
+        let chunk = Generator::new(7).generate(position);
+        let decoded = decode_chunk(position, &encode_chunk(&chunk), &Palette::default()).unwrap();
+        assert_eq!(decoded.position, position);
+        assert_eq!(block_ids(&decoded), block_ids(&chunk));
+    }
+
+    #[test]
+    fn empty_chunk_is_a_single_run() {
+        let position = Vector3::new(0, 5, 0);
+        let chunk = Chunk::new(0, position);
+        let data = encode_chunk(&chunk);
+        assert_eq!(data.len(), 5 + 4);
+        let decoded = decode_chunk(position, &data, &Palette::default()).unwrap();
+        assert!(decoded.blocks.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let position = Vector3::new(0, 0, 0);
+        let volume = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as u16;
+        let with = |runs: &[Vec<u8>]| {
+            let mut data = header();
+            for run in runs {
+                data.extend_from_slice(run);
+            }
+            data
+        };
+
+        let mut wrong_version = header();
+        wrong_version[4] = CHUNK_VERSION + 1;
+        let mut truncated = with(&[run(volume, 0)]);
+        truncated.pop();
+
+        let bad = [
+            Vec::new(),
+            b"CHN".to_vec(),
+            b"NOPE\x01".to_vec(),
+            wrong_version,
+            truncated,
+            with(&[run(volume, 0), run(1, 1)]),
+            with(&[run(volume - 1, 0)]),
+            with(&[]),
+            // Ids are stored plus one, so this is the first past the palette
+            with(&[run(volume, BLOCK_TYPES.len() as u16 + 1)]),
+        ];
+        for data in &bad {
+            assert!(
+                decode_chunk(position, data, &Palette::default()).is_err(),
+                "decoded {:?}",
+                data
+            );
+        }
+        let ok = with(&[run(volume - 1, 0), run(1, BLOCK_TYPES.len() as u16)]);
+        assert!(decode_chunk(position, &ok, &Palette::default()).is_ok());
+    }
+}
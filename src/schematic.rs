@@ -0,0 +1,293 @@
+use cgmath::Vector3;
+use std::path::Path;
+
+use crate::edit::{Clipboard, MAX_EDIT_VOLUME};
+use crate::error::{Error, Result};
+use crate::render::block::Palette;
+
+const SCHEMATIC_MAGIC: &[u8; 4] = b"SCHM";
+const SCHEMATIC_VERSION: u8 = 1;
+
+/// Encodes copied blocks as a schematic: the dimensions as three little endian
+/// `u16`s, a palette of the block names used, then the blocks run-length
+/// encoded like chunks, as `u16` counts and `u16` palette indices plus one with
+/// zero for air.
+///
+/// Blocks are stored by name rather than id, so schematics keep working if
+/// block types are added or reordered, and between worlds whose palettes
+/// differ.
+pub fn encode(clipboard: &Clipboard, palette: &Palette) -> Result<Vec<u8>> {
+    let size = clipboard.size();
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(SCHEMATIC_MAGIC);
+    data.push(SCHEMATIC_VERSION);
+    for axis in 0..3 {
+        if size[axis] < 1 || size[axis] > u16::MAX as i32 {
+            return Err(Error::CorruptSchematic("too large to store".into()));
+        }
+        data.extend_from_slice(&(size[axis] as u16).to_le_bytes());
+    }
+
+    let mut used: Vec<usize> = Vec::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for block in clipboard.blocks() {
+        let index = match block {
+            Some(id) => match used.iter().position(|used| used == id) {
+                Some(index) => index as u16 + 1,
+                None => {
+                    used.push(*id);
+                    used.len() as u16
+                }
+            },
+            None => 0,
+        };
+        match runs.last_mut() {
+            Some((count, run_index)) if *run_index == index && *count < u16::MAX => *count += 1,
+            _ => runs.push((1, index)),
+        }
+    }
+
+    data.extend_from_slice(&(used.len() as u16).to_le_bytes());
+    for &id in &used {
+        let name = palette.block_type(id).name.as_bytes();
+        data.push(name.len() as u8);
+        data.extend_from_slice(name);
+    }
+    for (count, index) in runs {
+        data.extend_from_slice(&count.to_le_bytes());
+        data.extend_from_slice(&index.to_le_bytes());
+    }
+    Ok(data)
+}
+
+/// Decodes a schematic, adding color-only block types it uses that aren't in
+/// `palette` yet
+pub fn decode(data: &[u8], palette: &mut Palette) -> Result<Clipboard> {
+    let corrupt = |reason: &str| Error::CorruptSchematic(reason.to_string());
+    if data.len() < 5 || &data[..4] != SCHEMATIC_MAGIC {
+        return Err(corrupt("missing header"));
+    }
+    if data[4] != SCHEMATIC_VERSION {
+        return Err(corrupt("unknown version"));
+    }
+
+    let mut reader = Reader { data, offset: 5 };
+    let size = Vector3::new(
+        reader.u16()? as i32,
+        reader.u16()? as i32,
+        reader.u16()? as i32,
+    );
+    if size.x == 0 || size.y == 0 || size.z == 0 {
+        return Err(corrupt("empty dimensions"));
+    }
+
+    let mut ids = Vec::new();
+    for _ in 0..reader.u16()? {
+        let length = reader.bytes(1)?[0] as usize;
+        let name = std::str::from_utf8(reader.bytes(length)?)
+            .map_err(|_| corrupt("block name isn't UTF-8"))?;
+        let id = palette
+            .block_by_name(name)?
+            .ok_or_else(|| Error::CorruptSchematic(format!("unknown block {:?}", name)))?;
+        ids.push(id);
+    }
+
+    let volume = size.x as usize * size.y as usize * size.z as usize;
+    // Checked before allocating, since the header can claim up to 65535³ blocks
+    if volume as i64 > MAX_EDIT_VOLUME {
+        return Err(corrupt("too large to paste"));
+    }
+    let mut blocks = Vec::with_capacity(volume);
+    while reader.offset < data.len() {
+        let count = reader.u16()? as usize;
+        let index = reader.u16()? as usize;
+        if blocks.len() + count > volume {
+            return Err(corrupt("too many blocks"));
+        }
+        let block = match index {
+            0 => None,
+            index => Some(
+                *ids.get(index - 1)
+                    .ok_or_else(|| corrupt("block missing from palette"))?,
+            ),
+        };
+        blocks.extend(std::iter::repeat_n(block, count));
+    }
+    if blocks.len() != volume {
+        return Err(corrupt("too few blocks"));
+    }
+    Ok(Clipboard::new(size, blocks))
+}
+
+pub fn save(path: &Path, clipboard: &Clipboard, palette: &Palette) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+    }
+    std::fs::write(path, encode(clipboard, palette)?).map_err(Error::io(path))?;
+    Ok(())
+}
+
+pub fn load(path: &Path, palette: &mut Palette) -> Result<Clipboard> {
+    decode(&std::fs::read(path).map_err(Error::io(path))?, palette)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + count)
+            .ok_or_else(|| Error::CorruptSchematic("truncated".into()))?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::{BlockEdit, Region};
+    use crate::render::{
+        block::{Block, BLOCK_TYPES, STONE},
+        chunk::{Chunk, ChunkManager, ChunkPosition},
+    };
+    use crate::terrain::Generator;
+    use std::collections::HashMap;
+
+    // Two chunks side by side
+    fn positions() -> [ChunkPosition; 2] {
+        [Vector3::new(0, 0, 0), Vector3::new(1, 0, 0)]
+    }
+
+    fn terrain() -> ChunkManager {
+        let generator = Generator::new(42);
+        let mut chunks = ChunkManager::new(HashMap::new());
+        for &position in &positions() {
+            chunks.add_chunk(generator.generate(position));
+        }
+        // A block of every type, so the palette isn't just what terrain uses
+        let mut edit = BlockEdit::new();
+        for id in 0..BLOCK_TYPES.len() {
+            edit.set(Vector3::new(id as i32, 15, 0), Some(Block::new(id)));
+        }
+        chunks.apply_edit(&edit);
+        chunks
+    }
+
+    fn empty(positions: &[ChunkPosition]) -> ChunkManager {
+        let mut chunks = ChunkManager::new(HashMap::new());
+        for &position in positions {
+            chunks.add_chunk(Chunk::new(0, position));
+        }
+        chunks
+    }
+
+    fn block_ids(chunks: &ChunkManager, position: ChunkPosition) -> Vec<Option<usize>> {
+        chunks
+            .get_chunk(&position)
+            .unwrap()
+            .blocks
+            .iter()
+            .map(|block| block.map(|block| block.id()))
+            .collect()
+    }
+
+    // Both chunks, which span x -8 to 23 in world space
+    fn region() -> Region {
+        Region::new(Vector3::new(-8, 0, -8), Vector3::new(23, 15, 7))
+    }
+
+    #[test]
+    fn round_trip_keeps_chunks_identical() {
+        let source = terrain();
+        let mut palette = Palette::default();
+        let data = encode(&Clipboard::copy(&source, region()), &palette).unwrap();
+
+        let mut target = empty(&positions());
+        let clipboard = decode(&data, &mut palette).unwrap();
+        let (summary, _) = target.apply_edit(&clipboard.paste(region().min));
+        assert_eq!(summary.unloaded, 0);
+        for &position in &positions() {
+            assert_eq!(block_ids(&source, position), block_ids(&target, position));
+        }
+    }
+
+    #[test]
+    fn rotated_import_turns_back() {
+        let source = terrain();
+        let mut palette = Palette::default();
+        let data = encode(&Clipboard::copy(&source, region()), &palette).unwrap();
+
+        // A quarter turn each way around the same corner
+        let rotated = decode(&data, &mut palette).unwrap().rotate(1);
+        let mut sideways = empty(&[Vector3::new(0, 0, 0), Vector3::new(0, 0, 1)]);
+        let (summary, _) = sideways.apply_edit(&rotated.paste(region().min));
+        assert_eq!(summary.unloaded, 0);
+        let size = rotated.size();
+        let turned = Region::new(region().min, region().min + size - Vector3::new(1, 1, 1));
+        let data = encode(&Clipboard::copy(&sideways, turned), &palette).unwrap();
+
+        let mut target = empty(&positions());
+        let clipboard = decode(&data, &mut palette).unwrap().rotate(3);
+        target.apply_edit(&clipboard.paste(region().min));
+        for &position in &positions() {
+            assert_eq!(block_ids(&source, position), block_ids(&target, position));
+        }
+    }
+
+    #[test]
+    fn palette_only_holds_used_blocks() {
+        let mut palette = Palette::default();
+        let clipboard = Clipboard::new(Vector3::new(2, 1, 1), vec![Some(STONE), None]);
+        let data = encode(&clipboard, &palette).unwrap();
+        // Header, dimensions, one palette entry and two runs
+        let name = BLOCK_TYPES[STONE].name.len();
+        assert_eq!(data.len(), 5 + 6 + 2 + 1 + name + 2 * 4);
+        let decoded = decode(&data, &mut palette).unwrap();
+        assert_eq!(decoded.blocks(), clipboard.blocks());
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let mut palette = Palette::default();
+        let clipboard = Clipboard::new(Vector3::new(1, 1, 1), vec![Some(STONE)]);
+        let data = encode(&clipboard, &palette).unwrap();
+        assert!(decode(&data[..data.len() - 1], &mut palette).is_err());
+        assert!(decode(b"CHNK\x01", &mut palette).is_err());
+
+        // Rename the block to one that doesn't exist
+        let mut unknown = data.clone();
+        let name = 5 + 6 + 2 + 1;
+        unknown[name] = b'?';
+        assert!(decode(&unknown, &mut palette).is_err());
+
+        // A header far larger than anything that could be pasted
+        let mut oversized = data;
+        oversized[5..11].copy_from_slice(&[0xff; 6]);
+        assert!(decode(&oversized, &mut palette).is_err());
+    }
+
+    #[test]
+    fn color_blocks_are_added_to_the_palette() {
+        let mut source = Palette::default();
+        let color = source.color_block([0x9b, 0x3d, 0x7a]).unwrap();
+        let clipboard = Clipboard::new(Vector3::new(2, 1, 1), vec![Some(color), Some(STONE)]);
+        let data = encode(&clipboard, &source).unwrap();
+
+        // A world that already has another color gives it the next id
+        let mut target = Palette::default();
+        target.color_block([0x12, 0xd4, 0xe1]).unwrap();
+        let decoded = decode(&data, &mut target).unwrap();
+        assert_eq!(decoded.blocks(), &[Some(color + 1), Some(STONE)]);
+        assert_eq!(target.block_type(color + 1).name, "Color #9b3d7a");
+    }
+}
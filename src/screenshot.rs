@@ -0,0 +1,107 @@
+use futures::task::{noop_waker_ref, Context as TaskContext, Poll};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use crate::render::{
+    graphics::Graphics,
+    target::{OffscreenTarget, Readback},
+};
+use crate::ui::Ui;
+use crate::world::World;
+
+// Largest texture dimension wgpu guarantees
+const MAX_TEXTURE_SIZE: u32 = 8192;
+
+type Mapping = Pin<Box<dyn Future<Output = Result<wgpu::BufferReadMapping, wgpu::BufferAsyncErr>>>>;
+
+struct PendingScreenshot {
+    path: PathBuf,
+    readback: Readback,
+    mapping: Mapping,
+}
+
+/// Captures frames to PNG files without waiting on the GPU
+pub struct Screenshots {
+    pub dir: PathBuf,
+    pending: Vec<PendingScreenshot>,
+}
+
+impl Screenshots {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Renders the world and the UI into an offscreen target `scale` times the
+    /// window size and queues it for readback
+    pub fn capture(&mut self, graphics: &Graphics, world: &mut World, ui: &mut Ui, scale: u32) {
+        let largest = graphics.size.width.max(graphics.size.height).max(1);
+        let scale = scale.max(1).min(MAX_TEXTURE_SIZE / largest).max(1);
+        let width = graphics.size.width * scale;
+        let height = graphics.size.height * scale;
+
+        let target = OffscreenTarget::new(&graphics.device, width, height, graphics.format);
+        world.render_offscreen(graphics, &target);
+        ui.render_scaled(graphics, &target.view, scale);
+
+        let readback = target.readback(graphics);
+        let mapping = Box::pin(readback.map());
+
+        let name = format!(
+            "{}.png",
+            chrono::Local::now().format("%Y-%m-%d_%H.%M.%S%.3f")
+        );
+        self.pending.push(PendingScreenshot {
+            path: self.dir.join(name),
+            readback,
+            mapping,
+        });
+    }
+
+    /// Checks on in-flight captures and hands finished ones off to be encoded
+    pub fn poll(&mut self, graphics: &Graphics) {
+        if self.pending.is_empty() {
+            return;
+        }
+        graphics.device.poll(wgpu::Maintain::Poll);
+
+        let mut cx = TaskContext::from_waker(noop_waker_ref());
+        let mut i = 0;
+        while i < self.pending.len() {
+            let screenshot = &mut self.pending[i];
+            let image = match screenshot.mapping.as_mut().poll(&mut cx) {
+                Poll::Pending => {
+                    i += 1;
+                    continue;
+                }
+                Poll::Ready(Ok(data)) => Some(screenshot.readback.to_image(data.as_slice())),
+                Poll::Ready(Err(_)) => None,
+            };
+
+            let screenshot = self.pending.swap_remove(i);
+            match image {
+                Some(image) => Self::save(image, screenshot.path),
+                None => log::error!("Failed to read back screenshot {:?}", screenshot.path),
+            }
+        }
+    }
+
+    fn save(image: image::RgbaImage, path: PathBuf) {
+        // PNG encoding is slow enough to hitch a frame, so do it off the main thread
+        std::thread::spawn(move || {
+            if let Some(dir) = path.parent() {
+                if let Err(e) = std::fs::create_dir_all(dir) {
+                    log::error!("Failed to create {:?}: {}", dir, e);
+                    return;
+                }
+            }
+            match image.save(&path) {
+                Ok(()) => log::info!("Saved screenshot {:?}", path),
+                Err(e) => log::error!("Failed to save screenshot {:?}: {}", path, e),
+            }
+        });
+    }
+}
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::Fullscreen;
+
+use crate::error::{Error, Result};
+use crate::render::chunk::CHUNK_SIZE;
+
+/// The furthest render distance the menu, commands and command line allow, in chunks
+pub const MAX_RENDER_DISTANCE: u16 = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    Windowed,
+    // Fullscreen window at the monitor's current resolution
+    #[serde(alias = "fullscreen")]
+    Borderless,
+    // Takes over the monitor, switching to `video_mode`
+    Exclusive,
+}
+
+impl WindowMode {
+    /// The mode F11 switches to
+    pub fn next(self) -> Self {
+        match self {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Exclusive,
+            WindowMode::Exclusive => WindowMode::Windowed,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VideoModeSettings {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // In chunks
+    pub render_distance: u16,
+    // How far chunks are drawn at a lower level of detail past the render
+    // distance, in chunks
+    pub view_distance: u16,
+    // Vertical field of view in degrees
+    pub fov: f32,
+    pub vsync: bool,
+    pub window_mode: WindowMode,
+    // Size of the window when windowed
+    pub window_width: u32,
+    pub window_height: u32,
+    // Name of the monitor to go fullscreen on, the primary one if unset
+    pub monitor: Option<String>,
+    // Used by exclusive fullscreen, the monitor's largest mode if unset
+    pub video_mode: Option<VideoModeSettings>,
+    pub mouse_sensitivity: f32,
+    pub movement_speed: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            render_distance: 2,
+            view_distance: 20,
+            fov: 70.0,
+            vsync: true,
+            window_mode: WindowMode::Windowed,
+            window_width: 1280,
+            window_height: 720,
+            monitor: None,
+            video_mode: None,
+            mouse_sensitivity: 0.05,
+            movement_speed: 8.0,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings at `path`, using the defaults if there are none. The
+    /// file is only written once a setting is changed. A file that fails to
+    /// parse is left alone and the defaults are used. Distances out of range
+    /// are clamped, with the view distance at least the render distance.
+    pub fn load(path: &Path) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => {
+                log::info!("No settings at {:?}, using defaults", path);
+                return Self::default();
+            }
+        };
+
+        let mut settings: Self = match toml::from_str(&text) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Failed to parse {:?}, using defaults: {}", path, e);
+                return Self::default();
+            }
+        };
+        settings.render_distance = settings.render_distance.clamp(1, MAX_RENDER_DISTANCE);
+        settings.view_distance = settings
+            .view_distance
+            .clamp(settings.render_distance, MAX_RENDER_DISTANCE);
+        settings
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(path, text).map_err(Error::io(path))?;
+        Ok(())
+    }
+
+    /// Restores the defaults of everything except the window
+    pub fn reset(&mut self) {
+        *self = Self {
+            window_mode: self.window_mode,
+            window_width: self.window_width,
+            window_height: self.window_height,
+            monitor: self.monitor.take(),
+            video_mode: self.video_mode,
+            ..Self::default()
+        };
+    }
+
+    pub fn znear(&self) -> f32 {
+        0.1
+    }
+
+    /// Far enough to see the corners of the furthest drawn chunks
+    pub fn zfar(&self) -> f32 {
+        let distance = self.render_distance.max(self.view_distance);
+        (distance as f32 + 1.0) * CHUNK_SIZE as f32 * 2.0
+    }
+
+    /// What to pass to winit for the current window mode. Exclusive fullscreen
+    /// falls back to borderless if the monitor has no video modes.
+    pub fn fullscreen(
+        &self,
+        mut monitors: impl Iterator<Item = MonitorHandle>,
+        primary: MonitorHandle,
+    ) -> Option<Fullscreen> {
+        let monitor = match &self.monitor {
+            Some(name) => monitors
+                .find(|monitor| monitor.name().as_ref() == Some(name))
+                .unwrap_or_else(|| {
+                    log::warn!("No monitor named {:?}, using the primary one", name);
+                    primary
+                }),
+            None => primary,
+        };
+
+        match self.window_mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Exclusive => match self.video_mode(&monitor) {
+                Some(mode) => Some(Fullscreen::Exclusive(mode)),
+                None => Some(Fullscreen::Borderless(monitor)),
+            },
+        }
+    }
+
+    fn video_mode(&self, monitor: &MonitorHandle) -> Option<VideoMode> {
+        let matches = |mode: &VideoMode| match self.video_mode {
+            Some(wanted) => {
+                mode.size().width == wanted.width
+                    && mode.size().height == wanted.height
+                    && mode.refresh_rate() == wanted.refresh_rate
+            }
+            None => false,
+        };
+        let largest = |mode: &VideoMode| {
+            let size = mode.size();
+            (
+                size.width * size.height,
+                mode.refresh_rate(),
+                mode.bit_depth(),
+            )
+        };
+
+        monitor
+            .video_modes()
+            .find(matches)
+            .or_else(|| monitor.video_modes().max_by_key(largest))
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Immediate
+        }
+    }
+}
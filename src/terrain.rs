@@ -0,0 +1,101 @@
+use cgmath::Vector3;
+
+use crate::render::{
+    block::{Block, DIRT, GRASS, STONE},
+    chunk::{Chunk, CHUNK_SIZE},
+};
+
+// Chunks are only generated from y = 0 up to this many chunks
+pub const HEIGHT_CHUNKS: i32 = 4;
+
+const BASE_HEIGHT: f32 = 12.0;
+const HEIGHT_VARIATION: f32 = 24.0;
+// Width in blocks of the largest hills
+const SCALE: f32 = 64.0;
+const OCTAVES: u32 = 4;
+// Blocks of dirt between the grass on top and the stone below
+const DIRT_DEPTH: i32 = 3;
+
+/// Deterministic heightmap terrain, so the same seed always produces the same world
+#[derive(Copy, Clone, Debug)]
+pub struct Generator {
+    seed: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn generate(&self, position: Vector3<i32>) -> Chunk {
+        let mut chunk = Chunk::new(0, position);
+        let size = CHUNK_SIZE as i32;
+        let bottom = position.y * size;
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let height =
+                    self.surface_height(position.x * size + x as i32, position.z * size + z as i32);
+                let top = (height - bottom).min(size);
+                for y in 0..top.max(0) {
+                    let depth = height - (bottom + y) - 1;
+                    let id = match depth {
+                        0 => GRASS,
+                        d if d <= DIRT_DEPTH => DIRT,
+                        _ => STONE,
+                    };
+                    chunk.insert_block(Block::new(id), (x, y as usize, z).into());
+                }
+            }
+        }
+
+        chunk.modified = false;
+        chunk
+    }
+
+    /// The number of solid blocks in the column, counted from y = 0
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let mut height = 0.0;
+        let mut amplitude = 0.5;
+        let mut frequency = 1.0 / SCALE;
+        for octave in 0..OCTAVES {
+            height +=
+                amplitude * self.value_noise(x as f32 * frequency, z as f32 * frequency, octave);
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        (BASE_HEIGHT + height * HEIGHT_VARIATION) as i32
+    }
+
+    // Smoothly interpolated random values on an integer lattice, in [0, 1)
+    fn value_noise(&self, x: f32, z: f32, octave: u32) -> f32 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (tx, tz) = (smoothstep(x - x0), smoothstep(z - z0));
+        let (x0, z0) = (x0 as i32, z0 as i32);
+
+        let corner = |dx, dz| self.lattice(x0 + dx, z0 + dz, octave);
+        let top = lerp(corner(0, 0), corner(1, 0), tx);
+        let bottom = lerp(corner(0, 1), corner(1, 1), tx);
+        lerp(top, bottom, tz)
+    }
+
+    fn lattice(&self, x: i32, z: i32, octave: u32) -> f32 {
+        let mut h = self.seed ^ (octave as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        h ^= (x as u32 as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h ^= (z as u32 as u64).wrapping_mul(0x94d0_49bb_1331_11eb) << 1;
+        // splitmix64 finalizer
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+        (h >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
@@ -0,0 +1,591 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use wgpu_glyph::{GlyphBrush, HorizontalAlign, Layout, Section, Text, VerticalAlign};
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::error::Result;
+use crate::render::{
+    graphics::Graphics,
+    quad::{Quad, QuadRenderer, Rect},
+    txt::Txt,
+};
+
+const PADDING: f32 = 12.0;
+const SPACING: f32 = 8.0;
+const ROW_HEIGHT: f32 = 36.0;
+const TEXT_SCALE: f32 = 24.0;
+const HEADING_SCALE: f32 = 40.0;
+
+pub const TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.08, 0.08, 0.1, 0.8];
+const WIDGET_COLOR: [f32; 4] = [0.25, 0.25, 0.3, 1.0];
+const HOT_COLOR: [f32; 4] = [0.35, 0.35, 0.42, 1.0];
+const ACTIVE_COLOR: [f32; 4] = [0.2, 0.2, 0.24, 1.0];
+pub const ACCENT_COLOR: [f32; 4] = [1.0, 0.8, 0.3, 1.0];
+
+pub type WidgetId = u64;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Direction {
+    Vertical,
+    Horizontal,
+}
+
+// Where the next widget goes within a panel or row
+struct Cursor {
+    area: Rect,
+    x: f32,
+    y: f32,
+    direction: Direction,
+    // Width given to each widget of a horizontal row
+    column_width: f32,
+}
+
+struct QueuedText {
+    text: String,
+    position: (f32, f32),
+    scale: f32,
+    color: [f32; 4],
+    align: (HorizontalAlign, VerticalAlign),
+}
+
+/// Immediate mode UI. Widgets are declared every frame between `begin_frame`
+/// and `end_frame`, and report their interaction straight away, e.g. `button`
+/// returns true on the frame it's clicked.
+pub struct Ui {
+    width: f32,
+    height: f32,
+
+    // Input gathered since the last frame
+    mouse: (f32, f32),
+    mouse_down: bool,
+    clicked: bool,
+    right_clicked: bool,
+    released: bool,
+    scroll: f32,
+    typed: String,
+    keys: Vec<VirtualKeyCode>,
+
+    // The widget under the mouse, the one being pressed or dragged, and the
+    // text input receiving the keyboard
+    hot: Option<WidgetId>,
+    active: Option<WidgetId>,
+    focused: Option<WidgetId>,
+
+    // Panels from the last frame. Mouse input over them stays in the UI.
+    blocking: Vec<Rect>,
+    panels: Vec<Rect>,
+    cursors: Vec<Cursor>,
+    ids: Vec<WidgetId>,
+
+    quads: Vec<Quad>,
+    text: Vec<QueuedText>,
+    pub renderer: QuadRenderer,
+    glyph_brush: GlyphBrush<()>,
+}
+
+impl Ui {
+    pub fn new(graphics: &Graphics) -> Result<Self> {
+        Ok(Self {
+            width: graphics.size.width as f32,
+            height: graphics.size.height as f32,
+            mouse: (0.0, 0.0),
+            mouse_down: false,
+            clicked: false,
+            right_clicked: false,
+            released: false,
+            scroll: 0.0,
+            typed: String::new(),
+            keys: Vec::new(),
+            hot: None,
+            active: None,
+            focused: None,
+            blocking: Vec::new(),
+            panels: Vec::new(),
+            cursors: Vec::new(),
+            ids: Vec::new(),
+            quads: Vec::new(),
+            text: Vec::new(),
+            renderer: QuadRenderer::new(graphics)?,
+            glyph_brush: Txt::create_glyph_brush(&graphics.device, graphics.format)?,
+        })
+    }
+
+    /// Records input for the next frame. Returns true if the UI is using it,
+    /// in which case it shouldn't reach the world.
+    pub fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        let over_ui = self.blocking.iter().any(|rect| rect.contains(self.mouse));
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse = (position.x as f32, position.y as f32);
+                over_ui || self.active.is_some()
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let consumed = over_ui || self.active.is_some();
+                match state {
+                    ElementState::Pressed => {
+                        self.mouse_down = true;
+                        self.clicked = true;
+                    }
+                    ElementState::Released => {
+                        self.mouse_down = false;
+                        self.released = true;
+                    }
+                }
+                consumed
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => {
+                if *state == ElementState::Pressed {
+                    self.right_clicked = true;
+                }
+                over_ui
+            }
+            WindowEvent::MouseWheel { delta, .. } if over_ui => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / ROW_HEIGHT,
+                };
+                true
+            }
+            WindowEvent::ReceivedCharacter(c) if self.focused.is_some() => {
+                if !c.is_control() {
+                    self.typed.push(*c);
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if self.focused.is_some() => {
+                if *state == ElementState::Pressed {
+                    self.keys.push(*key);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a text input has the keyboard
+    pub fn wants_keyboard(&self) -> bool {
+        self.focused.is_some()
+    }
+
+    pub fn begin_frame(&mut self, width: u32, height: u32) {
+        self.width = width as f32;
+        self.height = height as f32;
+        self.hot = None;
+        self.panels.clear();
+        self.cursors.clear();
+        self.ids.clear();
+        self.quads.clear();
+        self.text.clear();
+    }
+
+    pub fn end_frame(&mut self) {
+        if self.released {
+            self.active = None;
+        }
+        // Clicking anywhere else takes the keyboard away from a text input
+        if self.clicked && self.hot != self.focused {
+            self.focused = None;
+        }
+
+        self.blocking = std::mem::take(&mut self.panels);
+        self.clicked = false;
+        self.right_clicked = false;
+        self.released = false;
+        self.scroll = 0.0;
+        self.typed.clear();
+        self.keys.clear();
+    }
+
+    pub fn screen(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.width, self.height)
+    }
+
+    pub fn mouse(&self) -> (f32, f32) {
+        self.mouse
+    }
+
+    /// Draws a panel and lays out the widgets added in `add` top to bottom
+    /// inside it. Returns what `add` returns.
+    pub fn panel<R>(&mut self, rect: Rect, add: impl FnOnce(&mut Self) -> R) -> R {
+        self.quads.push(Quad::colored(rect, PANEL_COLOR));
+        self.panels.push(rect);
+
+        let area = rect.inset(PADDING);
+        self.cursors.push(Cursor {
+            area,
+            x: area.x,
+            y: area.y,
+            direction: Direction::Vertical,
+            column_width: area.w,
+        });
+        let result = add(self);
+        self.cursors.pop();
+        result
+    }
+
+    /// A panel of the given size in the middle of the screen
+    pub fn centered_panel<R>(
+        &mut self,
+        width: f32,
+        height: f32,
+        add: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let rect = Rect::new(
+            ((self.width - width) / 2.0).floor(),
+            ((self.height - height) / 2.0).floor(),
+            width,
+            height,
+        );
+        self.panel(rect, add)
+    }
+
+    /// Splits the next row into `columns` equal widgets laid out left to right
+    pub fn columns<R>(&mut self, columns: usize, add: impl FnOnce(&mut Self) -> R) -> R {
+        let row = self.allocate(ROW_HEIGHT);
+        let column_width = (row.w - SPACING * (columns as f32 - 1.0)) / columns as f32;
+        self.cursors.push(Cursor {
+            area: row,
+            x: row.x,
+            y: row.y,
+            direction: Direction::Horizontal,
+            column_width,
+        });
+        let result = add(self);
+        self.cursors.pop();
+        result
+    }
+
+    /// Leaves a gap before the next widget
+    pub fn space(&mut self, amount: f32) {
+        if let Some(cursor) = self.cursors.last_mut() {
+            match cursor.direction {
+                Direction::Vertical => cursor.y += amount,
+                Direction::Horizontal => cursor.x += amount,
+            }
+        }
+    }
+
+    // Takes the space for the next widget from the current panel or row
+    fn allocate(&mut self, height: f32) -> Rect {
+        let (screen_w, screen_h) = (self.width, self.height);
+        let cursor = match self.cursors.last_mut() {
+            Some(cursor) => cursor,
+            None => return Rect::new(0.0, 0.0, screen_w, screen_h),
+        };
+
+        match cursor.direction {
+            Direction::Vertical => {
+                let rect = Rect::new(cursor.x, cursor.y, cursor.area.w, height);
+                cursor.y += height + SPACING;
+                rect
+            }
+            Direction::Horizontal => {
+                let rect = Rect::new(cursor.x, cursor.y, cursor.column_width, cursor.area.h);
+                cursor.x += cursor.column_width + SPACING;
+                rect
+            }
+        }
+    }
+
+    // Widgets are identified by their label and position in the frame, so two
+    // buttons with the same text in different panels don't clash
+    fn id(&mut self, label: &str) -> WidgetId {
+        let mut hasher = DefaultHasher::new();
+        label.hash(&mut hasher);
+        self.panels.len().hash(&mut hasher);
+        let mut id = hasher.finish();
+        while self.ids.contains(&id) {
+            id = id.wrapping_add(1);
+        }
+        self.ids.push(id);
+        id
+    }
+
+    // Updates hot/active for a widget covering `rect`, returning whether it
+    // was pressed this frame
+    fn interact(&mut self, id: WidgetId, rect: Rect) -> bool {
+        if rect.contains(self.mouse) {
+            self.hot = Some(id);
+            if self.clicked && self.active.is_none() {
+                self.active = Some(id);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn widget_color(&self, id: WidgetId) -> [f32; 4] {
+        if self.active == Some(id) {
+            ACTIVE_COLOR
+        } else if self.hot == Some(id) {
+            HOT_COLOR
+        } else {
+            WIDGET_COLOR
+        }
+    }
+
+    /// An area laid out by the caller that reacts to both mouse buttons.
+    /// Returns the button that clicked it this frame.
+    pub fn click_area(&mut self, label: &str, rect: Rect) -> Option<MouseButton> {
+        let id = self.id(label);
+        if self.interact(id, rect) {
+            Some(MouseButton::Left)
+        } else if self.right_clicked && rect.contains(self.mouse) {
+            Some(MouseButton::Right)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the mouse is over `rect`
+    pub fn hovered(&self, rect: Rect) -> bool {
+        rect.contains(self.mouse)
+    }
+
+    pub fn quad(&mut self, quad: Quad) {
+        self.quads.push(quad);
+    }
+
+    pub fn text(
+        &mut self,
+        text: &str,
+        position: (f32, f32),
+        scale: f32,
+        color: [f32; 4],
+        align: HorizontalAlign,
+    ) {
+        self.text.push(QueuedText {
+            text: text.to_string(),
+            position,
+            scale,
+            color,
+            align: (align, VerticalAlign::Center),
+        });
+    }
+
+    pub fn heading(&mut self, text: &str) {
+        let rect = self.allocate(HEADING_SCALE);
+        self.text(
+            text,
+            rect.center(),
+            HEADING_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Center,
+        );
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let rect = self.allocate(ROW_HEIGHT);
+        self.text(
+            text,
+            (rect.x, rect.center().1),
+            TEXT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Left,
+        );
+    }
+
+    /// Returns true when clicked
+    pub fn button(&mut self, label: &str) -> bool {
+        let id = self.id(label);
+        let rect = self.allocate(ROW_HEIGHT);
+        let clicked = self.interact(id, rect);
+
+        self.quads.push(Quad::colored(rect, self.widget_color(id)));
+        self.text(
+            label,
+            rect.center(),
+            TEXT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Center,
+        );
+        clicked
+    }
+
+    /// A toggle showing its state next to the label. Returns true when changed.
+    pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+        let text = format!("{}: {}", label, if *value { "on" } else { "off" });
+        let id = self.id(label);
+        let rect = self.allocate(ROW_HEIGHT);
+        let clicked = self.interact(id, rect);
+        if clicked {
+            *value = !*value;
+        }
+
+        self.quads.push(Quad::colored(rect, self.widget_color(id)));
+        self.text(
+            &text,
+            rect.center(),
+            TEXT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Center,
+        );
+        clicked
+    }
+
+    /// Drags `value` between `min` and `max` in multiples of `step`. Scrolling
+    /// over the slider moves it a step at a time. Returns true when changed.
+    pub fn slider(&mut self, label: &str, value: &mut f32, min: f32, max: f32, step: f32) -> bool {
+        let id = self.id(label);
+        let rect = self.allocate(ROW_HEIGHT);
+        self.interact(id, rect);
+
+        let old = *value;
+        if self.active == Some(id) && self.mouse_down {
+            let t = ((self.mouse.0 - rect.x) / rect.w).clamp(0.0, 1.0);
+            *value = min + (max - min) * t;
+        } else if self.hot == Some(id) && self.scroll != 0.0 {
+            *value += step * self.scroll.signum();
+        }
+        *value = ((*value - min) / step).round() * step + min;
+        *value = value.clamp(min, max);
+
+        let t = if max > min {
+            (*value - min) / (max - min)
+        } else {
+            0.0
+        };
+        self.quads.push(Quad::colored(rect, self.widget_color(id)));
+        self.quads.push(Quad::colored(
+            Rect::new(rect.x, rect.y, rect.w * t, rect.h),
+            [0.4, 0.4, 0.5, 1.0],
+        ));
+
+        let decimals = if step.fract() == 0.0 { 0 } else { 2 };
+        self.text(
+            &format!("{}: {:.*}", label, decimals, value),
+            rect.center(),
+            TEXT_SCALE,
+            TEXT_COLOR,
+            HorizontalAlign::Center,
+        );
+        (*value - old).abs() > f32::EPSILON
+    }
+
+    /// A single line of editable text. Clicking it takes the keyboard until
+    /// Enter, Escape or a click elsewhere. Returns true when Enter is pressed.
+    pub fn text_input(&mut self, label: &str, text: &mut String) -> bool {
+        let id = self.id(label);
+        let rect = self.allocate(ROW_HEIGHT);
+        if self.interact(id, rect) {
+            self.focused = Some(id);
+        }
+
+        let mut submitted = false;
+        if self.focused == Some(id) {
+            text.push_str(&self.typed);
+            for key in &self.keys {
+                match key {
+                    VirtualKeyCode::Back => {
+                        text.pop();
+                    }
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        submitted = true;
+                        self.focused = None;
+                    }
+                    VirtualKeyCode::Escape => self.focused = None,
+                    _ => {}
+                }
+            }
+        }
+
+        let focused = self.focused == Some(id);
+        self.quads.push(Quad::colored(rect, ACTIVE_COLOR));
+        if focused {
+            // Underline while typing
+            self.quads.push(Quad::colored(
+                Rect::new(rect.x, rect.y + rect.h - 2.0, rect.w, 2.0),
+                ACCENT_COLOR,
+            ));
+        }
+
+        let shown = if text.is_empty() && !focused {
+            label.to_string()
+        } else if focused {
+            format!("{}_", text)
+        } else {
+            text.clone()
+        };
+        let color = if text.is_empty() && !focused {
+            [0.6, 0.6, 0.6, 1.0]
+        } else {
+            TEXT_COLOR
+        };
+        self.text(
+            &shown,
+            (rect.x + SPACING, rect.center().1),
+            TEXT_SCALE,
+            color,
+            HorizontalAlign::Left,
+        );
+        submitted
+    }
+
+    /// Draws everything declared this frame over `frame`
+    pub fn render(&mut self, graphics: &Graphics, frame: &wgpu::TextureView) {
+        self.render_scaled(graphics, frame, 1);
+    }
+
+    /// Draws everything declared this frame over `target`, which is `scale`
+    /// times the size the UI was laid out for. Text is laid out again at that
+    /// scale so it stays sharp.
+    pub fn render_scaled(&mut self, graphics: &Graphics, target: &wgpu::TextureView, scale: u32) {
+        if self.quads.is_empty() && self.text.is_empty() {
+            return;
+        }
+        let (width, height) = (self.width as u32, self.height as u32);
+        let factor = scale as f32;
+
+        for text in &self.text {
+            self.glyph_brush.queue(Section {
+                screen_position: (text.position.0 * factor, text.position.1 * factor),
+                bounds: (self.width * factor, self.height * factor),
+                text: vec![Text::new(&text.text)
+                    .with_scale(text.scale * factor)
+                    .with_color(text.color)],
+                layout: Layout::default_single_line()
+                    .h_align(text.align.0)
+                    .v_align(text.align.1),
+            });
+        }
+
+        let mut encoder = graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("ui encoder"),
+            });
+        // Quads are placed in normalized coordinates, so they fill a larger
+        // target without scaling
+        self.renderer
+            .draw(graphics, &mut encoder, target, &self.quads, width, height);
+        self.glyph_brush
+            .draw_queued(
+                &graphics.device,
+                &mut encoder,
+                target,
+                width * scale,
+                height * scale,
+            )
+            .unwrap_or_else(|e| log::error!("Failed to draw UI text: {}", e));
+        graphics.queue.submit(&[encoder.finish()]);
+    }
+}
@@ -0,0 +1,487 @@
+use cgmath::Vector3;
+use std::path::Path;
+
+use crate::edit::{Clipboard, Region};
+use crate::error::{Error, Result};
+use crate::render::block::{to_rgb, Palette};
+
+const VOX_MAGIC: &[u8; 4] = b"VOX ";
+const VOX_VERSION: u32 = 150;
+// Models can't be larger than this along any axis, as voxel positions are bytes
+const MAX_MODEL_SIZE: i32 = 256;
+
+/// A model from a MagicaVoxel file. Like MagicaVoxel, z is up.
+#[derive(Clone, Debug)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<Voxel>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Voxel {
+    pub position: [u8; 3],
+    // Index into the palette, never 0
+    pub color: u8,
+}
+
+/// The models in a .vox file and the palette they share
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    // RGBA colors by palette index. Index 0 is unused, it stands for empty.
+    pub palette: [[u8; 4]; 256],
+}
+
+impl VoxFile {
+    /// A model as blocks, turned so that its z axis points up. Each color
+    /// becomes the block type with that color, which is added to the world's
+    /// `palette` if no type has it yet, failing if the palette is full.
+    pub fn clipboard(&self, model: usize, palette: &mut Palette) -> Result<Clipboard> {
+        let model = &self.models[model];
+        let size = Vector3::new(model.size[0], model.size[2], model.size[1]).map(|v| v as i32);
+        let mut blocks = vec![None; (size.x * size.y * size.z) as usize];
+        let mut ids = [None; 256];
+        for voxel in &model.voxels {
+            let [x, y, z] = voxel.position.map(|v| v as i32);
+            let index = ((x * size.y + z) * size.z + (size.z - 1 - y)) as usize;
+            let color = self.palette[voxel.color as usize];
+            let id = match ids[voxel.color as usize] {
+                Some(id) => id,
+                None => palette.color_block([color[0], color[1], color[2]])?,
+            };
+            ids[voxel.color as usize] = Some(id);
+            blocks[index] = Some(id);
+        }
+        Ok(Clipboard::new(size, blocks))
+    }
+
+    /// A single model of copied blocks, with a palette of their colors in
+    /// the world's `palette`
+    pub fn from_clipboard(clipboard: &Clipboard, palette: &Palette) -> Result<Self> {
+        let size = clipboard.size();
+        if (0..3).any(|axis| size[axis] > MAX_MODEL_SIZE) {
+            return Err(Error::CorruptVox(format!(
+                "models can't be larger than {} blocks along any axis",
+                MAX_MODEL_SIZE
+            )));
+        }
+
+        let mut colors = [[0; 4]; 256];
+        let mut used = 0;
+        let mut voxels = Vec::new();
+        let region = Region::new(Vector3::new(0, 0, 0), size - Vector3::new(1, 1, 1));
+        for (position, block) in region.positions().zip(clipboard.blocks()) {
+            let id = match block {
+                Some(id) => *id,
+                None => continue,
+            };
+            let [r, g, b] = to_rgb(palette.block_type(id).color);
+            let rgba = [r, g, b, 255];
+            let color = match colors[1..=used].iter().position(|&color| color == rgba) {
+                Some(index) => index + 1,
+                None if used < 255 => {
+                    used += 1;
+                    colors[used] = rgba;
+                    used
+                }
+                None => return Err(Error::CorruptVox("more than 255 colors".into())),
+            };
+            voxels.push(Voxel {
+                position: [
+                    position.x as u8,
+                    (size.z - 1 - position.z) as u8,
+                    position.y as u8,
+                ],
+                color: color as u8,
+            });
+        }
+
+        Ok(Self {
+            models: vec![VoxModel {
+                size: [size.x as u32, size.z as u32, size.y as u32],
+                voxels,
+            }],
+            palette: colors,
+        })
+    }
+}
+
+/// The palette MagicaVoxel uses for files without an RGBA chunk: a cube of
+/// six levels per channel without black, then ramps of red, green, blue and
+/// gray
+pub fn default_palette() -> [[u8; 4]; 256] {
+    const LEVELS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+    for &r in &LEVELS {
+        for &g in &LEVELS {
+            for &b in &LEVELS {
+                if r == 0 && g == 0 && b == 0 {
+                    continue;
+                }
+                palette[index] = [r, g, b, 255];
+                index += 1;
+            }
+        }
+    }
+    for channel in 0..3 {
+        for &level in &RAMP {
+            palette[index] = [0, 0, 0, 255];
+            palette[index][channel] = level;
+            index += 1;
+        }
+    }
+    for &level in &RAMP {
+        palette[index] = [level, level, level, 255];
+        index += 1;
+    }
+    palette
+}
+
+/// Reads the models and palette of a .vox file. Chunks other than SIZE, XYZI
+/// and RGBA, such as the scene graph and materials, are skipped.
+pub fn decode(data: &[u8]) -> Result<VoxFile> {
+    let corrupt = |reason: &str| Error::CorruptVox(reason.to_string());
+    let mut reader = Reader { data, offset: 0 };
+    if reader.bytes(4).ok() != Some(&VOX_MAGIC[..]) {
+        return Err(corrupt("missing header"));
+    }
+    reader.u32()?;
+    let (id, _, children) = reader.chunk()?;
+    if id != b"MAIN" {
+        return Err(corrupt("missing MAIN chunk"));
+    }
+
+    let mut file = VoxFile {
+        models: Vec::new(),
+        palette: default_palette(),
+    };
+    let mut size = None;
+    let mut reader = Reader {
+        data: children,
+        offset: 0,
+    };
+    while reader.offset < children.len() {
+        let (id, content, _) = reader.chunk()?;
+        let mut content = Reader {
+            data: content,
+            offset: 0,
+        };
+        match id {
+            b"SIZE" => {
+                let model_size = [content.u32()?, content.u32()?, content.u32()?];
+                if model_size
+                    .iter()
+                    .any(|&v| v == 0 || v > MAX_MODEL_SIZE as u32)
+                {
+                    return Err(corrupt("bad model size"));
+                }
+                size = Some(model_size);
+            }
+            b"XYZI" => {
+                let size = size.take().ok_or_else(|| corrupt("XYZI without SIZE"))?;
+                let count = content.u32()? as usize;
+                let mut voxels = Vec::with_capacity(count.min(content.data.len() / 4));
+                for _ in 0..count {
+                    let bytes = content.bytes(4)?;
+                    let position = [bytes[0], bytes[1], bytes[2]];
+                    if (0..3).any(|axis| position[axis] as u32 >= size[axis]) {
+                        return Err(corrupt("voxel outside its model"));
+                    }
+                    // Index 0 is empty
+                    if bytes[3] != 0 {
+                        voxels.push(Voxel {
+                            position,
+                            color: bytes[3],
+                        });
+                    }
+                }
+                file.models.push(VoxModel { size, voxels });
+            }
+            b"RGBA" => {
+                // Colors are stored from palette index 1, the last one is unused
+                for index in 1..256 {
+                    let bytes = content.bytes(4)?;
+                    file.palette[index] = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if file.models.is_empty() {
+        return Err(corrupt("no models"));
+    }
+    Ok(file)
+}
+
+pub fn encode(file: &VoxFile) -> Vec<u8> {
+    let mut children = Vec::new();
+    for model in &file.models {
+        let mut size = Vec::with_capacity(12);
+        for &v in &model.size {
+            size.extend_from_slice(&v.to_le_bytes());
+        }
+        write_chunk(&mut children, b"SIZE", &size);
+
+        let mut voxels = Vec::with_capacity(4 + model.voxels.len() * 4);
+        voxels.extend_from_slice(&(model.voxels.len() as u32).to_le_bytes());
+        for voxel in &model.voxels {
+            voxels.extend_from_slice(&voxel.position);
+            voxels.push(voxel.color);
+        }
+        write_chunk(&mut children, b"XYZI", &voxels);
+    }
+    let mut palette = Vec::with_capacity(256 * 4);
+    for color in file.palette[1..].iter().chain(&[[0; 4]]) {
+        palette.extend_from_slice(color);
+    }
+    write_chunk(&mut children, b"RGBA", &palette);
+
+    let mut data = Vec::with_capacity(20 + children.len());
+    data.extend_from_slice(VOX_MAGIC);
+    data.extend_from_slice(&VOX_VERSION.to_le_bytes());
+    data.extend_from_slice(b"MAIN");
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&(children.len() as u32).to_le_bytes());
+    data.extend_from_slice(&children);
+    data
+}
+
+// A chunk with content but no children
+fn write_chunk(data: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    data.extend_from_slice(id);
+    data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(content);
+}
+
+pub fn save(path: &Path, clipboard: &Clipboard, palette: &Palette) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+    }
+    let data = encode(&VoxFile::from_clipboard(clipboard, palette)?);
+    std::fs::write(path, data).map_err(Error::io(path))?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<VoxFile> {
+    decode(&std::fs::read(path).map_err(Error::io(path))?)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .offset
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.offset..end))
+            .ok_or_else(|| Error::CorruptVox("truncated".into()))?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // A chunk's id, content and children
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8], &'a [u8])> {
+        let id = self.bytes(4)?;
+        let content = self.u32()? as usize;
+        let children = self.u32()? as usize;
+        Ok((id, self.bytes(content)?, self.bytes(children)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{
+        block::{BLOCK_TYPES, STONE},
+        chunk::{Chunk, ChunkManager, ChunkPosition},
+    };
+    use std::collections::HashMap;
+
+    // An 18 x 17 x 20 tower with its own palette: a stone floor and walls of
+    // color 1, except for color 3 at the top of the corner at the origin. It
+    // also has a transform node, which should be skipped.
+    const TOWER: &[u8] = include_bytes!("../tests/fixtures/tower.vox");
+    // Two models and no RGBA chunk: a line of 17 voxels along x stepping
+    // through default palette indices 1, 2, 216 and 255, then a single voxel
+    const DEFAULT_PALETTE: &[u8] = include_bytes!("../tests/fixtures/default_palette.vox");
+
+    const WALL: [u8; 3] = [0x9b, 0x3d, 0x7a];
+    const TOP: [u8; 3] = [0x12, 0xd4, 0xe1];
+
+    fn empty() -> ChunkManager {
+        let mut chunks = ChunkManager::new(HashMap::new());
+        for x in -1..=1 {
+            for y in 0..=1 {
+                for z in -1..=1 {
+                    chunks.add_chunk(Chunk::new(0, Vector3::new(x, y, z)));
+                }
+            }
+        }
+        chunks
+    }
+
+    fn block_ids(chunks: &ChunkManager, position: ChunkPosition) -> Vec<Option<usize>> {
+        chunks
+            .get_chunk(&position)
+            .unwrap()
+            .blocks
+            .iter()
+            .map(|block| block.map(|block| block.id()))
+            .collect()
+    }
+
+    fn color_at(
+        chunks: &ChunkManager,
+        palette: &Palette,
+        position: Vector3<i32>,
+    ) -> Option<[u8; 3]> {
+        chunks
+            .block(position)
+            .map(|block| to_rgb(palette.block_type(block.id()).color))
+    }
+
+    #[test]
+    fn parses_model_larger_than_a_chunk() {
+        let file = decode(TOWER).unwrap();
+        assert_eq!(file.models.len(), 1);
+        let model = &file.models[0];
+        assert_eq!(model.size, [18, 17, 20]);
+        // The floor and the walls above it
+        let walls = (2 * (18 + 17) - 4) * 19;
+        assert_eq!(model.voxels.len(), 18 * 17 + walls);
+        assert_eq!(&file.palette[1][..3], &WALL);
+        assert_eq!(&file.palette[3][..3], &TOP);
+    }
+
+    #[test]
+    fn places_model_across_chunks() {
+        let file = decode(TOWER).unwrap();
+        let mut palette = Palette::default();
+        let mut chunks = empty();
+        let origin = Vector3::new(-4, 0, -4);
+        let (summary, _) =
+            chunks.apply_edit(&file.clipboard(0, &mut palette).unwrap().paste(origin));
+        assert_eq!(summary.unloaded, 0);
+        assert_eq!(summary.changed, file.models[0].voxels.len());
+
+        // The floor uses the built-in stone, z becomes y, and y runs towards
+        // negative z, so vox (0, 0, 19) is at the far top corner
+        assert_eq!(chunks.block(origin).unwrap().id(), STONE);
+        assert_eq!(
+            color_at(&chunks, &palette, origin + Vector3::new(0, 19, 16)),
+            Some(TOP)
+        );
+        assert_eq!(
+            color_at(&chunks, &palette, origin + Vector3::new(17, 19, 0)),
+            Some(WALL)
+        );
+        assert_eq!(
+            color_at(&chunks, &palette, origin + Vector3::new(8, 19, 8)),
+            None
+        );
+        assert_eq!(
+            color_at(&chunks, &palette, origin + Vector3::new(17, 20, 0)),
+            None
+        );
+
+        let touched = chunks
+            .chunks
+            .values()
+            .filter(|chunk| chunk.blocks.iter().any(|block| block.is_some()))
+            .count();
+        assert_eq!(touched, 8);
+    }
+
+    #[test]
+    fn uses_default_palette_without_rgba() {
+        let file = decode(DEFAULT_PALETTE).unwrap();
+        assert_eq!(file.models.len(), 2);
+        assert_eq!(file.models[0].size, [17, 1, 1]);
+        assert_eq!(file.models[1].size, [1, 1, 1]);
+
+        let colors: Vec<[u8; 4]> = file.models[0]
+            .voxels
+            .iter()
+            .take(4)
+            .map(|voxel| file.palette[voxel.color as usize])
+            .collect();
+        assert_eq!(
+            colors,
+            [
+                [0xff, 0xff, 0xff, 0xff],
+                [0xff, 0xff, 0xcc, 0xff],
+                [0xee, 0x00, 0x00, 0xff],
+                [0x11, 0x11, 0x11, 0xff],
+            ]
+        );
+    }
+
+    #[test]
+    fn exported_region_reads_back_identically() {
+        let mut palette = Palette::default();
+        let mut source = empty();
+        let tower = decode(TOWER).unwrap().clipboard(0, &mut palette).unwrap();
+        source.apply_edit(&tower.paste(Vector3::new(-4, 0, -4)));
+
+        // All eight chunks the tower touches
+        let region = Region::new(Vector3::new(-8, 0, -8), Vector3::new(23, 31, 23));
+        let copied = Clipboard::copy(&source, region);
+        let data = encode(&VoxFile::from_clipboard(&copied, &palette).unwrap());
+        let file = decode(&data).unwrap();
+        assert_eq!(file.models[0].size, [32, 32, 32]);
+
+        let mut target = empty();
+        target.apply_edit(&file.clipboard(0, &mut palette).unwrap().paste(region.min));
+        for x in 0..=1 {
+            for y in 0..=1 {
+                for z in 0..=1 {
+                    let position = Vector3::new(x, y, z);
+                    assert_eq!(block_ids(&source, position), block_ids(&target, position));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn colors_reuse_block_types() {
+        let file = decode(TOWER).unwrap();
+        let mut palette = Palette::default();
+        let first = file.clipboard(0, &mut palette).unwrap();
+        let second = file.clipboard(0, &mut palette).unwrap();
+        assert_eq!(first.blocks(), second.blocks());
+        // Only the wall and top colors needed types of their own
+        assert_eq!(palette.len(), BLOCK_TYPES.len() + 2);
+        let wall = first.blocks().iter().flatten().find(|&&id| id != STONE);
+        assert_eq!(to_rgb(palette.block_type(*wall.unwrap()).color), WALL);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        assert!(decode(&TOWER[..TOWER.len() - 1]).is_err());
+        assert!(decode(b"SCHM\x01").is_err());
+
+        let clipboard = Clipboard::new(Vector3::new(1, 1, 1), vec![Some(STONE)]);
+        let palette = Palette::default();
+        let mut data = encode(&VoxFile::from_clipboard(&clipboard, &palette).unwrap());
+        assert!(decode(&data).is_ok());
+        // Move the voxel outside its 1 x 1 x 1 model
+        let voxel = 8 + 12 + 24 + 12 + 4;
+        data[voxel] = 1;
+        assert!(decode(&data).is_err());
+
+        let too_large = Clipboard::new(Vector3::new(257, 1, 1), vec![None; 257]);
+        assert!(VoxFile::from_clipboard(&too_large, &palette).is_err());
+    }
+}
@@ -1,22 +1,79 @@
+use cgmath::{Deg, Point3, Vector3};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
 use std::time::Duration;
 use wgpu_glyph::{Section, Text};
-use winit::event::{KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{ElementState, KeyboardInput, MouseButton, WindowEvent};
 
-use crate::player::Player;
+use crate::debug::{DebugStats, FrameTimes};
+use crate::edit::{BlockChange, BlockEdit, ChangeSet, EditSummary, Editor, HistoryError};
+use crate::error::Result;
+use crate::player::{GameMode, Player};
+use crate::profiler;
+use crate::raycast::{raycast, RayHit};
+#[cfg(feature = "dev")]
+use crate::render::shader::ShaderWatcher;
 use crate::render::{
-    block::{Block, BlockVertex},
-    camera::Projection,
-    chunk::{Chunk, ChunkManager, DrawBlock},
-    graphics::{Graphics, Render},
+    block::{BlockVertex, Palette},
+    camera::{Camera, Projection},
+    chunk::{block_to_chunk, chunk_position, Chunk, ChunkManager, ChunkPosition, DrawBlock},
+    graphics::{DepthState, Graphics, PipelineDesc, Render},
+    lod::{lod_level, mesh_lod, ring},
+    outline::{DrawOutline, Outline},
+    sky::{DrawSky, Sky},
+    target::OffscreenTarget,
     texture::Texture,
     txt::Txt,
-    Uniforms, Vertex,
+    Uniforms, Vertex, UNIFORM_BINDINGS,
 };
+use crate::save::WorldSave;
+use crate::settings::Settings;
+use crate::terrain::{Generator, HEIGHT_CHUNKS};
+
+// Chunks loaded or generated per frame as the player moves
+const MAX_LOAD_FRAME: usize = 4;
+// LOD meshes built per frame, most from chunks generated just to downsample
+const MAX_LOD_FRAME: usize = 8;
+
+// Size of the debug text in a window-sized frame, wgpu_glyph's default
+const TEXT_SCALE: f32 = 16.0;
+// How far away in blocks the player can break and place blocks
+const REACH: f32 = 8.0;
+// Seconds the break button has to be held to remove a block
+const BREAK_TIME: f32 = 0.5;
+
+// Walking movement, in blocks and seconds
+const GRAVITY: f32 = 28.0;
+const JUMP_SPEED: f32 = 9.0;
+const MAX_FALL_SPEED: f32 = 50.0;
+// How far the camera is above the player's feet
+const EYE_HEIGHT: f32 = 1.6;
+
+// Height of a line of the debug overlay at the default text scale
+const DEBUG_LINE_HEIGHT: f32 = 18.0;
 
 pub struct World {
     player: Player,
     chunks: ChunkManager,
+    // Block types of this world, saved with its level
+    palette: Palette,
+    save: WorldSave,
+    generator: Generator,
+    // Chunks in range that haven't been loaded or generated yet
+    loading: bool,
     text: Txt,
+    show_debug: bool,
+    frame_times: FrameTimes,
+    adapter: wgpu::AdapterInfo,
+    sky: Sky,
+    outline: Outline,
+    editor: Editor,
+
+    // The block the player is looking at, and how far it is from breaking
+    target: Option<RayHit>,
+    breaking: bool,
+    break_progress: f32,
     projection: Projection,
 
     depth_texture: Texture,
@@ -25,11 +82,20 @@ pub struct World {
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
 
-    pipeline: wgpu::RenderPipeline,
+    pipeline: Rc<wgpu::RenderPipeline>,
+
+    #[cfg(feature = "dev")]
+    shader_watcher: ShaderWatcher,
 }
 
 impl World {
-    pub fn new(player: Player, projection: Projection, graphics: &Graphics) -> Self {
+    pub fn new(
+        player: Player,
+        projection: Projection,
+        settings: &Settings,
+        save: WorldSave,
+        graphics: &Graphics,
+    ) -> Result<Self> {
         let mut uniforms = Uniforms::new();
         uniforms.update_camera(&player.camera, &projection);
 
@@ -39,16 +105,7 @@ impl World {
         );
 
         let uniform_bind_group_layout =
-            graphics
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    bindings: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::VERTEX,
-                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                    }],
-                    label: Some("uniform_bind_group_layout"),
-                });
+            graphics.bind_group_layout("uniform_bind_group_layout", UNIFORM_BINDINGS);
 
         let uniform_bind_group = graphics
             .device
@@ -64,82 +121,548 @@ impl World {
                 label: Some("uniform_bind_group_layout"),
             });
 
-        let mut block_chunk = Chunk::new(0, (0, 1, 0).into());
-        block_chunk.insert_block(Block::new(0), (7, 3, 7).into());
-        block_chunk.insert_block(Block::new(0), (8, 3, 7).into());
-        block_chunk.insert_block(Block::new(0), (6, 3, 7).into());
-        block_chunk.insert_block(Block::new(0), (6, 0, 7).into());
-        block_chunk.insert_block(Block::new(0), (7, 0, 7).into());
-        block_chunk.insert_block(Block::new(0), (8, 0, 7).into());
-        block_chunk.insert_block(Block::new(0), (7, 1, 7).into());
-        block_chunk.insert_block(Block::new(0), (6, 0, 8).into());
-        block_chunk.insert_block(Block::new(0), (8, 0, 8).into());
-        let mut chunks = ChunkManager::default(20);
-        chunks.add_chunk(block_chunk);
-        chunks
-            .get_chunk_mut(&(0, 0, 0).into())
-            .unwrap()
-            .remove_block((0, 15, 0).into());
-        chunks
-            .get_chunk_mut(&(0, 0, 0).into())
-            .unwrap()
-            .remove_block((8, 15, 8).into());
-        chunks
-            .get_chunk_mut(&(0, 0, 0).into())
-            .unwrap()
-            .remove_block((8, 14, 8).into());
-        chunks
-            .get_chunk_mut(&(0, 0, 0).into())
-            .unwrap()
-            .remove_block((8, 15, 9).into());
-        chunks
-            .get_chunk_mut(&(0, 0, 0).into())
-            .unwrap()
-            .remove_block((9, 15, 10).into());
-
-        let vs_src = include_str!("../shaders/shader.vert");
-        let fs_src = include_str!("../shaders/shader.frag");
-        let pipeline_layout =
-            graphics
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    bind_group_layouts: &[&uniform_bind_group_layout],
-                });
+        let mut chunks = ChunkManager::new(HashMap::new());
+        chunks.set_render_distance(settings.render_distance);
+        chunks.set_view_distance(settings.view_distance);
+        let generator = Generator::new(save.level.seed);
+        let palette = Palette::from_colors(&save.level.block_colors);
 
-        let pipeline = graphics.create_render_pipeline(
-            &pipeline_layout,
-            Some(Texture::DEPTH_FORMAT),
-            &[BlockVertex::desc()],
-            vs_src,
-            fs_src,
-        );
+        let pipeline = Self::create_pipeline(graphics)?;
+
+        let skybox_dir = Path::new("assets/skybox");
+        let sky = Sky::new(graphics, Some(skybox_dir).filter(|dir| dir.exists()))?;
 
-        let depth_texture =
-            Texture::create_depth_texture(&graphics.device, &graphics.sc_desc, "depth_texture");
+        let outline = Outline::new(graphics)?;
 
-        let text = Txt::new(String::from("x: y: z: "), &graphics.device);
+        let depth_texture = Texture::create_depth_texture(
+            &graphics.device,
+            graphics.size.width,
+            graphics.size.height,
+            "depth_texture",
+        );
+
+        let text = Txt::new(String::from("x: y: z: "), &graphics.device, graphics.format)?;
 
-        Self {
+        Ok(Self {
             player,
             chunks,
+            palette,
+            save,
+            generator,
+            loading: true,
             text,
+            show_debug: false,
+            frame_times: FrameTimes::new(),
+            adapter: graphics.adapter_info(),
+            sky,
+            outline,
+            editor: Editor::default(),
+            target: None,
+            breaking: false,
+            break_progress: 0.0,
             projection,
             depth_texture,
             uniforms,
             uniform_buffer,
             uniform_bind_group,
             pipeline,
+            #[cfg(feature = "dev")]
+            shader_watcher: ShaderWatcher::new(),
+        })
+    }
+
+    fn create_pipeline(graphics: &Graphics) -> Result<Rc<wgpu::RenderPipeline>> {
+        graphics.create_render_pipeline(&PipelineDesc {
+            bind_group_layouts: &[UNIFORM_BINDINGS],
+            vertex_shader: "shader.vert",
+            fragment_shader: "shader.frag",
+            depth_state: Some(DepthState::new(Texture::DEPTH_FORMAT)),
+            alpha_blend: false,
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            vertex_buffers: &[BlockVertex::desc()],
+        })
+    }
+
+    /// Rebuilds the pipelines whose shaders changed on disk. A pipeline that
+    /// fails to compile is left as it was and the error is shown on screen.
+    #[cfg(feature = "dev")]
+    fn reload_shaders(&mut self, graphics: &Graphics) {
+        let changed = self.shader_watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+        for name in &changed {
+            graphics.invalidate_shader(name);
+        }
+
+        if changed.iter().any(|name| name.starts_with("shader.")) {
+            log::info!("Reloading block shaders");
+            match Self::create_pipeline(graphics) {
+                Ok(pipeline) => {
+                    self.pipeline = pipeline;
+                    self.text.shader_errors.remove("block");
+                }
+                Err(e) => {
+                    self.text.shader_errors.insert("block", e.to_string());
+                }
+            }
+        }
+
+        if changed.iter().any(|name| name.starts_with("sky.")) {
+            log::info!("Reloading sky shaders");
+            match self.sky.reload(graphics) {
+                Ok(()) => {
+                    self.text.shader_errors.remove("sky");
+                }
+                Err(e) => {
+                    self.text.shader_errors.insert("sky", e.to_string());
+                }
+            }
+        }
+
+        if changed.iter().any(|name| name.starts_with("outline.")) {
+            log::info!("Reloading outline shaders");
+            match self.outline.reload(graphics) {
+                Ok(()) => {
+                    self.text.shader_errors.remove("outline");
+                }
+                Err(e) => {
+                    self.text.shader_errors.insert("outline", e.to_string());
+                }
+            }
         }
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>, graphics: &Graphics) {
         self.projection.resize(new_size.width, new_size.height);
 
-        self.depth_texture =
-            Texture::create_depth_texture(&graphics.device, &graphics.sc_desc, "depth_texture");
+        self.depth_texture = Texture::create_depth_texture(
+            &graphics.device,
+            new_size.width,
+            new_size.height,
+            "depth_texture",
+        );
+    }
+
+    pub fn toggle_debug(&mut self) {
+        self.show_debug = !self.show_debug;
+    }
+
+    pub fn debug_visible(&self) -> bool {
+        self.show_debug
+    }
+
+    pub fn frame_times(&self) -> &FrameTimes {
+        &self.frame_times
+    }
+
+    fn update_debug(&mut self) {
+        let camera = &self.player.camera;
+        let target = self.target.map(|target| {
+            let id = self
+                .chunks
+                .block(target.block)
+                .map_or(0, |block| block.id());
+            (target.block, id)
+        });
+        let stats = DebugStats {
+            frames: self.frame_times.summary(),
+            position: camera.position,
+            chunk: chunk_position(camera.position),
+            direction: camera.direction(),
+            yaw: camera.yaw.into(),
+            pitch: camera.pitch.into(),
+            target,
+            chunks: self.chunks.stats(),
+            scopes: profiler::last_frame(),
+            recording: profiler::is_recording(),
+            adapter: &self.adapter,
+        };
+        self.text.update_debug(&stats);
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn player_mut(&mut self) -> &mut Player {
+        &mut self.player
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.player.camera
+    }
+
+    pub fn chunks(&self) -> &ChunkManager {
+        &self.chunks
+    }
+
+    pub fn chunks_mut(&mut self) -> &mut ChunkManager {
+        &mut self.chunks
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn palette_mut(&mut self) -> &mut Palette {
+        &mut self.palette
+    }
+
+    /// The player along with the block types, for screens that change one
+    /// while listing the other
+    pub fn player_and_palette(&mut self) -> (&mut Player, &Palette) {
+        (&mut self.player, &self.palette)
+    }
+
+    pub fn editor(&self) -> &Editor {
+        &self.editor
+    }
+
+    pub fn editor_mut(&mut self) -> &mut Editor {
+        &mut self.editor
+    }
+
+    /// The block under the crosshair
+    pub fn target(&self) -> Option<RayHit> {
+        self.target
+    }
+
+    /// Changes many blocks at once, remeshing each affected chunk once, as a
+    /// single step in the undo history
+    pub fn apply_edit(&mut self, edit: &BlockEdit) -> EditSummary {
+        let (summary, changes) = self.chunks.apply_edit(edit);
+        self.editor.history.record(changes);
+        summary
+    }
+
+    // Records a single block placed or broken by the player
+    fn record_change(&mut self, change: BlockChange) {
+        let mut changes = ChangeSet::new();
+        changes.push(change);
+        self.editor.history.record(changes);
+    }
+
+    /// Reverts the latest edit, only in creative as the inventory isn't
+    /// charged or refunded for the blocks
+    pub fn undo(&mut self) -> std::result::Result<EditSummary, HistoryError> {
+        if self.player.game_mode == GameMode::Survival {
+            return Err(HistoryError::Survival);
+        }
+        self.editor.history.undo(&mut self.chunks)
+    }
+
+    pub fn redo(&mut self) -> std::result::Result<EditSummary, HistoryError> {
+        if self.player.game_mode == GameMode::Survival {
+            return Err(HistoryError::Survival);
+        }
+        self.editor.history.redo(&mut self.chunks)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.save.level.seed
+    }
+
+    /// Time of day from 0 to 1, where 0 is midnight and 0.5 is noon
+    pub fn time(&self) -> f32 {
+        self.sky.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.sky.time = time.rem_euclid(1.0);
+    }
+
+    // The blocks a walking player's feet and head are in
+    fn body_blocks(eye: Point3<f32>) -> [Vector3<i32>; 2] {
+        let block =
+            |y: f32| Vector3::new(eye.x.floor() as i32, y.floor() as i32, eye.z.floor() as i32);
+        [block(eye.y - EYE_HEIGHT), block(eye.y)]
+    }
+
+    fn body_blocked(&self, eye: Point3<f32>) -> bool {
+        Self::body_blocks(eye)
+            .iter()
+            .any(|&block| self.chunks.block_active(block))
+    }
+
+    /// Applies gravity while walking, and undoes movement since `from` along
+    /// any axis that would put the player inside a block
+    fn walk(&mut self, from: Point3<f32>, dt: Duration) {
+        if self.player.flying {
+            self.player.velocity_y = 0.0;
+            self.player.on_ground = false;
+            return;
+        }
+        let moved = self.player.camera.position;
+        // Hold still until the ground has loaded instead of falling through it
+        let (column, _) = block_to_chunk(Self::body_blocks(moved)[0]);
+        if self
+            .chunks
+            .get_chunk(&Vector3::new(column.x, 0, column.z))
+            .is_none()
+        {
+            self.player.velocity_y = 0.0;
+            return;
+        }
+        let dt = dt.as_secs_f32();
+
+        let mut position = from;
+        for axis in 0..3 {
+            if axis == 1 {
+                continue;
+            }
+            let mut next = position;
+            next[axis] = moved[axis];
+            if !self.body_blocked(next) {
+                position = next;
+            }
+        }
+
+        if self.player.jumping() && self.player.on_ground {
+            self.player.velocity_y = JUMP_SPEED;
+        }
+        self.player.velocity_y = (self.player.velocity_y - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+        let mut next = position;
+        next.y += self.player.velocity_y * dt;
+        if self.body_blocked(next) {
+            if self.player.velocity_y < 0.0 {
+                // Land on top of the block under the feet
+                let feet = (next.y - EYE_HEIGHT).floor() + 1.0;
+                position.y = position.y.min(feet + EYE_HEIGHT);
+                self.player.on_ground = true;
+            }
+            self.player.velocity_y = 0.0;
+        } else {
+            position = next;
+            self.player.on_ground = false;
+        }
+        self.player.camera.position = position;
+    }
+
+    /// Loads missing chunks within the render distance of the player, from the
+    /// save if they were stored and generated otherwise
+    fn load_chunks(&mut self, limit: Option<usize>) {
+        let center = chunk_position(self.player.camera.position);
+        let distance = self.chunks.render_distance() as i32;
+
+        let mut loaded = 0;
+        self.loading = true;
+        for x in (center.x - distance)..=(center.x + distance) {
+            for z in (center.z - distance)..=(center.z + distance) {
+                for y in 0..HEIGHT_CHUNKS {
+                    let position = Vector3::new(x, y, z);
+                    if self.chunks.chunks.contains_key(&position) {
+                        continue;
+                    }
+                    if matches!(limit, Some(limit) if loaded >= limit) {
+                        return;
+                    }
+
+                    let chunk = self.fetch_chunk(position);
+                    self.chunks.add_chunk(chunk);
+                    loaded += 1;
+                }
+            }
+        }
+        self.loading = false;
+    }
+
+    // A chunk from the save if it was stored, generated otherwise
+    fn fetch_chunk(&self, position: ChunkPosition) -> Chunk {
+        self.save
+            .load_chunk(position, &self.palette)
+            .unwrap_or_else(|| self.generator.generate(position))
+    }
+
+    /// Builds up to `limit` LOD meshes for the chunks between the render and
+    /// view distances that don't have one at the right level, nearest first.
+    /// Chunks that aren't loaded are fetched only to be meshed, keeping just
+    /// the mesh. Returns whether every chunk in range has its LOD mesh.
+    fn load_lods(&mut self, limit: usize, device: &wgpu::Device) -> bool {
+        self.chunks.unload_lods();
+        let center = chunk_position(self.player.camera.position);
+        let near = self.chunks.render_distance() as i32;
+        let far = self.chunks.view_distance() as i32;
+
+        let mut built = 0;
+        for distance in (near + 1)..=far {
+            let level = lod_level(distance, near);
+            for (x, z) in ring(distance) {
+                for y in 0..HEIGHT_CHUNKS {
+                    let position = Vector3::new(center.x + x, y, center.z + z);
+                    if self.chunks.lod(position) == Some(level) {
+                        continue;
+                    }
+                    if built >= limit {
+                        return false;
+                    }
+
+                    let data = {
+                        let _scope = profiler::scope("mesh_lod");
+                        match self.chunks.get_chunk(&position) {
+                            Some(chunk) => mesh_lod(chunk, level, &self.palette),
+                            None => mesh_lod(&self.fetch_chunk(position), level, &self.palette),
+                        }
+                    };
+                    self.chunks.set_lod(position, level, data.upload(device));
+                    built += 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether every chunk in range has been loaded and meshed
+    pub fn is_loaded(&self) -> bool {
+        !self.loading && self.chunks.rebuild.is_empty()
+    }
+
+    /// Chunks waiting to be loaded or meshed
+    pub fn pending_chunks(&self) -> usize {
+        self.chunks.rebuild.len()
+    }
+
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        if settings.render_distance != self.chunks.render_distance() {
+            self.chunks.set_render_distance(settings.render_distance);
+        }
+        self.chunks.set_view_distance(settings.view_distance);
+        self.projection.set_fovy(Deg(settings.fov));
+        self.projection.set_zfar(settings.zfar());
+        self.player.sensitivity = settings.mouse_sensitivity;
+        self.player.speed = settings.movement_speed;
+    }
+
+    /// Drops held keys and mouse movement, so the player doesn't keep moving
+    /// after input stops reaching the world
+    pub fn release_input(&mut self) {
+        self.player.actions.clear();
+        self.player.mouse_d = (0.0, 0.0);
+        self.breaking = false;
+    }
+
+    /// Finds the block under the crosshair and advances breaking it while
+    /// the break button is held
+    fn update_target(&mut self, dt: Duration) {
+        let camera = &self.player.camera;
+        let target = raycast(&self.chunks, camera.position, camera.direction(), REACH);
+
+        let same_block = matches!(
+            (self.target, target),
+            (Some(old), Some(new)) if old.block == new.block
+        );
+        if !same_block || !self.breaking {
+            self.break_progress = 0.0;
+        }
+        self.target = target;
+
+        if let (Some(target), true) = (target, self.breaking) {
+            self.break_progress += dt.as_secs_f32() / BREAK_TIME;
+            if self.break_progress >= 1.0 {
+                if let Some(block) = self.chunks.remove_block(target.block) {
+                    self.record_change(BlockChange {
+                        position: target.block,
+                        old: Some(block),
+                        new: None,
+                    });
+                    self.player.pick_up(block);
+                }
+                self.break_progress = 0.0;
+                self.target = None;
+            }
+        }
+    }
+
+    /// Places the block selected in the hotbar against the face of the
+    /// targeted block, unless it would end up inside the player
+    fn place_block(&mut self) {
+        let target = match self.target {
+            Some(target) => target,
+            None => return,
+        };
+        let position = target.adjacent();
+        if Self::body_blocks(self.player.camera.position).contains(&position) {
+            return;
+        }
+        if let Some(block) = self.player.selected_block() {
+            if self.chunks.insert_block(block, position) {
+                self.record_change(BlockChange {
+                    position,
+                    old: None,
+                    new: Some(block),
+                });
+                self.player.consume_selected();
+            }
+        }
+    }
+
+    /// Uploads the camera without advancing the world, for frames where the
+    /// simulation is stopped
+    pub fn update_camera(&mut self, graphics: &Graphics) {
+        let _scope = profiler::scope("uniform upload");
+        self.uniforms
+            .update_camera(&self.player.camera, &self.projection);
+
+        let mut encoder = graphics
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("update encoder"),
+            });
+
+        let staging_buffer = graphics.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[self.uniforms]),
+            wgpu::BufferUsage::COPY_SRC,
+        );
+
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.uniform_buffer,
+            0,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+        );
+
+        graphics.queue.submit(&[encoder.finish()]);
+    }
+
+    /// Writes the level, the player and every chunk changed since the last save
+    pub fn save(&mut self) -> Result<()> {
+        self.save.level.block_colors = self.palette.colors();
+        self.save.save_level()?;
+        self.save.save_player(self.player.to_data())?;
+
+        let mut saved = 0;
+        for chunk in self.chunks.chunks.values_mut() {
+            if chunk.modified {
+                self.save.save_chunk(chunk)?;
+                chunk.modified = false;
+                saved += 1;
+            }
+        }
+        log::info!("Saved {} chunks to {:?}", saved, self.save.dir);
+        Ok(())
+    }
+
+    /// Loads and meshes every chunk in view instead of spreading the work over
+    /// frames. The LOD meshes past them are still built a frame's budget at a
+    /// time, waiting for the GPU in between, so their uploads aren't all
+    /// queued at once.
+    pub fn finish_loading(&mut self, graphics: &Graphics) {
+        self.load_chunks(None);
+        self.update(Duration::from_secs(0), graphics);
+        while !self.chunks.rebuild.is_empty() {
+            self.chunks.rebuild_chunks(&self.palette, &graphics.device);
+        }
+        while !self.load_lods(MAX_LOD_FRAME, &graphics.device) {
+            graphics.device.poll(wgpu::Maintain::Wait);
+        }
     }
 
     pub fn handle_input(&mut self, event: &WindowEvent, width: u32, height: u32) -> bool {
+        if self.player.hotbar.handle_input(event) {
+            return true;
+        }
         match event {
             WindowEvent::KeyboardInput {
                 device_id: _,
@@ -157,6 +680,35 @@ impl World {
                 self.player.process_mouse(position, width, height);
                 false
             }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button,
+                ..
+            } if self.editor.tool => {
+                let selection = &mut self.editor.selection;
+                let corner = match button {
+                    MouseButton::Left => &mut selection.first,
+                    MouseButton::Right => &mut selection.second,
+                    _ => return false,
+                };
+                if let Some(target) = self.target {
+                    *corner = Some(target.block);
+                }
+                true
+            }
+            WindowEvent::MouseInput { state, button, .. } => match button {
+                MouseButton::Left => {
+                    self.breaking = *state == ElementState::Pressed;
+                    true
+                }
+                MouseButton::Right => {
+                    if *state == ElementState::Pressed {
+                        self.place_block();
+                    }
+                    true
+                }
+                _ => false,
+            },
             _ => false,
         }
     }
@@ -164,40 +716,59 @@ impl World {
 
 impl Render for World {
     fn update(&mut self, dt: Duration, graphics: &Graphics) {
+        self.frame_times.push(dt);
+        let from = self.player.camera.position;
         self.player.update_player(dt);
-        self.uniforms
-            .update_camera(&self.player.camera, &self.projection);
-
-        let mut encoder = graphics
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("update encoder"),
-            });
+        self.walk(from, dt);
+        self.update_camera(graphics);
 
-        let staging_buffer = graphics.device.create_buffer_with_data(
-            bytemuck::cast_slice(&[self.uniforms]),
-            wgpu::BufferUsage::COPY_SRC,
-        );
+        #[cfg(feature = "dev")]
+        self.reload_shaders(graphics);
 
-        encoder.copy_buffer_to_buffer(
-            &staging_buffer,
-            0,
-            &self.uniform_buffer,
-            0,
-            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+        self.sky.update(dt, graphics);
+        self.load_chunks(Some(MAX_LOAD_FRAME));
+        self.load_lods(MAX_LOD_FRAME, &graphics.device);
+        self.update_target(dt);
+        self.outline.update(
+            graphics,
+            self.target.map(|target| target.block),
+            self.break_progress,
         );
+        self.outline
+            .update_selection(graphics, self.editor.selection.bounds());
+        self.chunks
+            .update(&self.player.camera, &self.palette, &graphics.device);
+        if self.show_debug {
+            self.update_debug();
+        }
+    }
 
-        graphics.queue.submit(&[encoder.finish()]);
+    fn render(&mut self, graphics: &Graphics, frame: &wgpu::TextureView) {
+        self.draw(graphics, frame, None);
+    }
+}
 
-        self.chunks.update(&self.player.camera, &graphics.device);
-        self.text.update_debug(&self.player);
+impl World {
+    /// Renders into `target` instead of the window, at the target's resolution
+    pub fn render_offscreen(&mut self, graphics: &Graphics, target: &OffscreenTarget) {
+        self.draw(graphics, &target.view, Some(target));
     }
 
-    fn render(&mut self, graphics: &mut Graphics) {
-        let frame = graphics
-            .swap_chain
-            .get_next_texture()
-            .expect("Failed to acquire next swap chain texture");
+    fn draw(
+        &mut self,
+        graphics: &Graphics,
+        frame: &wgpu::TextureView,
+        target: Option<&OffscreenTarget>,
+    ) {
+        let _scope = profiler::scope("World::render");
+        let (depth_view, width, height) = match target {
+            Some(target) => (&target.depth.view, target.width, target.height),
+            None => (
+                &self.depth_texture.view,
+                graphics.size.width,
+                graphics.size.height,
+            ),
+        };
 
         let mut encoder = graphics
             .device
@@ -208,7 +779,7 @@ impl Render for World {
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: frame,
                     resolve_target: None,
                     load_op: wgpu::LoadOp::Clear,
                     store_op: wgpu::StoreOp::Store,
@@ -220,7 +791,7 @@ impl Render for World {
                     },
                 }],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                    attachment: &self.depth_texture.view,
+                    attachment: depth_view,
                     depth_load_op: wgpu::LoadOp::Clear,
                     depth_store_op: wgpu::StoreOp::Store,
                     clear_depth: 1.0,
@@ -229,28 +800,41 @@ impl Render for World {
                     clear_stencil: 0,
                 }),
             });
+            render_pass.draw_sky(&self.sky, &self.uniform_bind_group);
             render_pass.set_pipeline(&self.pipeline);
             render_pass.draw_chunks(&self.chunks, &self.uniform_bind_group);
+            render_pass.draw_outline(&self.outline, &self.uniform_bind_group);
         }
 
-        // Text rendering
-        self.text.glyph_brush.queue(Section {
-            screen_position: (5.0, 5.0),
-            bounds: (graphics.size.width as f32, graphics.size.height as f32),
-            text: vec![Text::new(&self.text.debug_text[..])],
-            ..Section::default()
-        });
+        // Text rendering, scaled with targets larger than the window so
+        // captures show it at the same size relative to the frame
+        let scale = width as f32 / graphics.size.width.max(1) as f32;
+        let mut errors_top = 5.0;
+        if self.show_debug {
+            self.text.glyph_brush.queue(Section {
+                screen_position: (5.0 * scale, 5.0 * scale),
+                bounds: (width as f32, height as f32),
+                text: vec![Text::new(&self.text.debug_text[..]).with_scale(TEXT_SCALE * scale)],
+                ..Section::default()
+            });
+            errors_top += DEBUG_LINE_HEIGHT * self.text.debug_text.lines().count() as f32;
+        }
+
+        for (i, error) in self.text.shader_errors.values().enumerate() {
+            self.text.glyph_brush.queue(Section {
+                screen_position: (5.0 * scale, (errors_top + 120.0 * i as f32) * scale),
+                bounds: (width as f32, height as f32),
+                text: vec![Text::new(error)
+                    .with_scale(TEXT_SCALE * scale)
+                    .with_color([1.0, 0.3, 0.3, 1.0])],
+                ..Section::default()
+            });
+        }
 
         self.text
             .glyph_brush
-            .draw_queued(
-                &graphics.device,
-                &mut encoder,
-                &frame.view,
-                graphics.size.width,
-                graphics.size.height,
-            )
-            .expect("Draw queued");
+            .draw_queued(&graphics.device, &mut encoder, frame, width, height)
+            .unwrap_or_else(|e| log::error!("Failed to draw text: {}", e));
 
         graphics.queue.submit(&[encoder.finish()]);
     }